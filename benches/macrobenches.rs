@@ -1,3 +1,4 @@
+use std::sync::LazyLock;
 use std::{error::Error, io::Cursor};
 use xenna::reader::{EventReader, XmlEvent};
 
@@ -59,6 +60,52 @@ fn xml_rs() -> Result<(), Box<dyn Error>> {
     }
 }
 
+#[divan::bench]
+fn xenna_large() -> Result<(), Box<dyn Error>> {
+    let mut reader = EventReader::from(divan::black_box(LARGE_XML.as_str()));
+
+    loop {
+        if reader.next_event()? == XmlEvent::Eof {
+            return Ok(());
+        }
+    }
+}
+
+#[divan::bench]
+fn quick_xml_large() -> Result<(), Box<dyn Error>> {
+    use quick_xml::{events::Event, reader::Reader};
+
+    let mut reader = Reader::from_reader(Cursor::new(divan::black_box(LARGE_XML.as_bytes())));
+    let mut buf = Vec::new();
+
+    loop {
+        if reader.read_event_into(&mut buf)? == Event::Eof {
+            return Ok(());
+        }
+        buf.clear();
+    }
+}
+
+/// A document of a few megabytes with long text, comments, CDATA sections
+/// and attribute values.
+static LARGE_XML: LazyLock<String> = LazyLock::new(|| {
+    let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20);
+    let mut xml = String::from("<articles>\n");
+
+    for i in 0..2000 {
+        xml.push_str(&format!(
+            "<article id=\"{i}\" title=\"{}\">\n\
+             <!-- {text} -->\n\
+             <body>{text}</body>\n\
+             <code><![CDATA[{text}]]></code>\n\
+             </article>\n",
+            &text[..200],
+        ));
+    }
+    xml.push_str("</articles>\n");
+    xml
+});
+
 const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 <CATALOG>
     <CD>
@@ -0,0 +1,778 @@
+use crate::error::{Error, Result, SyntaxError};
+use crate::parse::{ParseSource, Parser, SliceParser};
+use crate::reader::{Attribute, EventReader, XmlEvent};
+use crate::token::rules::accept_as_white_space;
+use crate::token::Literal;
+use crate::Position;
+use serde::de::value::{SeqDeserializer, StringDeserializer};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, VariantAccess, Visitor,
+};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::BufRead;
+use std::vec;
+
+impl de::Error for Error {
+    fn custom<M: Display>(msg: M) -> Self {
+        SyntaxError::Custom(msg.to_string()).into()
+    }
+}
+
+/// Deserializes a `T` from the document read from `src`, detecting its
+/// encoding.
+pub fn from_reader<T: DeserializeOwned>(src: impl BufRead) -> Result<T> {
+    T::deserialize(&mut Deserializer::new(EventReader::new(
+        Parser::from_reader(src),
+    )))
+}
+
+/// Deserializes a `T` from the document held in `src`, parsing it without
+/// copying its tokens.
+pub fn from_str<T: DeserializeOwned>(src: &str) -> Result<T> {
+    T::deserialize(&mut Deserializer::new(EventReader::new(SliceParser::new(
+        src.as_bytes(),
+    ))))
+}
+
+fn is_blank(text: &str) -> bool {
+    text.chars().all(accept_as_white_space)
+}
+
+/// Maps a document onto a type implementing `serde::Deserialize`, pulling
+/// events from an `EventReader` as values are deserialized.
+///
+/// The root element becomes the value, whatever its name. Within an element
+/// an attribute is a field named `@` followed by its name, a child element
+/// is a field named after it and the text is a field named `$text`. A
+/// sequence is read from child elements with the same name following one
+/// another, or from a value separated by white space. An enum is read from
+/// the name of the only child element or from text. An element without
+/// attributes or content is `None` where an `Option` is expected. Comments
+/// and processing instructions are skipped.
+pub struct Deserializer<'a, T> {
+    reader: EventReader<'a, T>,
+    peeked: VecDeque<(XmlEvent<'a>, Position)>,
+    /// Where the event last taken from `peeked` started.
+    position: Position,
+}
+
+impl<'a, T> Deserializer<'a, T> {
+    pub fn new(reader: EventReader<'a, T>) -> Self {
+        Self {
+            reader,
+            peeked: VecDeque::new(),
+            position: Position::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> EventReader<'a, T> {
+        self.reader
+    }
+}
+
+impl<'a, T: ParseSource<'a>> Deserializer<'a, T> {
+    /// Returns the next event that may hold a value.
+    fn peek(&mut self) -> Result<&XmlEvent<'a>> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the event that may hold a value `n` events after the next.
+    fn peek_nth(&mut self, n: usize) -> Result<&XmlEvent<'a>> {
+        while self.peeked.len() <= n {
+            match self.reader.next_event()? {
+                XmlEvent::Xml(_)
+                | XmlEvent::DocType(_)
+                | XmlEvent::Pi(_)
+                | XmlEvent::Comment(_)
+                | XmlEvent::S(_) => {}
+                event => self.peeked.push_back((event, self.reader.position())),
+            }
+        }
+        Ok(&self.peeked[n].0)
+    }
+
+    fn next(&mut self) -> Result<XmlEvent<'a>> {
+        self.peek()?;
+        let (event, position) = self.peeked.pop_front().unwrap();
+        self.position = position;
+
+        Ok(event)
+    }
+
+    /// Concatenates the text and CDATA sections up to the next markup.
+    fn read_text(&mut self) -> Result<String> {
+        let mut text = String::new();
+
+        loop {
+            match self.peek()? {
+                XmlEvent::Text(chunk) => text.push_str(chunk.value()),
+                XmlEvent::CData(chunk) => text.push_str(chunk.value()),
+                _ => return Ok(text),
+            }
+            self.next()?;
+        }
+    }
+
+    /// Skips white space, then returns the next event.
+    fn peek_markup(&mut self) -> Result<&XmlEvent<'a>> {
+        while matches!(self.peek()?, XmlEvent::Text(text) if is_blank(text.value())) {
+            self.next()?;
+        }
+        self.peek()
+    }
+
+    /// Reads the text of an element up to its end tag, failing on child
+    /// elements.
+    fn read_content_text(&mut self) -> Result<String> {
+        let text = self.read_text()?;
+
+        match self.next()? {
+            XmlEvent::ETag(_) => Ok(text),
+            XmlEvent::STag(tag) => Err(unexpected_elem(tag.name.value())),
+            XmlEvent::EmptyElem(elem) => Err(unexpected_elem(elem.name.value())),
+            _ => Err(SyntaxError::UnexpectedEof.into()),
+        }
+    }
+
+    /// Skips the rest of an element whose start tag was read.
+    fn skip_content(&mut self) -> Result<()> {
+        let mut depth = 0;
+
+        loop {
+            match self.next()? {
+                XmlEvent::STag(_) => depth += 1,
+                XmlEvent::ETag(_) if depth == 0 => return Ok(()),
+                XmlEvent::ETag(_) => depth -= 1,
+                XmlEvent::Eof => return Err(SyntaxError::UnexpectedEof.into()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Tells whether the next element has neither attributes nor content.
+    fn is_empty_elem(&mut self) -> Result<bool> {
+        let (is_s_tag, has_attrs) = match self.peek()? {
+            XmlEvent::STag(tag) => (true, !tag.attrs.is_empty()),
+            XmlEvent::EmptyElem(elem) => (false, !elem.attrs.is_empty()),
+            _ => return Ok(false),
+        };
+        Ok(!has_attrs && (!is_s_tag || matches!(self.peek_nth(1)?, XmlEvent::ETag(_))))
+    }
+
+    /// Reads the start tag of the next element.
+    fn start_elem<'d>(&'d mut self) -> Result<ElemDeserializer<'d, 'a, T>> {
+        let (attrs, is_empty) = match self.next()? {
+            XmlEvent::STag(tag) => (tag.attrs, false),
+            XmlEvent::EmptyElem(elem) => (elem.attrs, true),
+            _ => return Err(<Error as de::Error>::custom("expected an element")),
+        };
+
+        Ok(ElemDeserializer {
+            de: self,
+            attrs,
+            is_empty,
+        })
+    }
+
+    fn deserialize_root<V>(
+        &mut self,
+        deserialize: impl FnOnce(ElemDeserializer<'_, 'a, T>) -> Result<V>,
+    ) -> Result<V> {
+        let value = match self.peek_markup() {
+            Ok(XmlEvent::STag(_) | XmlEvent::EmptyElem(_)) => {
+                self.start_elem().and_then(deserialize)
+            }
+            Ok(_) => Err(SyntaxError::MissingRootElement.into()),
+            Err(err) => Err(err),
+        };
+        // Errors raised by `Deserialize` impls don't know where they are.
+        let value = value.map_err(|err| match err.position() {
+            Some(position) if position == Position::default() => err.at(self.position),
+            _ => err,
+        })?;
+
+        match self.peek_markup()? {
+            XmlEvent::Eof => Ok(value),
+            _ => Err(SyntaxError::ContentAfterRoot.into()),
+        }
+    }
+}
+
+fn unexpected_elem(name: &str) -> Error {
+    <Error as de::Error>::custom(format!("expected text, found element `<{name}>`"))
+}
+
+macro_rules! forward {
+    ($to:ident: $($method:ident $(($($arg:ident: $ty:ty),*))?)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($($arg: $ty,)*)?
+                visitor: V,
+            ) -> Result<V::Value> {
+                self.$to(|de| de.$method($($($arg,)*)? visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, 'a, T: ParseSource<'a>> de::Deserializer<'de> for &mut Deserializer<'a, T> {
+    type Error = Error;
+
+    forward! {deserialize_root:
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16
+        deserialize_i32 deserialize_i64 deserialize_i128 deserialize_u8
+        deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf
+        deserialize_option deserialize_unit
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier deserialize_ignored_any
+    }
+}
+
+/// Deserializes an element whose start tag was read.
+struct ElemDeserializer<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+    attrs: Vec<Attribute<'a>>,
+    is_empty: bool,
+}
+
+impl<'d, 'a, T: ParseSource<'a>> ElemDeserializer<'d, 'a, T> {
+    fn read_text(&mut self) -> Result<String> {
+        if self.is_empty {
+            Ok(String::new())
+        } else {
+            self.de.read_content_text()
+        }
+    }
+
+    fn skip(self) -> Result<()> {
+        if self.is_empty {
+            Ok(())
+        } else {
+            self.de.skip_content()
+        }
+    }
+
+    fn deserialize_text<V>(
+        mut self,
+        deserialize: impl FnOnce(TextDeserializer) -> Result<V>,
+    ) -> Result<V> {
+        deserialize(TextDeserializer(self.read_text()?))
+    }
+
+    fn into_map(self, text: Option<String>) -> ElemMap<'d, 'a, T> {
+        ElemMap {
+            de: self.de,
+            attrs: self.attrs.into_iter(),
+            text,
+            is_empty: self.is_empty,
+            value: None,
+        }
+    }
+}
+
+macro_rules! forward_to_text {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                self.deserialize_text(|de| de.$method(visitor))
+            }
+        )*
+    };
+}
+
+impl<'de, 'a, T: ParseSource<'a>> de::Deserializer<'de> for ElemDeserializer<'_, 'a, T> {
+    type Error = Error;
+
+    forward_to_text! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_i128 deserialize_u8 deserialize_u16
+        deserialize_u32 deserialize_u64 deserialize_u128 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_bytes deserialize_byte_buf deserialize_identifier
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.is_empty && self.attrs.is_empty() {
+            return visitor.visit_string(String::new());
+        }
+        if self.is_empty {
+            return visitor.visit_map(self.into_map(None));
+        }
+        let text = self.de.read_text()?;
+
+        if self.attrs.is_empty() && matches!(self.de.peek()?, XmlEvent::ETag(_)) {
+            self.de.next()?;
+            visitor.visit_string(text)
+        } else {
+            visitor.visit_map(self.into_map(Some(text)))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let has_content = !self.is_empty && !matches!(self.de.peek()?, XmlEvent::ETag(_));
+
+        if self.attrs.is_empty() && !has_content {
+            self.skip()?;
+            return visitor.visit_none();
+        }
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.skip()?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.is_empty {
+            return TextDeserializer(String::new()).deserialize_seq(visitor);
+        }
+        let text = self.de.read_text()?;
+
+        match self.de.peek()? {
+            XmlEvent::ETag(_) => {
+                self.de.next()?;
+                TextDeserializer(text).deserialize_seq(visitor)
+            }
+            _ => visitor.visit_seq(ChildSeq { de: self.de }),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(self.into_map(None))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        if self.is_empty {
+            return visitor.visit_enum(String::new().into_deserializer());
+        }
+        let text = self.de.read_text()?;
+
+        match self.de.peek()? {
+            XmlEvent::STag(_) | XmlEvent::EmptyElem(_) if is_blank(&text) => {
+                let value = visitor.visit_enum(VariantElem { de: &mut *self.de })?;
+
+                match self.de.peek_markup()? {
+                    XmlEvent::ETag(_) => {
+                        self.de.next()?;
+                        Ok(value)
+                    }
+                    _ => Err(<Error as de::Error>::custom("expected a single element")),
+                }
+            }
+            _ => {
+                let text = text + &self.de.read_content_text()?;
+                visitor.visit_enum(text.into_deserializer())
+            }
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+}
+
+enum PendingValue {
+    Text(String),
+    Child,
+}
+
+/// The attributes, child elements and text of an element as a map.
+struct ElemMap<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+    attrs: vec::IntoIter<Attribute<'a>>,
+    /// Text read before the map was asked for.
+    text: Option<String>,
+    is_empty: bool,
+    value: Option<PendingValue>,
+}
+
+impl<'a, T: ParseSource<'a>> ElemMap<'_, 'a, T> {
+    /// Returns the name of the next field, keeping its value for
+    /// `next_value_seed`.
+    fn next_key(&mut self) -> Result<Option<String>> {
+        if let Some(attr) = self.attrs.next() {
+            self.value = Some(PendingValue::Text(attr.value.value().to_owned()));
+            return Ok(Some(format!("@{}", attr.name.value())));
+        }
+        if self.is_empty {
+            return Ok(None);
+        }
+
+        let text = match self.text.take() {
+            Some(text) => text + &self.de.read_text()?,
+            None => self.de.read_text()?,
+        };
+        if !is_blank(&text) {
+            self.value = Some(PendingValue::Text(text));
+            return Ok(Some("$text".to_owned()));
+        }
+
+        let name = match self.de.peek()? {
+            XmlEvent::STag(tag) => tag.name.value().to_owned(),
+            XmlEvent::EmptyElem(elem) => elem.name.value().to_owned(),
+            XmlEvent::ETag(_) => {
+                self.de.next()?;
+                self.is_empty = true;
+                return Ok(None);
+            }
+            _ => return Err(SyntaxError::UnexpectedEof.into()),
+        };
+        self.value = Some(PendingValue::Child);
+
+        Ok(Some(name))
+    }
+}
+
+impl<'de, 'a, T: ParseSource<'a>> MapAccess<'de> for ElemMap<'_, 'a, T> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let Some(key) = self.next_key()? else {
+            return Ok(None);
+        };
+        seed.deserialize(StringDeserializer::<Error>::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value> {
+        match self.value.take() {
+            Some(PendingValue::Text(text)) => seed.deserialize(TextDeserializer(text)),
+            Some(PendingValue::Child) => seed.deserialize(ChildDeserializer { de: &mut *self.de }),
+            None => Err(<Error as de::Error>::custom("value requested before key")),
+        }
+    }
+}
+
+/// Deserializes the element starting with the next event, or the run of
+/// elements with its name as a sequence.
+struct ChildDeserializer<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+}
+
+impl<'a, T: ParseSource<'a>> ChildDeserializer<'_, 'a, T> {
+    fn deserialize_elem<V>(
+        self,
+        deserialize: impl FnOnce(ElemDeserializer<'_, 'a, T>) -> Result<V>,
+    ) -> Result<V> {
+        deserialize(self.de.start_elem()?)
+    }
+}
+
+impl<'de, 'a, T: ParseSource<'a>> de::Deserializer<'de> for ChildDeserializer<'_, 'a, T> {
+    type Error = Error;
+
+    forward! {deserialize_elem:
+        deserialize_any deserialize_bool deserialize_i8 deserialize_i16
+        deserialize_i32 deserialize_i64 deserialize_i128 deserialize_u8
+        deserialize_u16 deserialize_u32 deserialize_u64 deserialize_u128
+        deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf
+        deserialize_unit
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_map
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier deserialize_ignored_any
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.de.is_empty_elem()? {
+            self.de.start_elem()?.skip()?;
+            return visitor.visit_none();
+        }
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let name = match self.de.peek()? {
+            XmlEvent::STag(tag) => tag.name.value().to_owned(),
+            XmlEvent::EmptyElem(elem) => elem.name.value().to_owned(),
+            _ => return Err(<Error as de::Error>::custom("expected an element")),
+        };
+        visitor.visit_seq(SiblingSeq { de: self.de, name })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_seq(visitor)
+    }
+}
+
+/// The elements following one another with the same name.
+struct SiblingSeq<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+    name: String,
+}
+
+impl<'de, 'a, T: ParseSource<'a>> SeqAccess<'de> for SiblingSeq<'_, 'a, T> {
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>> {
+        let is_sibling = match self.de.peek_markup()? {
+            XmlEvent::STag(tag) => tag.name.is(&self.name),
+            XmlEvent::EmptyElem(elem) => elem.name.is(&self.name),
+            _ => false,
+        };
+        if !is_sibling {
+            return Ok(None);
+        }
+        seed.deserialize(self.de.start_elem()?).map(Some)
+    }
+}
+
+/// The child elements of an element, whatever their names.
+struct ChildSeq<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+}
+
+impl<'de, 'a, T: ParseSource<'a>> SeqAccess<'de> for ChildSeq<'_, 'a, T> {
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>> {
+        match self.de.peek_markup()? {
+            XmlEvent::STag(_) | XmlEvent::EmptyElem(_) => {
+                seed.deserialize(self.de.start_elem()?).map(Some)
+            }
+            XmlEvent::ETag(_) => {
+                self.de.next()?;
+                Ok(None)
+            }
+            _ => Err(<Error as de::Error>::custom("expected an element")),
+        }
+    }
+}
+
+/// An enum variant named after the next element.
+struct VariantElem<'d, 'a, T> {
+    de: &'d mut Deserializer<'a, T>,
+}
+
+impl<'de, 'd, 'a, T: ParseSource<'a>> EnumAccess<'de> for VariantElem<'d, 'a, T> {
+    type Error = Error;
+    type Variant = ElemDeserializer<'d, 'a, T>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self::Variant)> {
+        let name = match self.de.peek()? {
+            XmlEvent::STag(tag) => tag.name.value().to_owned(),
+            XmlEvent::EmptyElem(elem) => elem.name.value().to_owned(),
+            _ => return Err(<Error as de::Error>::custom("expected an element")),
+        };
+        let variant = seed.deserialize(StringDeserializer::<Error>::new(name))?;
+        let VariantElem { de } = self;
+
+        Ok((variant, de.start_elem()?))
+    }
+}
+
+impl<'de, 'a, T: ParseSource<'a>> VariantAccess<'de> for ElemDeserializer<'_, 'a, T> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        self.skip()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// Deserializes an attribute value or the text of an element.
+struct TextDeserializer(String);
+
+impl IntoDeserializer<'_, Error> for TextDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+                let value = self
+                    .0
+                    .trim_matches(accept_as_white_space)
+                    .parse()
+                    .map_err(|_| <Error as de::Error>::invalid_value(Unexpected::Str(&self.0), &visitor))?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for TextDeserializer {
+    type Error = Error;
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string identifier tuple tuple_struct map struct
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0.trim_matches(accept_as_white_space) {
+            "true" | "1" => visitor.visit_bool(true),
+            "false" | "0" => visitor.visit_bool(false),
+            _ => Err(<Error as de::Error>::invalid_value(
+                Unexpected::Str(&self.0),
+                &visitor,
+            )),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.0.into_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.0.into_bytes())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Reads a list separated by white space, like `xs:list`.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let items = self
+            .0
+            .split(accept_as_white_space)
+            .filter(|item| !item.is_empty())
+            .map(|item| TextDeserializer(item.to_owned()));
+
+        let mut seq = SeqDeserializer::new(items);
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(StringDeserializer::new(self.0))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+}
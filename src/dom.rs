@@ -0,0 +1,424 @@
+pub mod xpath;
+
+use crate::error::{Error, Result, SyntaxError};
+use crate::parse::{ParseSource, Parser};
+use crate::reader::ns::XML_NAMESPACE;
+use crate::reader::{Attribute, EmptyElem, EndTag, EventReader, Pi, StartTag, XmlDecl, XmlEvent};
+use crate::token::{AttValue, CData, Comment, Literal, Name, PiData, Text};
+use crate::writer::EventWriter;
+use std::io::Write;
+use std::iter;
+
+/// Refers to a node of the `Document` it was returned by.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct NodeId(usize);
+
+#[derive(PartialEq, Debug)]
+pub struct Element {
+    pub name: Name<'static>,
+    pub attrs: Vec<Attribute<'static>>,
+}
+
+impl Element {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: Name::new(name.into()),
+            attrs: Vec::new(),
+        }
+    }
+
+    /// Returns the value of the attribute called `name`.
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|attr| attr.name.is(name))
+            .map(|attr| attr.value.value())
+    }
+
+    /// Sets the attribute called `name`, adding it after the others if it
+    /// isn't there yet.
+    pub fn set_attr(&mut self, name: &str, value: impl Into<String>) {
+        let value = AttValue::new(value.into());
+
+        match self.attrs.iter_mut().find(|attr| attr.name.is(name)) {
+            Some(attr) => attr.value = value,
+            None => self.attrs.push(Attribute {
+                name: Name::new(name.to_owned()),
+                value,
+            }),
+        }
+    }
+
+    pub fn remove_attr(&mut self, name: &str) -> Option<AttValue<'static>> {
+        let index = self.attrs.iter().position(|attr| attr.name.is(name))?;
+
+        Some(self.attrs.remove(index).value)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Node {
+    /// The node at the top of every `Document`, above the root element.
+    Document,
+    Element(Element),
+    Text(Text<'static>),
+    CData(CData<'static>),
+    Comment(Comment<'static>),
+    Pi(Pi<'static>),
+}
+
+struct NodeData {
+    node: Node,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// An XML document held in memory as a tree of nodes.
+///
+/// Nodes are owned by the document and referred to by `NodeId`. Detached
+/// nodes stay in the document, so their ids remain valid until it is dropped.
+/// White space outside the root element and the document type declaration
+/// aren't kept.
+pub struct Document {
+    nodes: Vec<NodeData>,
+    pub xml_decl: Option<XmlDecl<'static>>,
+}
+
+impl Document {
+    /// Creates a document holding only `root`.
+    pub fn new(root: Element) -> Self {
+        let mut doc = Self::empty();
+        doc.append(doc.root(), Node::Element(root));
+
+        doc
+    }
+
+    fn empty() -> Self {
+        Self {
+            nodes: vec![NodeData {
+                node: Node::Document,
+                parent: None,
+                children: Vec::new(),
+            }],
+            xml_decl: None,
+        }
+    }
+
+    /// Builds a document from the events of `reader`, up to `XmlEvent::Eof`.
+    pub fn from_events<'a, T: ParseSource<'a>>(reader: &mut EventReader<'a, T>) -> Result<Self> {
+        let mut doc = Self::empty();
+        let mut parent = doc.root();
+
+        loop {
+            let node = match reader.next_event()? {
+                XmlEvent::Xml(xml_decl) => {
+                    doc.xml_decl = Some(XmlDecl {
+                        version: AttValue::new(xml_decl.version.into_value().into_owned()),
+                        encoding: xml_decl
+                            .encoding
+                            .map(|encoding| AttValue::new(encoding.into_value().into_owned())),
+                        standalone: xml_decl
+                            .standalone
+                            .map(|standalone| AttValue::new(standalone.into_value().into_owned())),
+                    });
+                    continue;
+                }
+                XmlEvent::DocType(_) | XmlEvent::S(_) => continue,
+                XmlEvent::STag(StartTag { name, attrs }) => {
+                    parent = doc.append(parent, Node::Element(owned_element(name, attrs)));
+                    continue;
+                }
+                XmlEvent::ETag(EndTag { name }) => {
+                    parent = doc.parent(parent).ok_or_else(|| {
+                        Error::from(SyntaxError::UnexpectedEndTag(
+                            name.into_value().into_owned(),
+                        ))
+                        .at(reader.position())
+                    })?;
+                    continue;
+                }
+                XmlEvent::EmptyElem(EmptyElem { name, attrs }) => {
+                    Node::Element(owned_element(name, attrs))
+                }
+                XmlEvent::Text(text) => Node::Text(Text::new(text.into_value().into_owned())),
+                XmlEvent::CData(cdata) => Node::CData(CData::new(cdata.into_value().into_owned())),
+                XmlEvent::Comment(comment) => {
+                    Node::Comment(Comment::new(comment.into_value().into_owned()))
+                }
+                XmlEvent::Pi(pi) => Node::Pi(Pi {
+                    target: Name::new(pi.target.into_value().into_owned()),
+                    data: pi
+                        .data
+                        .map(|data| PiData::new(data.into_value().into_owned())),
+                }),
+                XmlEvent::Eof => return Ok(doc),
+            };
+            doc.append(parent, node);
+        }
+    }
+
+    /// Returns the `Node::Document` node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    pub fn root_element(&self) -> Option<NodeId> {
+        self.children(self.root())
+            .iter()
+            .copied()
+            .find(|&id| self.element(id).is_some())
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0].node
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0].node
+    }
+
+    pub fn element(&self, id: NodeId) -> Option<&Element> {
+        match self.node(id) {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn element_mut(&mut self, id: NodeId) -> Option<&mut Element> {
+        match self.node_mut(id) {
+            Node::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+
+    /// Returns the child elements of `id` called `name`.
+    pub fn child_elements<'d>(
+        &'d self,
+        id: NodeId,
+        name: &'d str,
+    ) -> impl Iterator<Item = NodeId> + 'd {
+        self.children(id)
+            .iter()
+            .copied()
+            .filter(move |&child| self.element(child).is_some_and(|elem| elem.name.is(name)))
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let index = siblings.iter().position(|&sibling| sibling == id)?;
+
+        siblings.get(index + 1).copied()
+    }
+
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let index = siblings.iter().position(|&sibling| sibling == id)?;
+
+        index.checked_sub(1).map(|index| siblings[index])
+    }
+
+    /// Returns the parent of `id`, its parent and so on up to the root.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        iter::successors(self.parent(id), |&id| self.parent(id))
+    }
+
+    /// Returns `id` and the nodes below it in document order.
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let mut stack = vec![id];
+
+        iter::from_fn(move || {
+            let id = stack.pop()?;
+            stack.extend(self.children(id).iter().rev());
+
+            Some(id)
+        })
+    }
+
+    /// Concatenates the text and CDATA sections below `id`.
+    pub fn text(&self, id: NodeId) -> String {
+        let mut text = String::new();
+
+        for id in self.descendants(id) {
+            match self.node(id) {
+                Node::Text(chunk) => text.push_str(chunk.value()),
+                Node::CData(chunk) => text.push_str(chunk.value()),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// Returns the namespace `prefix` is bound to at `id` by the `xmlns`
+    /// attributes of it and its ancestors, or the default namespace if
+    /// `prefix` is `None`.
+    pub fn namespace(&self, id: NodeId, prefix: Option<&str>) -> Option<&str> {
+        let xmlns = match prefix {
+            Some(prefix) => format!("xmlns:{prefix}"),
+            None => "xmlns".to_owned(),
+        };
+        let namespace = iter::once(id)
+            .chain(self.ancestors(id))
+            .find_map(|id| self.element(id)?.attr(&xmlns));
+
+        match namespace {
+            Some("") => None,
+            None if prefix == Some("xml") => Some(XML_NAMESPACE),
+            namespace => namespace,
+        }
+    }
+
+    /// Adds `node` to the document without attaching it anywhere yet.
+    pub fn create(&mut self, node: Node) -> NodeId {
+        self.nodes.push(NodeData {
+            node,
+            parent: None,
+            children: Vec::new(),
+        });
+
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds `node` as the last child of `parent`.
+    pub fn append(&mut self, parent: NodeId, node: Node) -> NodeId {
+        let id = self.create(node);
+        self.append_child(parent, id);
+
+        id
+    }
+
+    /// Moves `child` to the end of the children of `parent`.
+    ///
+    /// Panics if `child` is the document node or `parent` itself or one of
+    /// its ancestors.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.check_insertion(parent, child);
+        self.detach(child);
+
+        self.nodes[child.0].parent = Some(parent);
+        self.nodes[parent.0].children.push(child);
+    }
+
+    /// Moves `child` right before `sibling`.
+    ///
+    /// Panics if `sibling` has no parent, or as `append_child` does.
+    pub fn insert_before(&mut self, sibling: NodeId, child: NodeId) {
+        let parent = self.parent(sibling).expect("`sibling` has a parent");
+        self.check_insertion(parent, child);
+        self.detach(child);
+
+        let siblings = &mut self.nodes[parent.0].children;
+        let index = siblings.iter().position(|&id| id == sibling).unwrap();
+        siblings.insert(index, child);
+        self.nodes[child.0].parent = Some(parent);
+    }
+
+    fn check_insertion(&self, parent: NodeId, child: NodeId) {
+        assert!(
+            child != self.root()
+                && child != parent
+                && self.ancestors(parent).all(|ancestor| ancestor != child),
+            "a node can't be moved below itself"
+        );
+    }
+
+    /// Removes `id` from the children of its parent.
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id.0].parent.take() {
+            self.nodes[parent.0].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Replaces the children of `id` with a single text node.
+    pub fn set_text(&mut self, id: NodeId, text: impl Into<String>) {
+        for child in self.nodes[id.0].children.split_off(0) {
+            self.nodes[child.0].parent = None;
+        }
+        self.append(id, Node::Text(Text::new(text.into())));
+    }
+
+    /// Writes the document to `writer`, ending with `XmlEvent::Eof`.
+    pub fn write_to<W: Write>(&self, writer: &mut EventWriter<W>) -> Result<()> {
+        if let Some(xml_decl) = &self.xml_decl {
+            writer.write_event(&XmlEvent::Xml(XmlDecl {
+                version: AttValue::new(xml_decl.version.value()),
+                encoding: xml_decl.encoding.as_ref().map(|e| AttValue::new(e.value())),
+                standalone: xml_decl
+                    .standalone
+                    .as_ref()
+                    .map(|s| AttValue::new(s.value())),
+            }))?;
+        }
+        for &child in self.children(self.root()) {
+            self.write_node(child, writer)?;
+        }
+        writer.write_event(&XmlEvent::Eof)
+    }
+
+    fn write_node<W: Write>(&self, id: NodeId, writer: &mut EventWriter<W>) -> Result<()> {
+        let event = match self.node(id) {
+            Node::Document => return Ok(()),
+            Node::Element(element) => {
+                let name = Name::new(element.name.value());
+                let attrs = element
+                    .attrs
+                    .iter()
+                    .map(|attr| Attribute {
+                        name: Name::new(attr.name.value()),
+                        value: AttValue::new(attr.value.value()),
+                    })
+                    .collect();
+
+                let children = self.children(id);
+                if children.is_empty() {
+                    return writer.write_event(&XmlEvent::EmptyElem(EmptyElem { name, attrs }));
+                }
+                writer.write_event(&XmlEvent::STag(StartTag {
+                    name: name.clone(),
+                    attrs,
+                }))?;
+
+                for &child in children {
+                    self.write_node(child, writer)?;
+                }
+                XmlEvent::ETag(EndTag { name })
+            }
+            Node::Text(text) => XmlEvent::Text(Text::new(text.value())),
+            Node::CData(cdata) => XmlEvent::CData(CData::new(cdata.value())),
+            Node::Comment(comment) => XmlEvent::Comment(Comment::new(comment.value())),
+            Node::Pi(pi) => XmlEvent::Pi(Pi {
+                target: Name::new(pi.target.value()),
+                data: pi.data.as_ref().map(|data| PiData::new(data.value())),
+            }),
+        };
+        writer.write_event(&event)
+    }
+}
+
+impl TryFrom<&[u8]> for Document {
+    type Error = crate::error::Error;
+
+    fn try_from(src: &[u8]) -> Result<Self> {
+        Self::from_events(&mut EventReader::new(Parser::from_reader(src)))
+    }
+}
+
+fn owned_element(name: Name, attrs: Vec<Attribute>) -> Element {
+    Element {
+        name: Name::new(name.into_value().into_owned()),
+        attrs: attrs
+            .into_iter()
+            .map(|attr| Attribute {
+                name: Name::new(attr.name.into_value().into_owned()),
+                value: AttValue::new(attr.value.into_value().into_owned()),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,761 @@
+mod expr;
+
+use super::{Document, Node, NodeId};
+use crate::error::{Result, SyntaxError};
+use crate::reader::ns::XML_NAMESPACE;
+use crate::token::rules::accept_as_white_space;
+use crate::token::Literal;
+use expr::{ArithOp, Axis, CompareOp, Expr, Function, NodeTest, PathStart, Step};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+
+/// A node as XPath sees it. Attributes and namespace bindings aren't nodes
+/// of the `Document`, so they are referred to through their element.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum NodeRef {
+    Node(NodeId),
+    /// The attribute at an index of `Element::attrs`.
+    Attribute(NodeId, usize),
+    /// The namespace bound to a prefix, empty for the default namespace,
+    /// at an element.
+    Namespace(NodeId, String),
+}
+
+impl NodeRef {
+    /// Returns the element of an attribute or namespace node, or the node
+    /// itself.
+    pub fn node_id(&self) -> NodeId {
+        match self {
+            Self::Node(id) | Self::Attribute(id, _) | Self::Namespace(id, _) => *id,
+        }
+    }
+
+    pub fn string_value(&self, doc: &Document) -> String {
+        match self {
+            Self::Node(id) => match doc.node(*id) {
+                Node::Document | Node::Element(_) => doc.text(*id),
+                Node::Text(text) => text.value().to_owned(),
+                Node::CData(text) => text.value().to_owned(),
+                Node::Comment(comment) => comment.value().to_owned(),
+                Node::Pi(pi) => pi
+                    .data
+                    .as_ref()
+                    .map_or_else(String::new, |data| data.value().to_owned()),
+            },
+            Self::Attribute(id, index) => doc
+                .element(*id)
+                .and_then(|elem| elem.attrs.get(*index))
+                .map_or_else(String::new, |attr| attr.value.value().to_owned()),
+            Self::Namespace(id, prefix) => doc
+                .namespace(
+                    *id,
+                    Some(prefix.as_str()).filter(|prefix| !prefix.is_empty()),
+                )
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+impl From<NodeId> for NodeRef {
+    fn from(id: NodeId) -> Self {
+        Self::Node(id)
+    }
+}
+
+/// The result of an XPath expression.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Value {
+    /// Nodes in document order, without duplicates.
+    NodeSet(Vec<NodeRef>),
+    Boolean(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    pub fn boolean(&self) -> bool {
+        match self {
+            Self::NodeSet(nodes) => !nodes.is_empty(),
+            Self::Boolean(boolean) => *boolean,
+            Self::Number(number) => *number != 0.0 && !number.is_nan(),
+            Self::String(string) => !string.is_empty(),
+        }
+    }
+
+    pub fn number(&self, doc: &Document) -> f64 {
+        match self {
+            Self::Boolean(boolean) => f64::from(u8::from(*boolean)),
+            Self::Number(number) => *number,
+            Self::String(string) => parse_number(string),
+            Self::NodeSet(_) => parse_number(&self.string(doc)),
+        }
+    }
+
+    pub fn string(&self, doc: &Document) -> String {
+        match self {
+            Self::NodeSet(nodes) => nodes
+                .first()
+                .map_or_else(String::new, |node| node.string_value(doc)),
+            Self::Boolean(boolean) => boolean.to_string(),
+            Self::Number(number) => format_number(*number),
+            Self::String(string) => string.clone(),
+        }
+    }
+}
+
+/// Converts a string to a number the way `number()` does, giving NaN for
+/// anything but an optionally negative decimal number.
+fn parse_number(string: &str) -> f64 {
+    let number = string.trim_matches(accept_as_white_space);
+    let digits = number.strip_prefix('-').unwrap_or(number);
+
+    let is_valid = digits.chars().any(|ch| ch.is_ascii_digit())
+        && digits.chars().all(|ch| ch.is_ascii_digit() || ch == '.')
+        && digits.matches('.').count() <= 1;
+
+    if is_valid {
+        number.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+fn format_number(number: f64) -> String {
+    if number.is_nan() {
+        "NaN".to_owned()
+    } else if number.is_infinite() {
+        if number > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+        .to_owned()
+    } else if number == 0.0 {
+        "0".to_owned()
+    } else {
+        number.to_string()
+    }
+}
+
+/// What an expression is evaluated with besides the context node.
+#[derive(Default, Clone, Debug)]
+pub struct Context {
+    /// The namespaces that prefixes in node tests stand for. Unprefixed
+    /// names never match namespaced nodes, as in XPath 1.0.
+    pub namespaces: HashMap<String, String>,
+    /// The values of `$name` references.
+    pub variables: HashMap<String, Value>,
+}
+
+/// A compiled XPath 1.0 expression.
+///
+/// Elements with an `xml:id` attribute are what `id()` finds, since the
+/// document type declaration isn't kept in a `Document`.
+#[derive(Debug)]
+pub struct XPath {
+    expr: Expr,
+}
+
+impl XPath {
+    /// Compiles `expr`, failing at the position of the first syntax error.
+    pub fn new(expr: &str) -> Result<Self> {
+        Ok(Self {
+            expr: expr::parse(expr)?,
+        })
+    }
+
+    pub fn evaluate(
+        &self,
+        doc: &Document,
+        node: impl Into<NodeRef>,
+        context: &Context,
+    ) -> Result<Value> {
+        let evaluator = Evaluator {
+            doc,
+            order: DocumentOrder::new(doc),
+            context,
+        };
+        evaluator.eval(&self.expr, &node.into(), 1, 1)
+    }
+
+    /// Evaluates an expression giving a node-set, with `node` as context
+    /// node and no namespaces or variables.
+    pub fn select(&self, doc: &Document, node: NodeId) -> Result<Vec<NodeRef>> {
+        match self.evaluate(doc, node, &Context::default())? {
+            Value::NodeSet(nodes) => Ok(nodes),
+            _ => Err(SyntaxError::NotANodeSet.into()),
+        }
+    }
+}
+
+/// The position of every node in a pre-order walk of its tree, detached
+/// trees coming after the document.
+struct DocumentOrder(Vec<usize>);
+
+impl DocumentOrder {
+    fn new(doc: &Document) -> Self {
+        let mut order = vec![0; doc.nodes.len()];
+        let tops = (0..doc.nodes.len()).filter(|&index| doc.nodes[index].parent.is_none());
+
+        for (position, id) in tops
+            .flat_map(|index| doc.descendants(NodeId(index)))
+            .enumerate()
+        {
+            order[id.0] = position;
+        }
+        Self(order)
+    }
+
+    /// Sorts `nodes` into document order and drops duplicates. Namespace
+    /// nodes come right after their element, then its attributes.
+    fn sort(&self, nodes: &mut Vec<NodeRef>) {
+        nodes.sort_by(|a, b| self.key(a).cmp(&self.key(b)));
+        nodes.dedup();
+    }
+
+    fn key<'n>(&self, node: &'n NodeRef) -> (usize, u8, usize, &'n str) {
+        match node {
+            NodeRef::Node(id) => (self.0[id.0], 0, 0, ""),
+            NodeRef::Namespace(id, prefix) => (self.0[id.0], 1, 0, prefix),
+            NodeRef::Attribute(id, index) => (self.0[id.0], 2, *index, ""),
+        }
+    }
+}
+
+struct Evaluator<'d> {
+    doc: &'d Document,
+    order: DocumentOrder,
+    context: &'d Context,
+}
+
+impl Evaluator<'_> {
+    fn eval(&self, expr: &Expr, node: &NodeRef, position: usize, size: usize) -> Result<Value> {
+        let eval = |expr| self.eval(expr, node, position, size);
+
+        Ok(match expr {
+            Expr::Or(lhs, rhs) => Value::Boolean(eval(lhs)?.boolean() || eval(rhs)?.boolean()),
+            Expr::And(lhs, rhs) => Value::Boolean(eval(lhs)?.boolean() && eval(rhs)?.boolean()),
+            Expr::Compare(op, lhs, rhs) => {
+                Value::Boolean(self.compare(*op, &eval(lhs)?, &eval(rhs)?))
+            }
+            Expr::Arith(op, lhs, rhs) => {
+                let (lhs, rhs) = (eval(lhs)?.number(self.doc), eval(rhs)?.number(self.doc));
+
+                Value::Number(match op {
+                    ArithOp::Add => lhs + rhs,
+                    ArithOp::Sub => lhs - rhs,
+                    ArithOp::Mul => lhs * rhs,
+                    ArithOp::Div => lhs / rhs,
+                    ArithOp::Mod => lhs % rhs,
+                })
+            }
+            Expr::Neg(operand) => Value::Number(-eval(operand)?.number(self.doc)),
+            Expr::Union(lhs, rhs) => {
+                let mut nodes = self.eval_node_set(lhs, node, position, size)?;
+                nodes.extend(self.eval_node_set(rhs, node, position, size)?);
+                self.order.sort(&mut nodes);
+
+                Value::NodeSet(nodes)
+            }
+            Expr::Filter(primary, predicates) => {
+                let nodes = self.eval_node_set(primary, node, position, size)?;
+
+                Value::NodeSet(self.filter(nodes, predicates)?)
+            }
+            Expr::Path(start, steps) => {
+                let mut nodes = match start {
+                    PathStart::Root => vec![NodeRef::Node(self.tree_root(node.node_id()))],
+                    PathStart::Context => vec![node.clone()],
+                    PathStart::Filter(filter) => {
+                        self.eval_node_set(filter, node, position, size)?
+                    }
+                };
+                for step in steps {
+                    nodes = self.eval_step(&nodes, step)?;
+                }
+                Value::NodeSet(nodes)
+            }
+            Expr::Literal(literal) => Value::String(literal.clone()),
+            Expr::Number(number) => Value::Number(*number),
+            Expr::Variable(name) => self
+                .context
+                .variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SyntaxError::UndefinedVariable(name.clone()))?,
+            Expr::Call(function, args) => self.call(*function, args, node, position, size)?,
+        })
+    }
+
+    fn eval_node_set(
+        &self,
+        expr: &Expr,
+        node: &NodeRef,
+        position: usize,
+        size: usize,
+    ) -> Result<Vec<NodeRef>> {
+        match self.eval(expr, node, position, size)? {
+            Value::NodeSet(nodes) => Ok(nodes),
+            _ => Err(SyntaxError::NotANodeSet.into()),
+        }
+    }
+
+    /// Keeps the nodes for which every predicate holds, numbering them in
+    /// the order they are given.
+    fn filter(&self, mut nodes: Vec<NodeRef>, predicates: &[Expr]) -> Result<Vec<NodeRef>> {
+        for predicate in predicates {
+            let size = nodes.len();
+            let mut kept = Vec::new();
+
+            for (index, node) in nodes.into_iter().enumerate() {
+                let is_kept = match self.eval(predicate, &node, index + 1, size)? {
+                    Value::Number(number) => number == (index + 1) as f64,
+                    value => value.boolean(),
+                };
+                if is_kept {
+                    kept.push(node);
+                }
+            }
+            nodes = kept;
+        }
+        Ok(nodes)
+    }
+
+    fn eval_step(&self, nodes: &[NodeRef], step: &Step) -> Result<Vec<NodeRef>> {
+        let mut result = Vec::new();
+
+        for node in nodes {
+            let mut selected = Vec::new();
+
+            for node in self.axis(node, step.axis) {
+                if self.test(&node, step.axis, &step.test)? {
+                    selected.push(node);
+                }
+            }
+
+            result.extend(self.filter(selected, &step.predicates)?);
+        }
+        self.order.sort(&mut result);
+
+        Ok(result)
+    }
+
+    fn tree_root(&self, id: NodeId) -> NodeId {
+        self.doc.ancestors(id).last().unwrap_or(id)
+    }
+
+    /// Returns the nodes on `axis` from `node`, nearest first.
+    fn axis(&self, node: &NodeRef, axis: Axis) -> Vec<NodeRef> {
+        let doc = self.doc;
+        let nodes = |ids: &mut dyn Iterator<Item = NodeId>| ids.map(NodeRef::Node).collect();
+
+        let NodeRef::Node(id) = *node else {
+            // Attribute and namespace nodes have their element as parent but
+            // aren't its children.
+            let parent = node.node_id();
+
+            return match axis {
+                Axis::Self_ => vec![node.clone()],
+                Axis::Parent => vec![NodeRef::Node(parent)],
+                Axis::Ancestor | Axis::AncestorOrSelf => {
+                    let this = (axis == Axis::AncestorOrSelf).then(|| node.clone());
+
+                    this.into_iter()
+                        .chain(self.axis(&NodeRef::Node(parent), Axis::AncestorOrSelf))
+                        .collect()
+                }
+                Axis::Following => {
+                    let mut following: Vec<_> = nodes(&mut doc.descendants(parent).skip(1));
+                    following.extend(self.axis(&NodeRef::Node(parent), Axis::Following));
+                    following
+                }
+                Axis::Preceding => self.axis(&NodeRef::Node(parent), Axis::Preceding),
+                _ => Vec::new(),
+            };
+        };
+
+        match axis {
+            Axis::Ancestor => nodes(&mut doc.ancestors(id)),
+            Axis::AncestorOrSelf => nodes(&mut iter::once(id).chain(doc.ancestors(id))),
+            Axis::Attribute => doc.element(id).map_or_else(Vec::new, |elem| {
+                (0..elem.attrs.len())
+                    .filter(|&index| !is_xmlns(elem.attrs[index].name.value()))
+                    .map(|index| NodeRef::Attribute(id, index))
+                    .collect()
+            }),
+            Axis::Child => nodes(&mut doc.children(id).iter().copied()),
+            Axis::Descendant => nodes(&mut doc.descendants(id).skip(1)),
+            Axis::DescendantOrSelf => nodes(&mut doc.descendants(id)),
+            Axis::Following => nodes(
+                &mut iter::once(id)
+                    .chain(doc.ancestors(id))
+                    .flat_map(|id| {
+                        iter::successors(doc.next_sibling(id), |&id| doc.next_sibling(id))
+                    })
+                    .flat_map(|id| doc.descendants(id)),
+            ),
+            Axis::FollowingSibling => nodes(&mut iter::successors(doc.next_sibling(id), |&id| {
+                doc.next_sibling(id)
+            })),
+            Axis::Namespace => self.namespaces(id),
+            Axis::Parent => nodes(&mut doc.parent(id).into_iter()),
+            Axis::Preceding => nodes(
+                &mut iter::once(id)
+                    .chain(doc.ancestors(id))
+                    .flat_map(|id| {
+                        iter::successors(doc.previous_sibling(id), |&id| doc.previous_sibling(id))
+                    })
+                    .flat_map(|id| doc.descendants(id).collect::<Vec<_>>().into_iter().rev()),
+            ),
+            Axis::PrecedingSibling => {
+                nodes(&mut iter::successors(doc.previous_sibling(id), |&id| {
+                    doc.previous_sibling(id)
+                }))
+            }
+            Axis::Self_ => vec![node.clone()],
+        }
+    }
+
+    /// Returns the namespace nodes of an element, one for every prefix
+    /// bound at it.
+    fn namespaces(&self, id: NodeId) -> Vec<NodeRef> {
+        if self.doc.element(id).is_none() {
+            return Vec::new();
+        }
+        let mut prefixes = HashSet::from(["xml".to_owned()]);
+
+        for elem in iter::once(id)
+            .chain(self.doc.ancestors(id))
+            .filter_map(|id| self.doc.element(id))
+        {
+            for attr in &elem.attrs {
+                match attr.name.value() {
+                    "xmlns" => prefixes.insert(String::new()),
+                    name => match name.strip_prefix("xmlns:") {
+                        Some(prefix) => prefixes.insert(prefix.to_owned()),
+                        None => false,
+                    },
+                };
+            }
+        }
+        let mut namespaces: Vec<_> = prefixes
+            .into_iter()
+            .filter(|prefix| {
+                let prefix = Some(prefix.as_str()).filter(|prefix| !prefix.is_empty());
+                self.doc.namespace(id, prefix).is_some()
+            })
+            .map(|prefix| NodeRef::Namespace(id, prefix))
+            .collect();
+        self.order.sort(&mut namespaces);
+
+        namespaces
+    }
+
+    fn test(&self, node: &NodeRef, axis: Axis, test: &NodeTest) -> Result<bool> {
+        let is_principal = match (node, axis) {
+            (NodeRef::Attribute(..), Axis::Attribute) => true,
+            (NodeRef::Namespace(..), Axis::Namespace) => true,
+            (NodeRef::Node(id), _) => self.doc.element(*id).is_some(),
+            _ => false,
+        };
+
+        Ok(match test {
+            NodeTest::Any => is_principal,
+            NodeTest::Namespace(prefix) => {
+                is_principal && self.namespace_uri(node) == Some(self.resolve(prefix)?)
+            }
+            NodeTest::Name { prefix, local_name } => {
+                let namespace = match prefix {
+                    Some(prefix) => Some(self.resolve(prefix)?),
+                    None => None,
+                };
+                is_principal
+                    && self.local_name(node) == *local_name
+                    && self.namespace_uri(node) == namespace
+            }
+            NodeTest::Node => true,
+            NodeTest::Text => matches!(
+                node,
+                NodeRef::Node(id) if matches!(self.doc.node(*id), Node::Text(_) | Node::CData(_))
+            ),
+            NodeTest::Comment => matches!(
+                node,
+                NodeRef::Node(id) if matches!(self.doc.node(*id), Node::Comment(_))
+            ),
+            NodeTest::Pi(target) => match node {
+                NodeRef::Node(id) => match self.doc.node(*id) {
+                    Node::Pi(pi) => target.as_ref().is_none_or(|target| pi.target.is(target)),
+                    _ => false,
+                },
+                _ => false,
+            },
+        })
+    }
+
+    fn resolve(&self, prefix: &str) -> Result<&str> {
+        match self.context.namespaces.get(prefix) {
+            Some(namespace) => Ok(namespace),
+            None if prefix == "xml" => Ok(XML_NAMESPACE),
+            None => Err(SyntaxError::UnboundPrefix(prefix.to_owned()).into()),
+        }
+    }
+
+    /// Returns the qualified name of an element, attribute or processing
+    /// instruction, or the prefix of a namespace node.
+    fn name(&self, node: &NodeRef) -> String {
+        match node {
+            NodeRef::Node(id) => match self.doc.node(*id) {
+                Node::Element(elem) => elem.name.value().to_owned(),
+                Node::Pi(pi) => pi.target.value().to_owned(),
+                _ => String::new(),
+            },
+            NodeRef::Attribute(id, index) => self
+                .doc
+                .element(*id)
+                .and_then(|elem| elem.attrs.get(*index))
+                .map_or_else(String::new, |attr| attr.name.value().to_owned()),
+            NodeRef::Namespace(_, prefix) => prefix.clone(),
+        }
+    }
+
+    fn local_name(&self, node: &NodeRef) -> String {
+        let name = self.name(node);
+
+        match name.split_once(':') {
+            Some((_, local_name)) if !matches!(node, NodeRef::Namespace(..)) => {
+                local_name.to_owned()
+            }
+            _ => name,
+        }
+    }
+
+    fn namespace_uri(&self, node: &NodeRef) -> Option<&str> {
+        match node {
+            NodeRef::Node(id) => {
+                let elem = self.doc.element(*id)?;
+                self.doc.namespace(*id, elem.name.prefix())
+            }
+            NodeRef::Attribute(id, index) => {
+                let prefix = self.doc.element(*id)?.attrs.get(*index)?.name.prefix()?;
+                self.doc.namespace(*id, Some(prefix))
+            }
+            NodeRef::Namespace(..) => None,
+        }
+    }
+
+    fn call(
+        &self,
+        function: Function,
+        args: &[Expr],
+        node: &NodeRef,
+        position: usize,
+        size: usize,
+    ) -> Result<Value> {
+        let doc = self.doc;
+        let eval = |index: usize| self.eval(&args[index], node, position, size);
+        let string = |index: usize| -> Result<String> {
+            match args.get(index) {
+                Some(arg) => Ok(self.eval(arg, node, position, size)?.string(doc)),
+                None => Ok(node.string_value(doc)),
+            }
+        };
+        let number = |index: usize| -> Result<f64> { Ok(eval(index)?.number(doc)) };
+        // The first node of the argument, or the context node without one.
+        let first_node = || -> Result<Option<NodeRef>> {
+            match args.first() {
+                Some(arg) => Ok(self
+                    .eval_node_set(arg, node, position, size)?
+                    .into_iter()
+                    .next()),
+                None => Ok(Some(node.clone())),
+            }
+        };
+
+        Ok(match function {
+            Function::Last => Value::Number(size as f64),
+            Function::Position => Value::Number(position as f64),
+            Function::Count => {
+                Value::Number(self.eval_node_set(&args[0], node, position, size)?.len() as f64)
+            }
+            Function::Id => {
+                let ids = match eval(0)? {
+                    Value::NodeSet(nodes) => nodes
+                        .iter()
+                        .map(|node| node.string_value(doc))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    value => value.string(doc),
+                };
+                let ids: HashSet<_> = ids.split(accept_as_white_space).collect();
+
+                let root = self.tree_root(node.node_id());
+                let nodes = doc
+                    .descendants(root)
+                    .filter(|&id| {
+                        doc.element(id)
+                            .and_then(|elem| elem.attr("xml:id"))
+                            .is_some_and(|id| ids.contains(id))
+                    })
+                    .map(NodeRef::Node)
+                    .collect();
+                Value::NodeSet(nodes)
+            }
+            Function::LocalName => {
+                Value::String(first_node()?.map_or_else(String::new, |node| self.local_name(&node)))
+            }
+            Function::NamespaceUri => {
+                Value::String(first_node()?.map_or_else(String::new, |node| {
+                    self.namespace_uri(&node).unwrap_or_default().to_owned()
+                }))
+            }
+            Function::Name => {
+                Value::String(first_node()?.map_or_else(String::new, |node| self.name(&node)))
+            }
+            Function::String => Value::String(string(0)?),
+            Function::Concat => Value::String((0..args.len()).map(string).collect::<Result<_>>()?),
+            Function::StartsWith => Value::Boolean(string(0)?.starts_with(&string(1)?)),
+            Function::Contains => Value::Boolean(string(0)?.contains(&string(1)?)),
+            Function::SubstringBefore => {
+                let (string, pattern) = (string(0)?, string(1)?);
+                let before = string.split_once(&pattern).map_or("", |(before, _)| before);
+
+                Value::String(before.to_owned())
+            }
+            Function::SubstringAfter => {
+                let (string, pattern) = (string(0)?, string(1)?);
+                let after = string.split_once(&pattern).map_or("", |(_, after)| after);
+
+                Value::String(after.to_owned())
+            }
+            Function::Substring => {
+                let start = round(number(1)?);
+                let end = match args.get(2) {
+                    Some(_) => start + round(number(2)?),
+                    None => f64::INFINITY,
+                };
+                let substring = string(0)?
+                    .chars()
+                    .enumerate()
+                    .filter(|&(index, _)| {
+                        let position = (index + 1) as f64;
+                        position >= start && position < end
+                    })
+                    .map(|(_, ch)| ch)
+                    .collect();
+                Value::String(substring)
+            }
+            Function::StringLength => Value::Number(string(0)?.chars().count() as f64),
+            Function::NormalizeSpace => Value::String(
+                string(0)?
+                    .split(accept_as_white_space)
+                    .filter(|word| !word.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            Function::Translate => {
+                let from: Vec<_> = string(1)?.chars().collect();
+                let to: Vec<_> = string(2)?.chars().collect();
+
+                let translated = string(0)?
+                    .chars()
+                    .filter_map(|ch| match from.iter().position(|&from| from == ch) {
+                        Some(index) => to.get(index).copied(),
+                        None => Some(ch),
+                    })
+                    .collect();
+                Value::String(translated)
+            }
+            Function::Boolean => Value::Boolean(eval(0)?.boolean()),
+            Function::Not => Value::Boolean(!eval(0)?.boolean()),
+            Function::True => Value::Boolean(true),
+            Function::False => Value::Boolean(false),
+            Function::Lang => {
+                let lang = string(0)?.to_lowercase();
+                let node_lang = iter::once(node.node_id())
+                    .chain(doc.ancestors(node.node_id()))
+                    .find_map(|id| doc.element(id)?.attr("xml:lang"))
+                    .map(str::to_lowercase);
+
+                Value::Boolean(node_lang.is_some_and(|node_lang| {
+                    node_lang == lang
+                        || node_lang
+                            .strip_prefix(&lang)
+                            .is_some_and(|rest| rest.starts_with('-'))
+                }))
+            }
+            Function::Number => match args.first() {
+                Some(_) => Value::Number(number(0)?),
+                None => Value::Number(parse_number(&node.string_value(doc))),
+            },
+            Function::Sum => Value::Number(
+                self.eval_node_set(&args[0], node, position, size)?
+                    .iter()
+                    .map(|node| parse_number(&node.string_value(doc)))
+                    .sum(),
+            ),
+            Function::Floor => Value::Number(number(0)?.floor()),
+            Function::Ceiling => Value::Number(number(0)?.ceil()),
+            Function::Round => Value::Number(round(number(0)?)),
+        })
+    }
+
+    fn compare(&self, op: CompareOp, lhs: &Value, rhs: &Value) -> bool {
+        let doc = self.doc;
+
+        match (lhs, rhs) {
+            (Value::NodeSet(lhs), Value::NodeSet(rhs)) => {
+                let rhs: Vec<_> = rhs.iter().map(|node| node.string_value(doc)).collect();
+
+                lhs.iter().any(|node| {
+                    let lhs = Value::String(node.string_value(doc));
+                    rhs.iter()
+                        .any(|rhs| self.compare(op, &lhs, &Value::String(rhs.clone())))
+                })
+            }
+            (Value::NodeSet(_), Value::Boolean(_)) => {
+                self.compare(op, &Value::Boolean(lhs.boolean()), rhs)
+            }
+            (Value::NodeSet(nodes), _) => nodes
+                .iter()
+                .any(|node| self.compare(op, &Value::String(node.string_value(doc)), rhs)),
+            (_, Value::NodeSet(_)) => self.compare(op.flip(), rhs, lhs),
+            _ => match op {
+                CompareOp::Eq | CompareOp::Neq => {
+                    let is_equal = match (lhs, rhs) {
+                        (Value::Boolean(_), _) | (_, Value::Boolean(_)) => {
+                            lhs.boolean() == rhs.boolean()
+                        }
+                        (Value::Number(_), _) | (_, Value::Number(_)) => {
+                            lhs.number(doc) == rhs.number(doc)
+                        }
+                        _ => lhs.string(doc) == rhs.string(doc),
+                    };
+                    is_equal == (op == CompareOp::Eq)
+                }
+                CompareOp::Lt => lhs.number(doc) < rhs.number(doc),
+                CompareOp::Le => lhs.number(doc) <= rhs.number(doc),
+                CompareOp::Gt => lhs.number(doc) > rhs.number(doc),
+                CompareOp::Ge => lhs.number(doc) >= rhs.number(doc),
+            },
+        }
+    }
+}
+
+fn is_xmlns(name: &str) -> bool {
+    name == "xmlns" || name.starts_with("xmlns:")
+}
+
+/// Rounds half up, keeping negative zero and what isn't finite.
+fn round(number: f64) -> f64 {
+    if !number.is_finite() || number == 0.0 {
+        return number;
+    }
+    let rounded = (number + 0.5).floor();
+
+    if rounded == 0.0 && number < 0.0 {
+        -0.0
+    } else {
+        rounded
+    }
+}
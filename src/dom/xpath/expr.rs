@@ -0,0 +1,799 @@
+use crate::error::{Error, Result, SyntaxError};
+use crate::token::rules::{accept_as_name, accept_as_white_space};
+use crate::Position;
+
+#[derive(PartialEq, Clone, Debug)]
+enum Token {
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Multiply,
+    And,
+    Or,
+    Mod,
+    Div,
+    /// A QName, `*` or `prefix:*`.
+    Name(String),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+}
+
+impl Token {
+    fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Self::Slash
+                | Self::DoubleSlash
+                | Self::Pipe
+                | Self::Plus
+                | Self::Minus
+                | Self::Eq
+                | Self::Neq
+                | Self::Lt
+                | Self::Le
+                | Self::Gt
+                | Self::Ge
+                | Self::Multiply
+                | Self::And
+                | Self::Or
+                | Self::Mod
+                | Self::Div
+        )
+    }
+
+    fn display(&self) -> String {
+        let punct = match self {
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::Dot => ".",
+            Self::DotDot => "..",
+            Self::At => "@",
+            Self::Comma => ",",
+            Self::ColonColon => "::",
+            Self::Slash => "/",
+            Self::DoubleSlash => "//",
+            Self::Pipe => "|",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Eq => "=",
+            Self::Neq => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Multiply => "*",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Mod => "mod",
+            Self::Div => "div",
+            Self::Name(name) => return name.clone(),
+            Self::Literal(literal) => return format!("\"{literal}\""),
+            Self::Number(number) => return number.to_string(),
+            Self::Variable(name) => return format!("${name}"),
+        };
+        punct.to_owned()
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(super) enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    Self_,
+}
+
+impl Axis {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ancestor" => Self::Ancestor,
+            "ancestor-or-self" => Self::AncestorOrSelf,
+            "attribute" => Self::Attribute,
+            "child" => Self::Child,
+            "descendant" => Self::Descendant,
+            "descendant-or-self" => Self::DescendantOrSelf,
+            "following" => Self::Following,
+            "following-sibling" => Self::FollowingSibling,
+            "namespace" => Self::Namespace,
+            "parent" => Self::Parent,
+            "preceding" => Self::Preceding,
+            "preceding-sibling" => Self::PrecedingSibling,
+            "self" => Self::Self_,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum NodeTest {
+    /// `*`
+    Any,
+    /// `prefix:*`
+    Namespace(String),
+    Name {
+        prefix: Option<String>,
+        local_name: String,
+    },
+    Node,
+    Text,
+    Comment,
+    Pi(Option<String>),
+}
+
+#[derive(Debug)]
+pub(super) struct Step {
+    pub axis: Axis,
+    pub test: NodeTest,
+    pub predicates: Vec<Expr>,
+}
+
+#[derive(Debug)]
+pub(super) enum PathStart {
+    Root,
+    Context,
+    Filter(Box<Expr>),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(super) enum CompareOp {
+    Eq,
+    Neq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// The operator comparing the same operands the other way round.
+    pub fn flip(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::Le => Self::Ge,
+            Self::Gt => Self::Lt,
+            Self::Ge => Self::Le,
+            op => op,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(super) enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(super) enum Function {
+    Last,
+    Position,
+    Count,
+    Id,
+    LocalName,
+    NamespaceUri,
+    Name,
+    String,
+    Concat,
+    StartsWith,
+    Contains,
+    SubstringBefore,
+    SubstringAfter,
+    Substring,
+    StringLength,
+    NormalizeSpace,
+    Translate,
+    Boolean,
+    Not,
+    True,
+    False,
+    Lang,
+    Number,
+    Sum,
+    Floor,
+    Ceiling,
+    Round,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "last" => Self::Last,
+            "position" => Self::Position,
+            "count" => Self::Count,
+            "id" => Self::Id,
+            "local-name" => Self::LocalName,
+            "namespace-uri" => Self::NamespaceUri,
+            "name" => Self::Name,
+            "string" => Self::String,
+            "concat" => Self::Concat,
+            "starts-with" => Self::StartsWith,
+            "contains" => Self::Contains,
+            "substring-before" => Self::SubstringBefore,
+            "substring-after" => Self::SubstringAfter,
+            "substring" => Self::Substring,
+            "string-length" => Self::StringLength,
+            "normalize-space" => Self::NormalizeSpace,
+            "translate" => Self::Translate,
+            "boolean" => Self::Boolean,
+            "not" => Self::Not,
+            "true" => Self::True,
+            "false" => Self::False,
+            "lang" => Self::Lang,
+            "number" => Self::Number,
+            "sum" => Self::Sum,
+            "floor" => Self::Floor,
+            "ceiling" => Self::Ceiling,
+            "round" => Self::Round,
+            _ => return None,
+        })
+    }
+
+    /// How many arguments the function takes at least and at most.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Self::Last | Self::Position | Self::True | Self::False => (0, 0),
+            Self::LocalName
+            | Self::NamespaceUri
+            | Self::Name
+            | Self::String
+            | Self::StringLength
+            | Self::NormalizeSpace
+            | Self::Number => (0, 1),
+            Self::Count
+            | Self::Id
+            | Self::Boolean
+            | Self::Not
+            | Self::Lang
+            | Self::Sum
+            | Self::Floor
+            | Self::Ceiling
+            | Self::Round => (1, 1),
+            Self::StartsWith | Self::Contains | Self::SubstringBefore | Self::SubstringAfter => {
+                (2, 2)
+            }
+            Self::Substring => (2, 3),
+            Self::Translate => (3, 3),
+            Self::Concat => (2, usize::MAX),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(super) enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    Arith(ArithOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Union(Box<Expr>, Box<Expr>),
+    Filter(Box<Expr>, Vec<Expr>),
+    Path(PathStart, Vec<Step>),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Call(Function, Vec<Expr>),
+}
+
+const NODE_TYPES: [&str; 4] = ["comment", "text", "processing-instruction", "node"];
+
+pub(super) fn parse(expr: &str) -> Result<Expr> {
+    let mut parser = ExprParser {
+        expr,
+        tokens: tokenize(expr)?,
+        index: 0,
+    };
+    let parsed = parser.parse_or()?;
+
+    match parser.peek() {
+        Some(token) => Err(parser.error(SyntaxError::UnexpectedToken(token.display()))),
+        None => Ok(parsed),
+    }
+}
+
+fn error_at(expr: &str, offset: usize, err: SyntaxError) -> Error {
+    Error::from(err).at(Position {
+        line: 1,
+        column: expr[..offset].chars().count() as u64 + 1,
+        byte_column: offset as u64 + 1,
+        offset: offset as u64,
+    })
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let rest = &expr[offset..];
+        offset += rest.len() - rest.trim_start_matches(accept_as_white_space).len();
+        let rest = &expr[offset..];
+
+        let Some(ch) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        // `*` and names like `div` are operators only right after an operand.
+        let is_after_operand = tokens.last().is_some_and(|(token, _)| {
+            !token.is_operator()
+                && !matches!(
+                    token,
+                    Token::At | Token::ColonColon | Token::LParen | Token::LBracket | Token::Comma
+                )
+        });
+
+        let (token, len) = match ch {
+            '(' => (Token::LParen, 1),
+            ')' => (Token::RParen, 1),
+            '[' => (Token::LBracket, 1),
+            ']' => (Token::RBracket, 1),
+            '@' => (Token::At, 1),
+            ',' => (Token::Comma, 1),
+            '|' => (Token::Pipe, 1),
+            '+' => (Token::Plus, 1),
+            '-' => (Token::Minus, 1),
+            '=' => (Token::Eq, 1),
+            '!' if rest.starts_with("!=") => (Token::Neq, 2),
+            '<' if rest.starts_with("<=") => (Token::Le, 2),
+            '<' => (Token::Lt, 1),
+            '>' if rest.starts_with(">=") => (Token::Ge, 2),
+            '>' => (Token::Gt, 1),
+            '/' if rest.starts_with("//") => (Token::DoubleSlash, 2),
+            '/' => (Token::Slash, 1),
+            ':' if rest.starts_with("::") => (Token::ColonColon, 2),
+            '.' if rest.starts_with("..") => (Token::DotDot, 2),
+            '.' if !rest[1..].starts_with(|ch: char| ch.is_ascii_digit()) => (Token::Dot, 1),
+            '.' | '0'..='9' => {
+                let len = rest
+                    .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+                    .unwrap_or(rest.len());
+                let number = rest[..len].parse().map_err(|_| {
+                    error_at(
+                        expr,
+                        offset,
+                        SyntaxError::UnexpectedToken(rest[..len].to_owned()),
+                    )
+                })?;
+
+                (Token::Number(number), len)
+            }
+            '"' | '\'' => {
+                let Some(len) = rest[1..].find(ch) else {
+                    let delim = if ch == '"' { "`\"`" } else { "`'`" };
+                    return Err(error_at(
+                        expr,
+                        offset,
+                        SyntaxError::UnclosedDelimiter(delim),
+                    ));
+                };
+                (Token::Literal(rest[1..len + 1].to_owned()), len + 2)
+            }
+            '$' => {
+                let len = qname_len(&rest[1..], false);
+
+                if len == 0 {
+                    return Err(error_at(expr, offset, SyntaxError::MismatchedToken("Name")));
+                }
+                (Token::Variable(rest[1..len + 1].to_owned()), len + 1)
+            }
+            '*' if is_after_operand => (Token::Multiply, 1),
+            '*' => (Token::Name("*".to_owned()), 1),
+            _ => {
+                let len = qname_len(rest, !is_after_operand);
+                let name = &rest[..len];
+
+                let token = match name {
+                    "" => {
+                        return Err(error_at(
+                            expr,
+                            offset,
+                            SyntaxError::UnexpectedToken(ch.to_string()),
+                        ))
+                    }
+                    "and" if is_after_operand => Token::And,
+                    "or" if is_after_operand => Token::Or,
+                    "mod" if is_after_operand => Token::Mod,
+                    "div" if is_after_operand => Token::Div,
+                    _ if is_after_operand => {
+                        return Err(error_at(
+                            expr,
+                            offset,
+                            SyntaxError::UnexpectedToken(name.to_owned()),
+                        ))
+                    }
+                    _ => Token::Name(name.to_owned()),
+                };
+                (token, len)
+            }
+        };
+        tokens.push((token, offset));
+        offset += len;
+    }
+}
+
+/// Returns the length of the QName at the start of `rest`, which may end
+/// in `:*` if `allow_wildcard` is set.
+fn qname_len(rest: &str, allow_wildcard: bool) -> usize {
+    let ncname_len = |rest: &str| {
+        let mut rule = accept_as_name();
+
+        rest.find(|ch| ch == ':' || !rule(ch)).unwrap_or(rest.len())
+    };
+    let len = ncname_len(rest);
+
+    // `::` ends the name of an axis.
+    match rest[len..].strip_prefix(':') {
+        Some(local) if len > 0 && !local.starts_with(':') => {
+            if allow_wildcard && local.starts_with('*') {
+                len + 2
+            } else {
+                let local_len = ncname_len(local);
+                if local_len > 0 {
+                    len + 1 + local_len
+                } else {
+                    len
+                }
+            }
+        }
+        _ => len,
+    }
+}
+
+struct ExprParser<'e> {
+    expr: &'e str,
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.index + ahead).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.index += 1;
+
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let is_next = self.peek() == Some(token);
+        if is_next {
+            self.index += 1;
+        }
+        is_next
+    }
+
+    fn expect(&mut self, token: &Token, display: &'static str) -> Result<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected(display))
+        }
+    }
+
+    fn error(&self, err: SyntaxError) -> Error {
+        let offset = self
+            .tokens
+            .get(self.index)
+            .map_or(self.expr.len(), |(_, offset)| *offset);
+
+        error_at(self.expr, offset, err)
+    }
+
+    /// The error for finding something else than `expected`.
+    fn unexpected(&self, expected: &'static str) -> Error {
+        match self.peek() {
+            Some(token) => self.error(SyntaxError::UnexpectedToken(token.display())),
+            None => self.error(SyntaxError::MismatchedToken(expected)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_and()?;
+
+        while self.eat(&Token::Or) {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.parse_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_equality()?;
+
+        while self.eat(&Token::And) {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.parse_equality()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_relational()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Eq) => CompareOp::Eq,
+                Some(Token::Neq) => CompareOp::Neq,
+                _ => return Ok(lhs),
+            };
+            self.index += 1;
+            lhs = Expr::Compare(op, Box::new(lhs), Box::new(self.parse_relational()?));
+        }
+    }
+
+    fn parse_relational(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_additive()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Lt) => CompareOp::Lt,
+                Some(Token::Le) => CompareOp::Le,
+                Some(Token::Gt) => CompareOp::Gt,
+                Some(Token::Ge) => CompareOp::Ge,
+                _ => return Ok(lhs),
+            };
+            self.index += 1;
+            lhs = Expr::Compare(op, Box::new(lhs), Box::new(self.parse_additive()?));
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_multiplicative()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => ArithOp::Add,
+                Some(Token::Minus) => ArithOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.index += 1;
+            lhs = Expr::Arith(op, Box::new(lhs), Box::new(self.parse_multiplicative()?));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+
+        loop {
+            let op = match self.peek() {
+                Some(Token::Multiply) => ArithOp::Mul,
+                Some(Token::Div) => ArithOp::Div,
+                Some(Token::Mod) => ArithOp::Mod,
+                _ => return Ok(lhs),
+            };
+            self.index += 1;
+            lhs = Expr::Arith(op, Box::new(lhs), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat(&Token::Minus) {
+            return Ok(Expr::Neg(Box::new(self.parse_unary()?)));
+        }
+        let mut lhs = self.parse_path()?;
+
+        while self.eat(&Token::Pipe) {
+            lhs = Expr::Union(Box::new(lhs), Box::new(self.parse_path()?));
+        }
+        Ok(lhs)
+    }
+
+    fn is_at_step(&self) -> bool {
+        match self.peek() {
+            Some(Token::Dot | Token::DotDot | Token::At) => true,
+            Some(Token::Name(name)) => {
+                self.peek_at(1) != Some(&Token::LParen) || NODE_TYPES.contains(&name.as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_path(&mut self) -> Result<Expr> {
+        let start = match self.peek() {
+            Some(Token::Slash) => {
+                self.index += 1;
+
+                if !self.is_at_step() {
+                    return Ok(Expr::Path(PathStart::Root, Vec::new()));
+                }
+                PathStart::Root
+            }
+            Some(Token::DoubleSlash) => PathStart::Root,
+            _ if self.is_at_step() => PathStart::Context,
+            _ => {
+                let primary = self.parse_primary()?;
+                let predicates = self.parse_predicates()?;
+                let filter = if predicates.is_empty() {
+                    primary
+                } else {
+                    Expr::Filter(Box::new(primary), predicates)
+                };
+
+                if !matches!(self.peek(), Some(Token::Slash | Token::DoubleSlash)) {
+                    return Ok(filter);
+                }
+                self.eat(&Token::Slash);
+                PathStart::Filter(Box::new(filter))
+            }
+        };
+        let mut steps = Vec::new();
+
+        loop {
+            if self.eat(&Token::DoubleSlash) {
+                steps.push(Step {
+                    axis: Axis::DescendantOrSelf,
+                    test: NodeTest::Node,
+                    predicates: Vec::new(),
+                });
+            }
+            steps.push(self.parse_step()?);
+
+            if !self.eat(&Token::Slash) && self.peek() != Some(&Token::DoubleSlash) {
+                return Ok(Expr::Path(start, steps));
+            }
+        }
+    }
+
+    fn parse_step(&mut self) -> Result<Step> {
+        let abbreviated = |axis| Step {
+            axis,
+            test: NodeTest::Node,
+            predicates: Vec::new(),
+        };
+        if self.eat(&Token::Dot) {
+            return Ok(abbreviated(Axis::Self_));
+        }
+        if self.eat(&Token::DotDot) {
+            return Ok(abbreviated(Axis::Parent));
+        }
+
+        let axis = if self.eat(&Token::At) {
+            Axis::Attribute
+        } else if let (Some(Token::Name(name)), Some(Token::ColonColon)) =
+            (self.peek(), self.peek_at(1))
+        {
+            let axis = Axis::from_name(name)
+                .ok_or_else(|| self.error(SyntaxError::UnknownAxis(name.clone())))?;
+            self.index += 2;
+            axis
+        } else {
+            Axis::Child
+        };
+
+        let test = match self.next() {
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LParen) => {
+                self.index += 1;
+
+                let test = match name.as_str() {
+                    "comment" => NodeTest::Comment,
+                    "text" => NodeTest::Text,
+                    "node" => NodeTest::Node,
+                    "processing-instruction" => match self.peek() {
+                        Some(Token::Literal(target)) => {
+                            let target = target.clone();
+                            self.index += 1;
+                            NodeTest::Pi(Some(target))
+                        }
+                        _ => NodeTest::Pi(None),
+                    },
+                    _ => {
+                        self.index -= 2;
+                        return Err(self.error(SyntaxError::UnexpectedToken(name)));
+                    }
+                };
+                self.expect(&Token::RParen, "`)`")?;
+                test
+            }
+            Some(Token::Name(name)) => match name.split_once(':') {
+                _ if name == "*" => NodeTest::Any,
+                Some((prefix, "*")) => NodeTest::Namespace(prefix.to_owned()),
+                Some((prefix, local_name)) => NodeTest::Name {
+                    prefix: Some(prefix.to_owned()),
+                    local_name: local_name.to_owned(),
+                },
+                None => NodeTest::Name {
+                    prefix: None,
+                    local_name: name,
+                },
+            },
+            _ => {
+                self.index -= 1;
+                return Err(self.unexpected("node test"));
+            }
+        };
+
+        Ok(Step {
+            axis,
+            test,
+            predicates: self.parse_predicates()?,
+        })
+    }
+
+    fn parse_predicates(&mut self) -> Result<Vec<Expr>> {
+        let mut predicates = Vec::new();
+
+        while self.eat(&Token::LBracket) {
+            predicates.push(self.parse_or()?);
+            self.expect(&Token::RBracket, "`]`")?;
+        }
+        Ok(predicates)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Variable(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Literal(literal)) => Ok(Expr::Literal(literal)),
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                self.expect(&Token::RParen, "`)`")?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LParen) => {
+                self.index -= 1;
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| self.error(SyntaxError::UnknownFunction(name.clone())))?;
+                let call_index = self.index;
+                self.index += 2;
+
+                let mut args = Vec::new();
+                if !self.eat(&Token::RParen) {
+                    loop {
+                        args.push(self.parse_or()?);
+
+                        if self.eat(&Token::RParen) {
+                            break;
+                        }
+                        self.expect(&Token::Comma, "`)`")?;
+                    }
+                }
+
+                let (min, max) = function.arity();
+                if args.len() < min || args.len() > max {
+                    self.index = call_index;
+                    return Err(self.error(SyntaxError::WrongArgumentCount(name)));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            _ => {
+                self.index -= 1;
+                Err(self.unexpected("expression"))
+            }
+        }
+    }
+}
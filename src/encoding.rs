@@ -1,64 +1,208 @@
-use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
-use std::io::{BufRead, Result};
+use crate::error::{Result, SyntaxError, WriteError};
+use encoding_rs::{
+    CoderResult, Decoder, DecoderResult, Encoder, EncoderResult, Encoding, UTF_16BE, UTF_16LE,
+    UTF_8,
+};
+use std::io::{BufRead, Write};
 use std::ops::{Deref, DerefMut};
 use std::str;
 
+/// How far the encoding of the source is known (XML 1.0 Appendix F).
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Detection {
+    /// Nothing was read yet, the first bytes are still to be sniffed.
+    Pending,
+    /// The XML declaration is being decoded with the sniffed encoding,
+    /// filling stops right after its `>`.
+    InDecl,
+    /// The XML declaration was decoded, filling waits for its encoding.
+    AfterDecl,
+    Done,
+}
+
 pub struct DecBuffer<const S: usize = 8192> {
     buf: [u8; S],
     pos: usize,
     filled: usize,
     decoder: Decoder,
+    detection: Detection,
+    bom: Option<&'static Encoding>,
+    /// Replace malformed sequences with U+FFFD instead of failing.
+    lenient: bool,
+    /// How many bytes were read from the source.
+    source_pos: u64,
+    /// Where the malformed sequence that stopped filling starts.
+    malformed: Option<u64>,
+    is_finished: bool,
 }
 
 impl<const S: usize> DecBuffer<S> {
     pub fn new(enc: &str) -> Self {
+        Self::with_decoder(
+            Encoding::for_label(enc.as_bytes())
+                .unwrap_or(UTF_8)
+                .new_decoder(),
+            Detection::Done,
+        )
+    }
+
+    /// Creates a buffer which detects the encoding from the byte order mark
+    /// or the XML declaration of the source.
+    pub fn detect() -> Self {
+        Self::with_decoder(UTF_8.new_decoder_without_bom_handling(), Detection::Pending)
+    }
+
+    fn with_decoder(decoder: Decoder, detection: Detection) -> Self {
         Self {
             buf: [0; S],
             pos: 0,
             filled: 0,
-            decoder: Encoding::for_label(enc.as_bytes())
-                .unwrap_or(UTF_8)
-                .new_decoder(),
+            decoder,
+            detection,
+            bom: None,
+            lenient: false,
+            source_pos: 0,
+            malformed: None,
+            is_finished: false,
         }
     }
 
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
     pub fn encoding(&self) -> &str {
         self.decoder.encoding().name()
     }
 
-    pub fn discard(&mut self) {
-        self.pos = 0;
-        self.filled = 0;
+    /// Sniffs the encoding from the first bytes of `reader` if it wasn't
+    /// yet, returning the length of the byte order mark it skipped.
+    pub fn sniff(&mut self, mut reader: impl BufRead) -> Result<usize> {
+        if self.detection != Detection::Pending {
+            return Ok(0);
+        }
+        // Enough for a byte order mark and `<?xml` in UTF-16, which may take
+        // several reads.
+        let mut head = [0; 12];
+        let mut len = 0;
+
+        while len < head.len() {
+            let input = reader.fill_buf()?;
+
+            if input.is_empty() {
+                break;
+            }
+            let read = input.len().min(head.len() - len);
+            head[len..len + read].copy_from_slice(&input[..read]);
+            reader.consume(read);
+            len += read;
+        }
+        let head = &head[..len];
+
+        let (encoding, bom_len) = Encoding::for_bom(head).unwrap_or(match head {
+            [0x3C, 0x00, 0x3F, 0x00, ..] => (UTF_16LE, 0),
+            [0x00, 0x3C, 0x00, 0x3F, ..] => (UTF_16BE, 0),
+            _ => (UTF_8, 0),
+        });
+        let (decl, _) = encoding.decode_without_bom_handling(&head[bom_len..]);
+
+        self.detection = if decl.starts_with("<?xml") {
+            Detection::InDecl
+        } else {
+            Detection::Done
+        };
+        self.bom = (bom_len > 0).then_some(encoding);
+        self.decoder = encoding.new_decoder_without_bom_handling();
+        self.source_pos += bom_len as u64;
+
+        // What follows the byte order mark was taken from `reader` already.
+        let _ = self.decode(&head[bom_len..], false);
+
+        Ok(bom_len)
+    }
+
+    /// Switches to the encoding named by the XML declaration, or keeps the
+    /// sniffed one if `label` is `None`. Does nothing if the encoding was
+    /// given up front.
+    pub fn declare(&mut self, label: Option<&str>) -> Result<()> {
+        if !matches!(self.detection, Detection::InDecl | Detection::AfterDecl) {
+            return Ok(());
+        }
+        self.detection = Detection::Done;
+
+        let Some(label) = label else {
+            return Ok(());
+        };
+        let detected = self.decoder.encoding();
+        let declared = declared_encoding(detected, self.bom.is_some(), label)?;
+
+        if !is_utf16(detected) && declared != detected {
+            self.decoder = declared.new_decoder_without_bom_handling();
+        }
+        Ok(())
+    }
+
+    /// Returns how many bytes `decoded` took up in the source before decoding.
+    pub fn source_len(&self, decoded: &str) -> usize {
+        let encoding = self.decoder.encoding();
+
+        if encoding == UTF_8 {
+            decoded.len()
+        } else if encoding == UTF_16LE || encoding == UTF_16BE {
+            decoded.encode_utf16().count() * 2
+        } else if encoding.is_single_byte() {
+            decoded.chars().count()
+        } else {
+            encoded_len(encoding, decoded)
+        }
     }
 
     pub fn consume(&mut self, amt: usize) {
         self.pos += amt.min(self.filled - self.pos);
     }
 
-    pub fn unconsume(&mut self, amt: usize) {
-        self.pos -= amt.min(self.pos);
+    pub fn fill(&mut self, reader: impl BufRead) -> Result<&str> {
+        self.fill_from(reader, false)
     }
 
-    pub fn fill(&mut self, mut reader: impl BufRead) -> Result<&str> {
-        let decoder = &mut self.decoder;
-        let mut is_last = false;
-        let mut _total_had_errors = false;
+    /// Fills from what `reader` holds, without taking it running out as the
+    /// end of the source.
+    pub fn fill_available(&mut self, reader: impl BufRead) -> Result<&str> {
+        self.fill_from(reader, true)
+    }
 
-        loop {
-            let unfilled = unsafe { str::from_utf8_unchecked_mut(&mut self.buf[self.filled..]) };
+    /// Tells whether the source started with a byte order mark.
+    pub fn has_bom(&self) -> bool {
+        self.bom.is_some()
+    }
+
+    /// Tells whether the encoding is still to be sniffed from the first bytes.
+    pub fn is_sniffing(&self) -> bool {
+        self.detection == Detection::Pending
+    }
 
-            let (result, read, written, had_errors) =
-                decoder.decode_to_str(reader.fill_buf()?, unfilled, is_last);
+    fn fill_from(&mut self, mut reader: impl BufRead, is_partial: bool) -> Result<&str> {
+        self.compact();
 
-            self.filled += written;
+        match self.detection {
+            Detection::InDecl => return self.fill_decl(reader, is_partial),
+            Detection::AfterDecl => return Ok(self),
+            Detection::Pending | Detection::Done => {}
+        }
+        let mut is_last = false;
+
+        while !self.is_finished && self.malformed.is_none() {
+            let input = reader.fill_buf()?;
+
+            if is_partial && input.is_empty() {
+                break;
+            }
+            let (result, read) = self.decode(input, is_last);
             reader.consume(read);
-            _total_had_errors |= had_errors;
 
             match result {
                 CoderResult::InputEmpty => {
-                    if is_last {
-                        break;
-                    }
+                    self.is_finished = is_last;
                     is_last = read == 0;
                 }
                 CoderResult::OutputFull => break,
@@ -67,6 +211,80 @@ impl<const S: usize> DecBuffer<S> {
 
         Ok(self)
     }
+
+    /// Returns where the malformed sequence decoding stopped at starts.
+    pub fn malformed(&self) -> Option<u64> {
+        self.malformed
+    }
+
+    /// Decodes as much of `input` as fits, returning how many bytes were read.
+    /// In strict mode, a malformed sequence stops decoding right before it.
+    fn decode(&mut self, input: &[u8], last: bool) -> (CoderResult, usize) {
+        let unfilled = unsafe { str::from_utf8_unchecked_mut(&mut self.buf[self.filled..]) };
+
+        let (result, read, written) = if self.lenient {
+            let (result, read, written, _) = self.decoder.decode_to_str(input, unfilled, last);
+            (result, read, written)
+        } else {
+            let (result, read, written) = self
+                .decoder
+                .decode_to_str_without_replacement(input, unfilled, last);
+
+            let result = match result {
+                DecoderResult::InputEmpty => CoderResult::InputEmpty,
+                DecoderResult::OutputFull => CoderResult::OutputFull,
+                DecoderResult::Malformed(len, extra) => {
+                    let end = self.source_pos + read as u64;
+                    self.malformed = Some(end.saturating_sub(len as u64 + extra as u64));
+                    CoderResult::OutputFull
+                }
+            };
+            (result, read, written)
+        };
+
+        self.filled += written;
+        self.source_pos += read as u64;
+
+        (result, read)
+    }
+
+    /// Decodes the XML declaration a byte at a time, so that nothing past
+    /// its `>` is decoded before its encoding is known.
+    fn fill_decl(&mut self, mut reader: impl BufRead, is_partial: bool) -> Result<&str> {
+        while S - self.filled >= 4 {
+            let input = reader.fill_buf()?;
+
+            if input.is_empty() && is_partial {
+                return Ok(self);
+            }
+            if input.is_empty() {
+                self.detection = Detection::Done;
+                return self.fill(reader);
+            }
+            let (_, read) = self.decode(&input[..1], false);
+            reader.consume(read);
+
+            if self.malformed.is_some() {
+                break;
+            }
+            if self.buf[..self.filled].ends_with(b">") {
+                self.detection = Detection::AfterDecl;
+                return Ok(self);
+            }
+        }
+        self.detection = Detection::Done;
+
+        Ok(self)
+    }
+
+    /// Moves what is left to the start, making room for filling.
+    fn compact(&mut self) {
+        if self.pos > 0 {
+            self.buf.copy_within(self.pos..self.filled, 0);
+            self.filled -= self.pos;
+            self.pos = 0;
+        }
+    }
 }
 
 impl<const S: usize> Deref for DecBuffer<S> {
@@ -82,3 +300,180 @@ impl<const S: usize> DerefMut for DecBuffer<S> {
         unsafe { str::from_utf8_unchecked_mut(&mut self.buf[self.pos..self.filled]) }
     }
 }
+
+/// Returns the encoding named by the XML declaration, unless it contradicts
+/// the one detected from the byte order mark, if any, or the first bytes.
+pub fn declared_encoding(
+    detected: &'static Encoding,
+    has_bom: bool,
+    label: &str,
+) -> Result<&'static Encoding> {
+    let declared = Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| SyntaxError::UnsupportedEncoding(label.to_owned()))?;
+
+    let conflicts = if is_utf16(detected) {
+        // `UTF-16` is declared whatever the byte order.
+        !is_utf16(declared)
+    } else if has_bom {
+        declared != UTF_8
+    } else {
+        is_utf16(declared) || !declared.is_ascii_compatible()
+    };
+    if conflicts {
+        return Err(SyntaxError::EncodingConflict {
+            detected: detected.name(),
+            declared: label.to_owned(),
+        }
+        .into());
+    }
+    Ok(declared)
+}
+
+fn encoded_len(encoding: &'static Encoding, mut decoded: &str) -> usize {
+    let mut encoder = encoding.new_encoder();
+    let mut scratch = [0; 1024];
+    let mut total = 0;
+
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(decoded, &mut scratch, false);
+
+        total += written;
+        decoded = &decoded[read..];
+
+        match result {
+            EncoderResult::InputEmpty => return total,
+            EncoderResult::OutputFull => {}
+            // Only U+FFFD standing in for a malformed sequence can't be encoded back.
+            EncoderResult::Unmappable(_) => total += 1,
+        }
+    }
+}
+
+/// Writes text to `writer` in `encoding`. `encoding_rs` doesn't encode into
+/// UTF-16, so that is done here.
+pub struct EncWriter<W> {
+    writer: W,
+    encoding: &'static Encoding,
+    /// `None` for UTF-8 and UTF-16.
+    encoder: Option<Encoder>,
+    is_started: bool,
+}
+
+impl<W: Write> EncWriter<W> {
+    pub fn new(writer: W, label: &str) -> Result<Self> {
+        let encoding = Encoding::for_label(label.as_bytes())
+            .filter(|&encoding| is_utf16(encoding) || encoding.output_encoding() == encoding)
+            .ok_or_else(|| SyntaxError::UnsupportedEncoding(label.to_owned()))?;
+        let encoder = (encoding != UTF_8 && !is_utf16(encoding)).then(|| encoding.new_encoder());
+
+        Ok(Self {
+            writer,
+            encoding,
+            encoder,
+            is_started: false,
+        })
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes `text`, spelling the chars `encoding` lacks as character
+    /// references if `escape` is set and failing on them otherwise.
+    pub fn write_str(&mut self, text: &str, escape: bool) -> Result<()> {
+        if !self.is_started && is_utf16(self.encoding) {
+            // UTF-16 entities must start with a byte order mark.
+            self.is_started = true;
+            self.write_str("\u{FEFF}", false)?;
+        }
+        self.is_started = true;
+
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(self.write_unicode(text)?);
+        };
+        let mut scratch = [0; 1024];
+        let mut rest = text;
+
+        loop {
+            let (result, read, written) =
+                encoder.encode_from_utf8_without_replacement(rest, &mut scratch, false);
+
+            self.writer.write_all(&scratch[..written])?;
+            rest = &rest[read..];
+
+            match result {
+                EncoderResult::InputEmpty => return Ok(()),
+                EncoderResult::OutputFull => {}
+                EncoderResult::Unmappable(ch) if escape => {
+                    let reference = format!("&#{};", ch as u32);
+                    let (_, _, written) = encoder.encode_from_utf8_without_replacement(
+                        &reference,
+                        &mut scratch,
+                        false,
+                    );
+
+                    self.writer.write_all(&scratch[..written])?;
+                }
+                EncoderResult::Unmappable(ch) => {
+                    return Err(WriteError::UnmappableChar(ch).into());
+                }
+            }
+        }
+    }
+
+    fn write_unicode(&mut self, text: &str) -> std::io::Result<()> {
+        if self.encoding == UTF_8 {
+            return self.writer.write_all(text.as_bytes());
+        }
+        let to_bytes = if self.encoding == UTF_16LE {
+            u16::to_le_bytes
+        } else {
+            u16::to_be_bytes
+        };
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(to_bytes).collect();
+
+        self.writer.write_all(&bytes)
+    }
+
+    /// Starts the output with a byte order mark, unless something was written
+    /// already or the encoding has none.
+    pub fn write_bom(&mut self) -> Result<()> {
+        if self.is_started || self.encoding != UTF_8 && !is_utf16(self.encoding) {
+            return Ok(());
+        }
+        // UTF-16 output gets one anyway.
+        self.write_str(
+            if self.encoding == UTF_8 {
+                "\u{FEFF}"
+            } else {
+                ""
+            },
+            false,
+        )
+    }
+
+    /// Ends the output, flushing any pending shift sequence.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(encoder) = &mut self.encoder {
+            let mut scratch = [0; 16];
+            let (_, _, written) =
+                encoder.encode_from_utf8_without_replacement("", &mut scratch, true);
+
+            self.writer.write_all(&scratch[..written])?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn is_utf16(encoding: &'static Encoding) -> bool {
+    encoding == UTF_16LE || encoding == UTF_16BE
+}
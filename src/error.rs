@@ -1,13 +1,62 @@
+use crate::read::Position;
 use std::{error, fmt, io};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub enum SyntaxError {
     MismatchedToken(&'static str),
     UnclosedDelimiter(&'static str),
     UnexpectedDelimiter(&'static str),
     UnexpectedEof,
+    /// A char that is allowed in XML but not where it stands.
+    UnexpectedChar(char),
+    UndefinedEntity(String),
+    InvalidCharRef(String),
+    MismatchedEndTag {
+        expected: String,
+        found: String,
+    },
+    UnclosedElement(String),
+    /// An end tag with no element open to close, such as when the reader
+    /// read the start tag before a document was built from its events.
+    UnexpectedEndTag(String),
+    MissingRootElement,
+    ContentBeforeRoot,
+    ContentAfterRoot,
+    InvalidMarkup,
+    ReservedPiTarget(String),
+    DuplicateAttribute(String),
+    RecursiveEntity(String),
+    /// An entity referenced in an attribute value puts `<` into it.
+    MarkupInEntity(String),
+    /// The replacement text of an entity ends an element it didn't start.
+    EndTagInEntity(String),
+    UnsupportedExternalEntity(String),
+    /// Expanding entities went over the named field of `EntityLimits`.
+    EntityExpansionLimit(&'static str),
+    InvalidQName(String),
+    UnboundPrefix(String),
+    InvalidNamespaceDecl(String),
+    UnsupportedEncoding(String),
+    /// A char outside the XML `Char` range.
+    InvalidChar(char),
+    /// The encoding declared by the XML declaration contradicts the one
+    /// detected from the byte order mark or the first bytes.
+    EncodingConflict {
+        detected: &'static str,
+        declared: String,
+    },
+    UnexpectedToken(String),
+    UnknownAxis(String),
+    UnknownFunction(String),
+    WrongArgumentCount(String),
+    UndefinedVariable(String),
+    /// An XPath operand had to be a node-set but wasn't.
+    NotANodeSet,
+    /// A document couldn't be mapped onto a Rust type, or the other way
+    /// round.
+    Custom(String),
 }
 
 impl fmt::Display for SyntaxError {
@@ -17,14 +66,124 @@ impl fmt::Display for SyntaxError {
             Self::UnclosedDelimiter(delim) => write!(f, "expected {delim} before EOF"),
             Self::UnexpectedDelimiter(delim) => write!(f, "unexpected {delim}"),
             Self::UnexpectedEof => write!(f, "unexpected EOF"),
+            Self::UnexpectedChar(ch) => write!(f, "unexpected `{ch}`"),
+            Self::UndefinedEntity(name) => write!(f, "undefined entity `&{name};`"),
+            Self::InvalidCharRef(code) => write!(f, "invalid character reference `&{code};`"),
+            Self::MismatchedEndTag { expected, found } => {
+                write!(f, "expected `</{expected}>`, found `</{found}>`")
+            }
+            Self::UnclosedElement(name) => write!(f, "expected `</{name}>` before EOF"),
+            Self::UnexpectedEndTag(name) => {
+                write!(f, "unexpected `</{name}>` with no open element")
+            }
+            Self::MissingRootElement => write!(f, "expected root element before EOF"),
+            Self::ContentBeforeRoot => write!(f, "unexpected content before root element"),
+            Self::ContentAfterRoot => write!(f, "unexpected content after root element"),
+            Self::InvalidMarkup => write!(f, "invalid markup in element content"),
+            Self::ReservedPiTarget(target) => {
+                write!(f, "reserved processing instruction target `{target}`")
+            }
+            Self::DuplicateAttribute(name) => write!(f, "duplicate attribute `{name}`"),
+            Self::RecursiveEntity(name) => write!(f, "entity `&{name};` references itself"),
+            Self::MarkupInEntity(name) => {
+                write!(
+                    f,
+                    "markup in the replacement text of `&{name};` within an attribute value"
+                )
+            }
+            Self::EndTagInEntity(name) => {
+                write!(
+                    f,
+                    "`</{name}>` in replacement text closes an element outside it"
+                )
+            }
+            Self::UnsupportedExternalEntity(name) => {
+                write!(f, "external entity `&{name};` is not supported")
+            }
+            Self::EntityExpansionLimit(limit) => write!(f, "entity expansion exceeds `{limit}`"),
+            Self::InvalidQName(name) => write!(f, "invalid qualified name `{name}`"),
+            Self::UnboundPrefix(prefix) => write!(f, "unbound namespace prefix `{prefix}`"),
+            Self::InvalidNamespaceDecl(name) => {
+                write!(f, "invalid namespace declaration `{name}`")
+            }
+            Self::UnsupportedEncoding(label) => write!(f, "unsupported encoding `{label}`"),
+            Self::InvalidChar(ch) => write!(f, "char U+{:04X} is not allowed in XML", *ch as u32),
+            Self::EncodingConflict { detected, declared } => write!(
+                f,
+                "declared encoding `{declared}` conflicts with detected `{detected}`"
+            ),
+            Self::UnexpectedToken(token) => write!(f, "unexpected `{token}`"),
+            Self::UnknownAxis(name) => write!(f, "unknown axis `{name}`"),
+            Self::UnknownFunction(name) => write!(f, "unknown function `{name}()`"),
+            Self::WrongArgumentCount(name) => {
+                write!(f, "wrong number of arguments to `{name}()`")
+            }
+            Self::UndefinedVariable(name) => write!(f, "undefined variable `${name}`"),
+            Self::NotANodeSet => write!(f, "expected a node-set"),
+            Self::Custom(msg) => f.write_str(msg),
         }
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+/// Output the writer was given but has no well-formed spelling for.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum WriteError {
+    /// A name isn't an XML `Name`.
+    InvalidName(String),
+    /// A char outside the XML `Char` range, which no escaping can write.
+    InvalidChar(char),
+    /// An XML declaration came after other output.
+    MisplacedXmlDecl,
+    /// A literal contains both `'` and `"`, so neither can quote it.
+    UnquotableLiteral(String),
+    /// The encoding being written lacks a char that can't be escaped where
+    /// it appears.
+    UnmappableChar(char),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "invalid name `{name}`"),
+            Self::InvalidChar(ch) => write!(f, "char U+{:04X} is not allowed in XML", *ch as u32),
+            Self::MisplacedXmlDecl => write!(f, "XML declaration must come first"),
+            Self::UnquotableLiteral(literal) => {
+                write!(f, "literal `{literal}` contains both kinds of quotes")
+            }
+            Self::UnmappableChar(ch) => {
+                write!(f, "char `{ch}` can't be written in the output encoding")
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
 pub enum Error {
     Io(io::ErrorKind),
-    Syntax(SyntaxError),
+    Syntax(SyntaxError, Position),
+    Write(WriteError),
+    /// The source holds a byte sequence that is malformed in its encoding,
+    /// `offset` bytes from its start.
+    Malformed {
+        offset: u64,
+    },
+}
+
+impl Error {
+    /// Attaches `position` to a syntax error.
+    pub fn at(self, position: Position) -> Self {
+        match self {
+            Self::Syntax(err, _) => Self::Syntax(err, position),
+            err => err,
+        }
+    }
+
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Self::Syntax(_, position) => Some(*position),
+            Self::Io(_) | Self::Malformed { .. } | Self::Write(_) => None,
+        }
+    }
 }
 impl error::Error for Error {}
 
@@ -32,7 +191,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Io(e) => e.fmt(f),
-            Self::Syntax(e) => e.fmt(f),
+            Self::Malformed { offset } => write!(f, "malformed byte sequence at offset {offset}"),
+            Self::Syntax(e, position) if *position == Position::default() => e.fmt(f),
+            Self::Syntax(e, position) => write!(f, "{e} at {position}"),
+            Self::Write(e) => e.fmt(f),
         }
     }
 }
@@ -45,6 +207,12 @@ impl From<io::Error> for Error {
 
 impl From<SyntaxError> for Error {
     fn from(err: SyntaxError) -> Self {
-        Error::Syntax(err)
+        Error::Syntax(err, Position::default())
+    }
+}
+
+impl From<WriteError> for Error {
+    fn from(err: WriteError) -> Self {
+        Error::Write(err)
     }
 }
@@ -0,0 +1,134 @@
+use crate::error::{Result, SyntaxError};
+use crate::token::rules::{accept_as_char, accept_as_name};
+use crate::token::{Delimiter, Name, Punctuation, Reference, Token};
+use std::borrow::Cow;
+
+/// Decodes the predefined entities and character references in `raw`,
+/// borrowing it back if there is nothing to decode.
+pub fn unescape(raw: &str) -> Result<Cow<'_, str>> {
+    if !raw.contains(Reference::PUNCT) {
+        return Ok(Cow::Borrowed(raw));
+    }
+    let mut result = String::with_capacity(raw.len());
+
+    unescape_into(raw, &mut result, &mut |name, result| {
+        Ok(push_predefined_entity(name, result))
+    })?;
+
+    Ok(Cow::Owned(result))
+}
+
+/// Appends `raw` to `result`, decoding character references and passing the
+/// name of every entity reference to `resolve_entity`, which appends its
+/// replacement and tells whether the entity is known.
+pub(crate) fn unescape_into(
+    raw: &str,
+    result: &mut String,
+    resolve_entity: &mut dyn FnMut(&str, &mut String) -> Result<bool>,
+) -> Result<()> {
+    let mut rest = raw;
+
+    while let Some(start) = rest.find(Reference::PUNCT) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + Reference::PUNCT.len()..];
+
+        let end = rest
+            .find(<Reference as Delimiter>::End::PUNCT)
+            .ok_or_else(|| {
+                SyntaxError::UnclosedDelimiter(<Reference as Delimiter>::End::display())
+            })?;
+        let reference = &rest[..end];
+
+        if let Some(code) = reference.strip_prefix('#') {
+            result.push(parse_char_ref(code)?);
+        } else {
+            let mut rule = accept_as_name();
+
+            if reference.is_empty() || !reference.chars().all(&mut rule) {
+                return Err(SyntaxError::MismatchedToken(Name::display()).into());
+            }
+            if !resolve_entity(reference, result)? {
+                return Err(SyntaxError::UndefinedEntity(reference.to_owned()).into());
+            }
+        }
+        rest = &rest[end + <Reference as Delimiter>::End::PUNCT.len()..];
+    }
+    result.push_str(rest);
+
+    Ok(())
+}
+
+fn parse_char_ref(code: &str) -> Result<char> {
+    let invalid = || SyntaxError::InvalidCharRef(format!("#{code}")).into();
+
+    let (digits, radix) = match code.strip_prefix('x') {
+        Some(hex) => (hex, 16),
+        None => (code, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+        return Err(invalid());
+    }
+
+    u32::from_str_radix(digits, radix)
+        .ok()
+        .and_then(char::from_u32)
+        .filter(|&ch| accept_as_char(ch))
+        .ok_or_else(invalid)
+}
+
+/// Escapes `text` for use as character data. `\r` is written as a character
+/// reference so that line-ending normalization doesn't drop it.
+pub fn escape_text(text: &str) -> Cow<'_, str> {
+    escape_with(text, |ch| match ch {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '\r' => Some("&#13;"),
+        _ => None,
+    })
+}
+
+/// Escapes `value` for use in an attribute value quoted with `"`. White
+/// space other than spaces is written as character references so that
+/// attribute value normalization keeps it.
+pub fn escape_att_value(value: &str) -> Cow<'_, str> {
+    escape_with(value, |ch| match ch {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '"' => Some("&quot;"),
+        '\t' => Some("&#9;"),
+        '\n' => Some("&#10;"),
+        '\r' => Some("&#13;"),
+        _ => None,
+    })
+}
+
+fn escape_with(text: &str, escape: impl Fn(char) -> Option<&'static str>) -> Cow<'_, str> {
+    let Some(start) = text.find(|ch| escape(ch).is_some()) else {
+        return Cow::Borrowed(text);
+    };
+    let mut result = String::with_capacity(text.len() + 8);
+    result.push_str(&text[..start]);
+
+    for ch in text[start..].chars() {
+        match escape(ch) {
+            Some(escaped) => result.push_str(escaped),
+            None => result.push(ch),
+        }
+    }
+    Cow::Owned(result)
+}
+
+pub(crate) fn push_predefined_entity(name: &str, result: &mut String) -> bool {
+    let replacement = match name {
+        "lt" => "<",
+        "gt" => ">",
+        "amp" => "&",
+        "apos" => "'",
+        "quot" => "\"",
+        _ => return false,
+    };
+    result.push_str(replacement);
+
+    true
+}
@@ -1,6 +1,18 @@
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod dom;
 mod encoding;
 pub mod error;
+pub mod escape;
 pub mod parse;
 mod read;
 pub mod reader;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod writer;
+#[cfg(feature = "serde")]
+pub use de::{from_reader, from_str};
 pub use parse::token;
+pub use read::Position;
+#[cfg(feature = "serde")]
+pub use ser::{to_string, to_writer};
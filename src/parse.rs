@@ -1,78 +1,118 @@
 pub mod token;
 
+use self::token::rules::{accept_as_char, Skip};
 use self::token::{Delimiter, Punctuation};
 use crate::error::{Error, Result, SyntaxError};
-use crate::read::{ReadSource, SourceReader};
+use crate::read::{Position, ReadSource, SliceReader, SourceReader, Stop};
 use std::borrow::Cow;
 
 pub type Parser<T> = SourceReader<T>;
+pub type SliceParser<'a> = SliceReader<'a>;
 
-pub trait Parse: Sized {
-    fn parse(input: &mut impl ParseSource) -> Result<Self>;
+/// Parsing from a source whose text lives for `'a`. The parsed value may
+/// borrow from that text.
+pub trait Parse<'a>: Sized {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self>;
 
-    fn opt_parse(input: &mut impl ParseSource) -> Result<Option<Self>> {
+    fn opt_parse(input: &mut impl ParseSource<'a>) -> Result<Option<Self>> {
         input.default_opt_parse::<Self>()
     }
 }
 
-trait PrivParseSource {
+pub(crate) trait PrivParseSource<'a> {
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()>;
+
+    fn has_bom(&self) -> bool;
+
     fn opt_parse_punct<'p>(&mut self, punct: &'p str) -> Result<Option<&'p str>>;
 
-    fn opt_parse_lit<'l>(
+    fn opt_parse_lit(
+        &mut self,
+        rule: impl FnMut(char) -> bool,
+        skip: Skip,
+    ) -> Result<Option<Cow<'a, str>>>;
+
+    fn parse_lit_until<E: Punctuation>(
         &mut self,
         rule: impl FnMut(char) -> bool,
-        delim: Option<&str>,
-    ) -> Result<Option<Cow<'l, str>>>;
+        skip: Skip,
+    ) -> Result<Cow<'a, str>>;
 
-    fn default_opt_parse<P: Parse>(&mut self) -> Result<Option<P>>;
+    fn default_opt_parse<P: Parse<'a>>(&mut self) -> Result<Option<P>>;
 }
 
 #[allow(private_bounds)]
-pub trait ParseSource: PrivParseSource + Sized {
+pub trait ParseSource<'a>: PrivParseSource<'a> + Sized {
     fn is_empty(&mut self) -> Result<bool>;
 
-    fn parse<P: Parse>(&mut self) -> Result<P> {
+    fn position(&self) -> Position;
+
+    fn parse<P: Parse<'a>>(&mut self) -> Result<P> {
         P::parse(self)
     }
 
-    fn opt_parse<P: Parse>(&mut self) -> Result<Option<P>> {
+    fn opt_parse<P: Parse<'a>>(&mut self) -> Result<Option<P>> {
         if self.is_empty()? {
             return Ok(None);
         }
         P::opt_parse(self)
     }
 
-    fn delimited<D: Delimiter>(&mut self) -> Result<impl ParseSource> {
-        D::parse(self)?;
-        Ok(Delimited::new(self, D::End::PUNCT))
+    fn delimited<D: Delimiter>(&mut self) -> Result<impl ParseSource<'a>> {
+        if self.opt_parse_punct(D::PUNCT)?.is_none() {
+            return Err(SyntaxError::MismatchedToken(D::display()).into());
+        }
+        Ok(Delimited::new::<D::End>(self))
     }
 }
 
-impl<T: ReadSource> PrivParseSource for T {
+impl<'a, T: ReadSource<'a>> PrivParseSource<'a> for T {
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()> {
+        ReadSource::declare_encoding(self, label)
+    }
+
+    fn has_bom(&self) -> bool {
+        ReadSource::has_bom(self)
+    }
+
     fn opt_parse_punct<'p>(&mut self, punct: &'p str) -> Result<Option<&'p str>> {
         Ok(self.skip_next(punct)?.then_some(punct))
     }
 
-    fn opt_parse_lit<'a>(
+    fn opt_parse_lit(
         &mut self,
         rule: impl FnMut(char) -> bool,
-        delim: Option<&str>,
+        skip: Skip,
     ) -> Result<Option<Cow<'a, str>>> {
-        let token = self.read_while(rule, delim.unwrap_or_default())?;
+        let (token, _) = self.read_while(rule, skip, "")?;
 
-        if token.is_empty() {
-            return Ok(None);
+        Ok((!token.is_empty()).then_some(token))
+    }
+
+    fn parse_lit_until<E: Punctuation>(
+        &mut self,
+        rule: impl FnMut(char) -> bool,
+        skip: Skip,
+    ) -> Result<Cow<'a, str>> {
+        match self.read_while(rule, skip, E::PUNCT)? {
+            (token, Stop::Delim) => Ok(token),
+            (_, Stop::Rejected(ch)) if !accept_as_char(ch) => {
+                Err(SyntaxError::InvalidChar(ch).into())
+            }
+            // The rule rejects a char that is only wrong where it stands,
+            // such as the second `-` of a `--` in a comment.
+            (_, Stop::Rejected(ch)) => Err(SyntaxError::UnexpectedChar(ch).into()),
+            (_, Stop::End) => Err(SyntaxError::UnclosedDelimiter(E::display()).into()),
         }
-        Ok(Some(token))
     }
 
-    fn default_opt_parse<P: Parse>(&mut self) -> Result<Option<P>> {
+    fn default_opt_parse<P: Parse<'a>>(&mut self) -> Result<Option<P>> {
         let pos_before = self.pos();
         let result = P::parse(self);
         let pos = self.pos();
 
         match result {
-            Err(Error::Syntax(SyntaxError::MismatchedToken(_)))
+            Err(Error::Syntax(SyntaxError::MismatchedToken(_), _))
                 if self.go_back(pos - pos_before) =>
             {
                 Ok(None)
@@ -82,55 +122,85 @@ impl<T: ReadSource> PrivParseSource for T {
     }
 }
 
-impl<T: ReadSource> ParseSource for T {
+impl<'a, T: ReadSource<'a>> ParseSource<'a> for T {
     fn is_empty(&mut self) -> Result<bool> {
         ReadSource::is_empty(self)
     }
+
+    fn position(&self) -> Position {
+        ReadSource::position(self)
+    }
 }
 
-struct Delimited<'a, T> {
-    inner: &'a mut T,
+struct Delimited<'d, T> {
+    inner: &'d mut T,
     delim: &'static str,
+    display: &'static str,
     is_ended: bool,
 }
 
-impl<'a, T: PrivParseSource> Delimited<'a, T> {
-    fn new(inner: &'a mut T, delim: &'static str) -> Self {
+impl<'d, T> Delimited<'d, T> {
+    fn new<E: Punctuation>(inner: &'d mut T) -> Self {
         Self {
             inner,
-            delim,
+            delim: E::PUNCT,
+            display: E::display(),
             is_ended: false,
         }
     }
 }
 
-impl<'a, T: PrivParseSource> PrivParseSource for Delimited<'a, T> {
+impl<'a, T: PrivParseSource<'a>> PrivParseSource<'a> for Delimited<'_, T> {
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()> {
+        self.inner.declare_encoding(label)
+    }
+
+    fn has_bom(&self) -> bool {
+        self.inner.has_bom()
+    }
+
     fn opt_parse_punct<'p>(&mut self, punct: &'p str) -> Result<Option<&'p str>> {
         self.inner.opt_parse_punct(punct)
     }
 
-    fn opt_parse_lit<'l>(
+    fn opt_parse_lit(
+        &mut self,
+        rule: impl FnMut(char) -> bool,
+        skip: Skip,
+    ) -> Result<Option<Cow<'a, str>>> {
+        self.inner.opt_parse_lit(rule, skip)
+    }
+
+    fn parse_lit_until<E: Punctuation>(
         &mut self,
         rule: impl FnMut(char) -> bool,
-        delim: Option<&str>,
-    ) -> Result<Option<Cow<'l, str>>> {
-        self.inner.opt_parse_lit(rule, delim)
+        skip: Skip,
+    ) -> Result<Cow<'a, str>> {
+        self.inner.parse_lit_until::<E>(rule, skip)
     }
 
-    fn default_opt_parse<P: Parse>(&mut self) -> Result<Option<P>> {
+    fn default_opt_parse<P: Parse<'a>>(&mut self) -> Result<Option<P>> {
         self.inner.default_opt_parse()
     }
 }
 
-impl<'a, T: ParseSource> ParseSource for Delimited<'a, T> {
+impl<'a, T: ParseSource<'a>> ParseSource<'a> for Delimited<'_, T> {
     fn is_empty(&mut self) -> Result<bool> {
-        self.is_ended |= self.inner.is_empty()?;
-        self.is_ended |= self.inner.opt_parse_punct(self.delim)?.is_some();
-
+        // Past the delimiter, the source may well have nothing left yet.
+        if !self.is_ended {
+            if self.inner.is_empty()? {
+                return Err(SyntaxError::UnclosedDelimiter(self.display).into());
+            }
+            self.is_ended = self.inner.opt_parse_punct(self.delim)?.is_some();
+        }
         Ok(self.is_ended)
     }
 
-    fn delimited<D: Delimiter>(&mut self) -> Result<impl ParseSource> {
+    fn position(&self) -> Position {
+        self.inner.position()
+    }
+
+    fn delimited<D: Delimiter>(&mut self) -> Result<impl ParseSource<'a>> {
         self.inner.delimited::<D>()
     }
 }
@@ -1,11 +1,11 @@
-mod rules;
+pub(crate) mod rules;
 
 use super::ParseSource;
 use crate::error::Result;
 use crate::parse::Parse;
 use std::borrow::Cow;
 
-pub trait Token: Parse {
+pub trait Token {
     fn display() -> &'static str;
 }
 
@@ -25,19 +25,27 @@ pub trait Literal: Token {
     }
 }
 
-pub fn opt_parse_punct<'p>(
-    input: &mut impl ParseSource,
+pub fn opt_parse_punct<'a, 'p>(
+    input: &mut impl ParseSource<'a>,
     punct: &'p str,
 ) -> Result<Option<&'p str>> {
     input.opt_parse_punct(punct)
 }
 
-pub fn opt_parse_lit<'l>(
-    input: &mut impl ParseSource,
+pub fn opt_parse_lit<'a>(
+    input: &mut impl ParseSource<'a>,
     rule: impl FnMut(char) -> bool,
-    delim: Option<&str>,
-) -> Result<Option<Cow<'l, str>>> {
-    input.opt_parse_lit(rule, delim)
+) -> Result<Option<Cow<'a, str>>> {
+    input.opt_parse_lit(rule, rules::Skip::Nothing)
+}
+
+/// Parses a literal up to and including the punctuation `E`, failing if the
+/// input ends before it.
+pub fn parse_lit_until<'a, E: Punctuation>(
+    input: &mut impl ParseSource<'a>,
+    rule: impl FnMut(char) -> bool,
+) -> Result<Cow<'a, str>> {
+    input.parse_lit_until::<E>(rule, rules::Skip::Nothing)
 }
 
 #[macro_export]
@@ -56,8 +64,8 @@ macro_rules! define_punctuation {
             const PUNCT: &'static str = $punct;
         }
 
-        impl $crate::parse::Parse for $name {
-            fn parse(input: &mut impl $crate::parse::ParseSource) -> $crate::error::Result<Self> {
+        impl<'a> $crate::parse::Parse<'a> for $name {
+            fn parse(input: &mut impl $crate::parse::ParseSource<'a>) -> $crate::error::Result<Self> {
                 use  $crate::token::Token;
 
                 Self::opt_parse(input)?.ok_or_else(
@@ -65,7 +73,7 @@ macro_rules! define_punctuation {
                 )
             }
 
-            fn opt_parse(input: &mut impl $crate::parse::ParseSource) -> $crate::error::Result<Option<Self>> {
+            fn opt_parse(input: &mut impl $crate::parse::ParseSource<'a>) -> $crate::error::Result<Option<Self>> {
                 use  $crate::token::Punctuation;
 
                 $crate::token::opt_parse_punct(input, $name::PUNCT).map(|r| r.map(|_| Self))
@@ -91,7 +99,25 @@ macro_rules! define_delimiters {
 
 #[macro_export]
 macro_rules! define_literals {
-    ($($name:ident by { $rule:expr } $( in $( $delim:ident )|+ )?),+ $(,)?) => {$(
+    (@parse $input:ident, $rule:expr, $skip:expr, in $( $delim:ident )|+) => {
+        $( if $input.opt_parse_punct($delim::PUNCT)?.is_some() {
+            $input.parse_lit_until::<<$delim as Delimiter>::End>($rule, $skip).map(Some)
+        } else )+ {
+            Ok(None)
+        }
+    };
+    (@parse $input:ident, $rule:expr, $skip:expr, until $end:ident) => {
+        $input.parse_lit_until::<<$end as Delimiter>::End>($rule, $skip).map(Some)
+    };
+    (@parse $input:ident, $rule:expr, $skip:expr,) => {
+        $input.opt_parse_lit($rule, $skip)
+    };
+    ($(
+        $name:ident by { $rule:expr }
+        $( skipping { $skip:expr } )?
+        $( in $( $delim:ident )|+ )?
+        $( until $end:ident )?
+    ),+ $(,)?) => {$(
         #[derive(PartialEq, Clone, Debug)]
         pub struct $name<'a>(std::borrow::Cow<'a, str>);
 
@@ -99,6 +125,10 @@ macro_rules! define_literals {
             pub fn new<T: Into<Cow<'a, str>>>(value: T) -> Self {
                 Self(value.into())
             }
+
+            pub fn into_value(self) -> Cow<'a, str> {
+                self.0
+            }
         }
 
         impl $crate::token::Token for $name<'_> {
@@ -113,8 +143,8 @@ macro_rules! define_literals {
             }
         }
 
-        impl $crate::token::Parse for $name<'_> {
-            fn parse(input: &mut impl $crate::parse::ParseSource) -> $crate::error::Result<Self> {
+        impl<'a> $crate::token::Parse<'a> for $name<'a> {
+            fn parse(input: &mut impl $crate::parse::ParseSource<'a>) -> $crate::error::Result<Self> {
                 use  $crate::token::Token;
 
                 Self::opt_parse(input)?.ok_or_else(
@@ -122,15 +152,14 @@ macro_rules! define_literals {
                 )
             }
 
-            fn opt_parse(input: &mut impl $crate::parse::ParseSource) -> $crate::error::Result<Option<Self>> {
-                let delim = None $( .or($(if input.opt_parse_punct($delim::PUNCT)?.is_some() {
-                        Some(<$delim as Delimiter>::End::PUNCT)
-                    } )else+ else {
-                        return Ok(None)
-                    })
-                )?;
+            fn opt_parse(input: &mut impl $crate::parse::ParseSource<'a>) -> $crate::error::Result<Option<Self>> {
+                let skip = None $( .or(Some($skip)) )?;
+                let skip = skip.unwrap_or($crate::token::rules::Skip::Nothing);
 
-                $crate::token::opt_parse_lit(input, $rule, delim).map(|r| r.map(|lit| Self(lit)))
+                let lit: $crate::error::Result<Option<Cow<'a, str>>> = $crate::define_literals!(
+                    @parse input, $rule, skip, $( in $( $delim )|+ )? $( until $end )?
+                );
+                lit.map(|r| r.map(|lit| Self(lit)))
             }
         }
     )+};
@@ -139,6 +168,12 @@ macro_rules! define_literals {
 define_punctuation! {
     Eq "=",
     Colon ":",
+    Percent "%",
+    Pipe "|",
+    Comma ",",
+    Question "?",
+    Star "*",
+    Plus "+",
 }
 
 define_delimiters! {
@@ -147,22 +182,60 @@ define_delimiters! {
     Comm "<!--" .. "-->",
     STag "<" .. ">",
     ETag "</" .. ">",
-    CData "<![CDATA[" .. "]]>",
+    EmptyElemTag "<" .. "/>",
+    CDSect "<![CDATA[" .. "]]>",
     Reference "&" .. ";",
+    PeReference "%" .. ";",
+    DocTypeDecl "<!DOCTYPE" .. ">",
+    ElementDecl "<!ELEMENT" .. ">",
+    AttlistDecl "<!ATTLIST" .. ">",
+    EntityDecl "<!ENTITY" .. ">",
+    NotationDecl "<!NOTATION" .. ">",
+    Subset "[" .. "]",
+    Group "(" .. ")",
     SQuote "'" .. "'",
     DQuote "\"" .. "\"",
 }
 
 define_literals! {
     S by { rules::accept_as_white_space },
-    Comment by { rules::accept_as_comment() } in Comm,
+    Comment by { rules::accept_as_comment() } skipping { rules::COMMENT_SKIP } in Comm,
+    CData by { rules::accept_as_char } skipping { rules::CHAR_SKIP } in CDSect,
+    PiData by { rules::accept_as_char } skipping { rules::CHAR_SKIP } until Pi,
     Name by { rules::accept_as_name() },
-    AttValue by { rules::accept_as_att_value } in DQuote | SQuote,
-    Text by { rules::accept_as_char_data() },
+    Nmtoken by { rules::accept_as_nmtoken },
+    AttValue by { rules::accept_as_att_value } skipping { rules::ATT_VALUE_SKIP } in DQuote | SQuote,
+    Text by { rules::accept_as_char_data() } skipping { rules::CHAR_DATA_SKIP },
+    SystemLiteral by { rules::accept_as_char } skipping { rules::CHAR_SKIP } in DQuote | SQuote,
+    PubidLiteral by { rules::accept_as_pubid_char } in DQuote | SQuote,
+    EntityValue by { rules::accept_as_char } skipping { rules::CHAR_SKIP } in DQuote | SQuote,
+    IntSubset by { rules::accept_as_int_subset() },
+}
+
+impl Name<'_> {
+    /// The part of a qualified name before its first `:`, if any.
+    pub fn prefix(&self) -> Option<&str> {
+        self.value()
+            .split_once(Colon::PUNCT)
+            .map(|(prefix, _)| prefix)
+    }
+
+    /// The part of a qualified name after its first `:`, or the whole name.
+    pub fn local_name(&self) -> &str {
+        self.value()
+            .split_once(Colon::PUNCT)
+            .map_or(self.value(), |(_, local_name)| local_name)
+    }
 }
 
 #[macro_export]
 macro_rules! Token {
     [=] => { $crate::token::Eq };
     [:] => { $crate::token::Colon };
+    [%] => { $crate::token::Percent };
+    [|] => { $crate::token::Pipe };
+    [,] => { $crate::token::Comma };
+    [?] => { $crate::token::Question };
+    [*] => { $crate::token::Star };
+    [+] => { $crate::token::Plus };
 }
@@ -1,4 +1,34 @@
-pub(super) fn accept_as_char(ch: char) -> bool {
+/// What `read_while` may skip over without asking a rule about each char.
+///
+/// Once the rule accepted a char which isn't one of the stop bytes, it must
+/// be as it was before its first char, and accept every char up to the next
+/// stop byte or delimiter.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum Skip {
+    Nothing,
+    /// Any chars up to one of these ASCII bytes.
+    Until(&'static [u8]),
+    /// XML chars up to one of these ASCII bytes.
+    CharsUntil(&'static [u8]),
+}
+
+impl Skip {
+    pub(crate) fn stops_at(self, ch: char) -> bool {
+        match self {
+            Self::Nothing => true,
+            Self::Until(stops) | Self::CharsUntil(stops) => {
+                ch.is_ascii() && stops.contains(&(ch as u8))
+            }
+        }
+    }
+}
+
+pub(crate) const CHAR_SKIP: Skip = Skip::CharsUntil(b"");
+pub(crate) const ATT_VALUE_SKIP: Skip = Skip::Until(b"<");
+pub(crate) const COMMENT_SKIP: Skip = Skip::CharsUntil(b"-");
+pub(crate) const CHAR_DATA_SKIP: Skip = Skip::Until(b"<]");
+
+pub(crate) fn accept_as_char(ch: char) -> bool {
     matches!(ch,
         | '\u{9}'
         | '\u{A}'
@@ -9,7 +39,7 @@ pub(super) fn accept_as_char(ch: char) -> bool {
     )
 }
 
-pub(super) fn accept_as_white_space(ch: char) -> bool {
+pub(crate) fn accept_as_white_space(ch: char) -> bool {
     matches!(ch, '\u{20}' | '\u{9}' | '\u{D}' | '\u{A}')
 }
 
@@ -46,11 +76,28 @@ fn accept_as_name_char(ch: char) -> bool {
         )
 }
 
-pub(super) fn accept_as_att_value(ch: char) -> bool {
-    !matches!(ch, '<' | '&')
+pub(crate) fn accept_as_nmtoken(ch: char) -> bool {
+    accept_as_name_char(ch)
+}
+
+pub(crate) fn accept_as_pubid_char(ch: char) -> bool {
+    matches!(ch,
+        | '\u{20}'
+        | '\u{D}'
+        | '\u{A}'
+        | 'a'..='z'
+        | 'A'..='Z'
+        | '0'..='9'
+        | '-' | '\'' | '(' | ')' | '+' | ',' | '.' | '/' | ':'
+        | '=' | '?' | ';' | '!' | '*' | '#' | '@' | '$' | '_' | '%'
+    )
+}
+
+pub(crate) fn accept_as_att_value(ch: char) -> bool {
+    ch != '<'
 }
 
-pub(super) fn accept_as_name() -> impl FnMut(char) -> bool {
+pub(crate) fn accept_as_name() -> impl FnMut(char) -> bool {
     let mut is_start_char = true;
 
     move |ch| {
@@ -63,7 +110,7 @@ pub(super) fn accept_as_name() -> impl FnMut(char) -> bool {
     }
 }
 
-pub(super) fn accept_as_comment() -> impl FnMut(char) -> bool {
+pub(crate) fn accept_as_comment() -> impl FnMut(char) -> bool {
     let mut previous_was_a_hyphen = false;
 
     move |ch| {
@@ -82,7 +129,7 @@ pub(super) fn accept_as_comment() -> impl FnMut(char) -> bool {
 
 const CDATA_CLOSE_DELIM: &str = "]]>";
 
-pub(super) fn accept_as_char_data() -> impl FnMut(char) -> bool {
+pub(crate) fn accept_as_char_data() -> impl FnMut(char) -> bool {
     let delim_len = CDATA_CLOSE_DELIM.len();
     let mut matched_bytes_count = 0;
 
@@ -93,6 +140,45 @@ pub(super) fn accept_as_char_data() -> impl FnMut(char) -> bool {
             matched_bytes_count = 0;
         }
 
-        matched_bytes_count < delim_len && !matches!(ch, '<' | '&')
+        matched_bytes_count < delim_len && ch != '<'
+    }
+}
+
+pub(crate) fn accept_as_int_subset() -> impl FnMut(char) -> bool {
+    enum Context {
+        Markup,
+        Quoted(char),
+        Comment,
+        Pi,
+    }
+    let mut context = Context::Markup;
+    let mut recent = [' '; 3];
+
+    // `]` closes the subset only outside of literals, comments and PIs.
+    move |ch| {
+        let previous = recent;
+        recent = [previous[1], previous[2], ch];
+
+        match context {
+            Context::Markup => match ch {
+                ']' => return false,
+                '"' | '\'' => context = Context::Quoted(ch),
+                '-' if previous == ['<', '!', '-'] => {
+                    context = Context::Comment;
+                    recent = [' '; 3];
+                }
+                '?' if previous[2] == '<' => {
+                    context = Context::Pi;
+                    recent = [' '; 3];
+                }
+                _ => {}
+            },
+            Context::Quoted(quote) if ch == quote => context = Context::Markup,
+            Context::Comment if recent == ['-', '-', '>'] => context = Context::Markup,
+            Context::Pi if recent[1..] == ['?', '>'] => context = Context::Markup,
+            _ => {}
+        }
+
+        accept_as_char(ch)
     }
 }
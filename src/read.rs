@@ -1,33 +1,169 @@
-use crate::encoding::DecBuffer;
-use crate::error::Result;
+use crate::encoding::{self, DecBuffer};
+use crate::error::{Error, Result};
+use crate::token::rules::Skip;
+use encoding_rs::UTF_8;
+use memchr::{memchr, memchr2, memchr3, memrchr2};
 use std::borrow::Cow;
-use std::io::BufRead;
-use std::{char, str};
+use std::io::{BufRead, ErrorKind};
+use std::{char, fmt, str};
 
-pub(crate) trait ReadSource {
+pub(crate) trait ReadSource<'a> {
     fn _encoding(&mut self) -> &str;
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()>;
+    /// Tells whether the source started with a byte order mark, once its
+    /// first bytes were read.
+    fn has_bom(&self) -> bool;
     fn is_empty(&mut self) -> Result<bool>;
 
     fn pos(&self) -> usize;
+    fn position(&self) -> Position;
     fn go_back(&mut self, n: usize) -> bool;
 
     fn skip_next(&mut self, slice: &str) -> Result<bool>;
 
-    fn read_while<'a>(
+    /// Reads the chars `predicate` accepts up to `delim`, which is consumed
+    /// but not returned, and tells where it stopped.
+    fn read_while(
         &mut self,
         predicate: impl FnMut(char) -> bool,
+        skip: Skip,
         delim: &str,
-    ) -> Result<Cow<'a, str>>;
+    ) -> Result<(Cow<'a, str>, Stop)>;
 }
 
+/// Where `read_while` stopped reading.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum Stop {
+    /// At the delimiter, which was consumed.
+    Delim,
+    /// At a char the predicate rejected, which is left unread.
+    Rejected(char),
+    /// At the end of the source.
+    End,
+}
+
+/// Returns how many bytes at the start of `text` `read_while` may take
+/// without asking its rule, as `skip` allows, before `delim` could start.
+fn skip_run(text: &str, skip: Skip, delim: &str) -> usize {
+    let (stops, only_chars) = match skip {
+        Skip::Nothing => return 0,
+        Skip::Until(stops) => (stops, false),
+        Skip::CharsUntil(stops) => (stops, true),
+    };
+    let mut needles = [0; 3];
+    let mut len = 0;
+
+    for &byte in stops.iter().chain(delim.as_bytes().first()) {
+        if !needles[..len].contains(&byte) {
+            let Some(needle) = needles.get_mut(len) else {
+                return 0;
+            };
+            *needle = byte;
+            len += 1;
+        }
+    }
+    let bytes = text.as_bytes();
+    let end = match needles[..len] {
+        [a] => memchr(a, bytes),
+        [a, b] => memchr2(a, b, bytes),
+        [a, b, c] => memchr3(a, b, c, bytes),
+        _ => None,
+    }
+    .unwrap_or(bytes.len());
+
+    if !only_chars {
+        return end;
+    }
+    // The chars which aren't XML chars are control chars, and U+FFFE and
+    // U+FFFF, which start with 0xEF like the rest of U+F000..=U+FFFF.
+    bytes[..end]
+        .iter()
+        .position(|&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r')) || b == 0xEF)
+        .unwrap_or(end)
+}
+
+/// A location in the source. `Position::default()` is all zeros and stands
+/// for an unknown location.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub struct Position {
+    /// Line number, starting at 1.
+    pub line: u64,
+    /// Column within the line in chars, starting at 1.
+    pub column: u64,
+    /// Column within the line in bytes of the source's original encoding,
+    /// starting at 1.
+    pub byte_column: u64,
+    /// Offset from the start of the source in bytes of its original encoding.
+    pub offset: u64,
+}
+
+impl Position {
+    fn start() -> Self {
+        Self {
+            line: 1,
+            column: 1,
+            byte_column: 1,
+            offset: 0,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct ReaderState {
     pos: usize,
     skipped: usize,
+    position: Position,
+    after_cr: bool,
 }
 
 impl ReaderState {
     fn new() -> Self {
-        Self { pos: 0, skipped: 0 }
+        Self {
+            pos: 0,
+            skipped: 0,
+            position: Position::start(),
+            after_cr: false,
+        }
+    }
+
+    /// Moves past `consumed`, `source_len` telling how many bytes a text
+    /// took up in the source.
+    fn advance(&mut self, consumed: &str, source_len: impl Fn(&str) -> usize) {
+        let position = &mut self.position;
+        let len = source_len(consumed) as u64;
+        position.offset += len;
+
+        match memrchr2(b'\n', b'\r', consumed.as_bytes()) {
+            Some(i) => position.byte_column = source_len(&consumed[i + 1..]) as u64 + 1,
+            None => position.byte_column += len,
+        }
+
+        let mut rest = consumed;
+
+        while let Some(i) = memchr2(b'\n', b'\r', rest.as_bytes()) {
+            if i > 0 {
+                position.column += rest[..i].chars().count() as u64;
+                self.after_cr = false;
+            }
+            let is_cr = rest.as_bytes()[i] == b'\r';
+
+            if is_cr || !self.after_cr {
+                position.line += 1;
+                position.column = 1;
+            }
+            self.after_cr = is_cr;
+            rest = &rest[i + 1..];
+        }
+        if !rest.is_empty() {
+            position.column += rest.chars().count() as u64;
+            self.after_cr = false;
+        }
     }
 }
 
@@ -41,6 +177,7 @@ pub struct SourceReader<T> {
     reader: T,
     state: ReaderState,
     buf: DecBuffer,
+    raw_line_endings: bool,
 }
 
 impl<T> SourceReader<T> {
@@ -49,41 +186,102 @@ impl<T> SourceReader<T> {
             reader,
             state: ReaderState::new(),
             buf: DecBuffer::new(enc),
+            raw_line_endings: false,
         }
     }
+
+    /// Creates a reader which detects the encoding from the byte order mark
+    /// or the XML declaration, as described in XML 1.0 Appendix F.
+    pub fn from_reader(reader: T) -> Self {
+        Self {
+            reader,
+            state: ReaderState::new(),
+            buf: DecBuffer::detect(),
+            raw_line_endings: false,
+        }
+    }
+
+    /// Replaces malformed byte sequences in the source with U+FFFD instead
+    /// of failing with `Error::Malformed`.
+    pub fn lenient(mut self) -> Self {
+        self.buf.set_lenient(true);
+        self
+    }
+
+    /// Keeps `\r\n` and lone `\r` in literals as written instead of
+    /// normalizing them to `\n`.
+    pub fn raw_line_endings(mut self) -> Self {
+        self.raw_line_endings = true;
+        self
+    }
+
+    /// Returns the name of the encoding the source is decoded from, as far
+    /// as it is known yet.
+    pub fn encoding(&self) -> &str {
+        self.buf.encoding()
+    }
 }
 
 impl<T: BufRead> SourceReader<T> {
     fn buf(&mut self) -> Result<&str> {
-        if self.buf.is_empty() {
+        if self.buf.len() <= self.state.skipped {
             return self.fill_buf();
         }
         Ok(&self.buf[self.state.skipped..])
     }
 
     fn fill_buf(&mut self) -> Result<&str> {
-        self.advance(0);
+        self.state.position.offset += self.buf.sniff(&mut self.reader)? as u64;
+
+        self.buf.fill(&mut self.reader)?;
 
-        if self.reader.fill_buf()?.is_empty() {
-            return Ok("");
+        match self.buf.malformed() {
+            // Only fail once everything before the malformed sequence is read.
+            Some(offset) if self.buf.len() == self.state.skipped => {
+                Err(Error::Malformed { offset })
+            }
+            _ => Ok(&self.buf[self.state.skipped..]),
         }
-        Ok(self.buf.fill(&mut self.reader)?)
+    }
+
+    /// Fills the buffer until it holds at least `len` bytes past the
+    /// skipped text or the source ends.
+    fn fill_to(&mut self, len: usize) -> Result<&str> {
+        while self.buf.len() < self.state.skipped + len {
+            let before = self.buf.len() - self.state.skipped;
+
+            if self.fill_buf()?.len() == before {
+                break;
+            }
+        }
+        Ok(&self.buf[self.state.skipped..])
     }
 
     fn advance(&mut self, n: usize) {
         let n = self.state.skipped + n;
+        let consumed = &self.buf[..n];
 
+        self.state
+            .advance(consumed, |text| self.buf.source_len(text));
         self.state.pos += n;
         self.state.skipped = 0;
         self.buf.consume(n);
     }
 }
 
-impl<T: BufRead> ReadSource for SourceReader<T> {
+impl<'a, T: BufRead> ReadSource<'a> for SourceReader<T> {
     fn _encoding(&mut self) -> &str {
         self.buf.encoding()
     }
 
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()> {
+        self.buf.declare(label)
+    }
+
+    fn has_bom(&self) -> bool {
+        self.buf.has_bom()
+    }
+
     fn is_empty(&mut self) -> Result<bool> {
         Ok(self.buf()?.is_empty())
     }
@@ -92,6 +290,14 @@ impl<T: BufRead> ReadSource for SourceReader<T> {
         self.state.pos + self.state.skipped
     }
 
+    fn position(&self) -> Position {
+        let mut state = self.state;
+        let skipped = &self.buf[..self.state.skipped];
+
+        state.advance(skipped, |text| self.buf.source_len(text));
+        state.position
+    }
+
     fn go_back(&mut self, n: usize) -> bool {
         if n <= self.state.skipped {
             self.state.skipped -= n;
@@ -101,22 +307,24 @@ impl<T: BufRead> ReadSource for SourceReader<T> {
     }
 
     fn skip_next(&mut self, slice: &str) -> Result<bool> {
-        if self.buf()?.starts_with(slice) {
+        if self.fill_to(slice.len())?.starts_with(slice) {
             self.state.skipped += slice.len();
             return Ok(true);
         }
         Ok(false)
     }
 
-    fn read_while<'r>(
+    fn read_while(
         &mut self,
         mut predicate: impl FnMut(char) -> bool,
+        skip: Skip,
         delim: &str,
-    ) -> Result<Cow<'r, str>> {
+    ) -> Result<(Cow<'a, str>, Stop)> {
         let mut buf = self.buf()?;
         let mut result = String::new();
         let mut delim_reached = false;
-        let mut check_failed = false;
+        let mut rejected = None;
+        let mut at_eof = false;
 
         loop {
             if buf.is_empty() {
@@ -125,42 +333,37 @@ impl<T: BufRead> ReadSource for SourceReader<T> {
             let mut total_read = 0;
             let mut too_small = false;
 
-            if delim.is_empty() {
-                buf.char_indices()
-                    .map(|(pos, ch)| {
-                        total_read = pos;
-                        ch
-                    })
-                    .take_while(|&ch| {
-                        check_failed = !predicate(ch);
-                        !check_failed
-                    })
-                    .for_each(|_| ());
-            } else {
-                buf.char_indices()
-                    .map(|(pos, ch)| {
-                        total_read = pos;
-                        (&buf[pos..], ch)
-                    })
-                    .map_while(|(buf, ch)| {
-                        too_small = buf.len() < delim.len();
-                        delim_reached = buf.starts_with(delim);
-                        (!too_small && !delim_reached).then_some(ch)
-                    })
-                    .take_while(|&ch| {
-                        check_failed = !predicate(ch);
-                        !check_failed
-                    })
-                    .for_each(|_| ());
-            };
+            while let Some(ch) = buf[total_read..].chars().next() {
+                if !delim.is_empty() {
+                    let tail = &buf[total_read..];
+                    too_small = !at_eof && tail.len() < delim.len();
+                    delim_reached = tail.starts_with(delim);
+
+                    if too_small || delim_reached {
+                        break;
+                    }
+                }
+                if !predicate(ch) {
+                    rejected = Some(ch);
+                    break;
+                }
+                total_read += ch.len_utf8();
+
+                if !skip.stops_at(ch) {
+                    total_read += skip_run(&buf[total_read..], skip, delim);
+                }
+            }
+
+            if too_small || delim_reached || rejected.is_some() {
+                let rest = buf.len() - total_read;
 
-            if too_small || delim_reached || check_failed {
                 if total_read > 0 {
                     result.push_str(&buf[..total_read]);
                     self.advance(total_read);
                 }
                 if too_small {
                     buf = self.fill_buf()?;
+                    at_eof = buf.len() == rest;
                     continue;
                 }
                 if delim_reached {
@@ -175,6 +378,511 @@ impl<T: BufRead> ReadSource for SourceReader<T> {
             buf = self.fill_buf()?;
         }
 
-        Ok(Cow::Owned(result))
+        let stop = match rejected {
+            Some(ch) => Stop::Rejected(ch),
+            None if delim_reached => Stop::Delim,
+            None => Stop::End,
+        };
+        // A `\r\n` split across refills is whole again in `result`.
+        if !self.raw_line_endings && result.contains('\r') {
+            result = result.replace("\r\n", "\n").replace('\r', "\n");
+        }
+        Ok((Cow::Owned(result), stop))
+    }
+}
+
+/// Reads a document held in memory, borrowing its tokens instead of
+/// copying them while it is UTF-8.
+///
+/// A document detected or declared to be in another encoding is decoded as
+/// `SourceReader` does, from where that turns out, and its tokens are owned.
+pub struct SliceReader<'a> {
+    src: &'a [u8],
+    /// The valid UTF-8 at the start of `src`, after any byte order mark.
+    text: &'a str,
+    /// How many bytes of `src` come before `text`.
+    bom_len: usize,
+    state: ReaderState,
+    /// Reads what is left of `src` once it turns out not to be UTF-8.
+    decoded: Option<SourceReader<&'a [u8]>>,
+    raw_line_endings: bool,
+}
+
+impl<'a> SliceReader<'a> {
+    /// Creates a reader which detects the encoding as
+    /// `SourceReader::from_reader` does.
+    pub fn new(src: &'a [u8]) -> Self {
+        let mut reader = Self {
+            src,
+            text: "",
+            bom_len: 0,
+            state: ReaderState::new(),
+            decoded: None,
+            raw_line_endings: false,
+        };
+
+        match src {
+            [0xFF, 0xFE, ..]
+            | [0xFE, 0xFF, ..]
+            | [0x3C, 0x00, 0x3F, 0x00, ..]
+            | [0x00, 0x3C, 0x00, 0x3F, ..] => {
+                reader.decoded = Some(SourceReader::from_reader(src));
+                return reader;
+            }
+            [0xEF, 0xBB, 0xBF, ..] => reader.bom_len = 3,
+            _ => {}
+        }
+        reader.state.position.offset = reader.bom_len as u64;
+        reader.text = match str::from_utf8(&src[reader.bom_len..]) {
+            Ok(text) => text,
+            Err(err) => {
+                let valid = &src[reader.bom_len..reader.bom_len + err.valid_up_to()];
+                // SAFETY: `valid_up_to` is where the valid UTF-8 ends.
+                unsafe { str::from_utf8_unchecked(valid) }
+            }
+        };
+        reader
+    }
+
+    /// Replaces malformed byte sequences in the source with U+FFFD instead
+    /// of failing with `Error::Malformed`.
+    pub fn lenient(mut self) -> Self {
+        if self.decoded.is_none() && self.bom_len + self.text.len() < self.src.len() {
+            self.decoded = Some(SourceReader::from_reader(self.src));
+        }
+        self.decoded = self.decoded.map(SourceReader::lenient);
+        self
+    }
+
+    /// Keeps `\r\n` and lone `\r` in literals as written instead of
+    /// normalizing them to `\n`.
+    pub fn raw_line_endings(mut self) -> Self {
+        self.raw_line_endings = true;
+        self.decoded = self.decoded.map(SourceReader::raw_line_endings);
+        self
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.state.pos + self.state.skipped..]
+    }
+
+    fn advance(&mut self, n: usize) {
+        let n = self.state.skipped + n;
+        let consumed = &self.text[self.state.pos..self.state.pos + n];
+
+        self.state.advance(consumed, str::len);
+        self.state.pos += n;
+        self.state.skipped = 0;
+    }
+
+    /// Fails if the text ends before the source does.
+    fn check_end(&self) -> Result<()> {
+        let end = self.bom_len + self.text.len();
+
+        if end < self.src.len() {
+            return Err(Error::Malformed { offset: end as u64 });
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ReadSource<'a> for SliceReader<'a> {
+    fn _encoding(&mut self) -> &str {
+        match &mut self.decoded {
+            Some(decoded) => decoded._encoding(),
+            None => "UTF-8",
+        }
+    }
+
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()> {
+        if let Some(decoded) = &mut self.decoded {
+            return ReadSource::declare_encoding(decoded, label);
+        }
+        let Some(label) = label else {
+            return Ok(());
+        };
+        let declared = encoding::declared_encoding(UTF_8, self.bom_len > 0, label)?;
+
+        if declared != UTF_8 {
+            let mut state = self.state;
+            let skipped = &self.text[state.pos..state.pos + state.skipped];
+            state.advance(skipped, str::len);
+            state.pos = 0;
+            state.skipped = 0;
+
+            let rest = &self.src[self.bom_len + self.state.pos + self.state.skipped..];
+            let mut decoded = SourceReader::new(rest, declared.name());
+            decoded.state = state;
+            decoded.raw_line_endings = self.raw_line_endings;
+
+            self.decoded = Some(decoded);
+        }
+        Ok(())
+    }
+
+    fn has_bom(&self) -> bool {
+        self.bom_len > 0 || self.decoded.as_ref().is_some_and(ReadSource::has_bom)
+    }
+
+    fn is_empty(&mut self) -> Result<bool> {
+        if let Some(decoded) = &mut self.decoded {
+            return ReadSource::is_empty(decoded);
+        }
+        if !self.rest().is_empty() {
+            return Ok(false);
+        }
+        self.check_end()?;
+        Ok(true)
+    }
+
+    fn pos(&self) -> usize {
+        match &self.decoded {
+            Some(decoded) => decoded.pos(),
+            None => self.state.pos + self.state.skipped,
+        }
+    }
+
+    fn position(&self) -> Position {
+        if let Some(decoded) = &self.decoded {
+            return ReadSource::position(decoded);
+        }
+        let mut state = self.state;
+        let skipped = &self.text[state.pos..state.pos + state.skipped];
+
+        state.advance(skipped, str::len);
+        state.position
+    }
+
+    fn go_back(&mut self, n: usize) -> bool {
+        if let Some(decoded) = &mut self.decoded {
+            return decoded.go_back(n);
+        }
+        if n <= self.state.skipped {
+            self.state.skipped -= n;
+            return true;
+        }
+        false
+    }
+
+    fn skip_next(&mut self, slice: &str) -> Result<bool> {
+        if let Some(decoded) = &mut self.decoded {
+            return decoded.skip_next(slice);
+        }
+        let rest = self.rest();
+
+        if rest.is_empty() {
+            self.check_end()?;
+        }
+        if rest.starts_with(slice) {
+            self.state.skipped += slice.len();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn read_while(
+        &mut self,
+        mut predicate: impl FnMut(char) -> bool,
+        skip: Skip,
+        delim: &str,
+    ) -> Result<(Cow<'a, str>, Stop)> {
+        if let Some(decoded) = &mut self.decoded {
+            return decoded.read_while(predicate, skip, delim);
+        }
+        let rest = self.rest();
+        let mut end = None;
+        let mut delim_len = 0;
+        let mut stop = Stop::End;
+        let mut pos = 0;
+
+        while let Some(ch) = rest[pos..].chars().next() {
+            if !delim.is_empty() && rest[pos..].starts_with(delim) {
+                end = Some(pos);
+                delim_len = delim.len();
+                stop = Stop::Delim;
+                break;
+            }
+            if !predicate(ch) {
+                end = Some(pos);
+                stop = Stop::Rejected(ch);
+                break;
+            }
+            pos += ch.len_utf8();
+
+            if !skip.stops_at(ch) {
+                pos += skip_run(&rest[pos..], skip, delim);
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                self.check_end()?;
+                rest.len()
+            }
+        };
+        let token = &rest[..end];
+
+        // Reading nothing keeps what `skip_next` skipped open to `go_back`.
+        if end + delim_len > 0 {
+            self.advance(end + delim_len);
+        }
+
+        if !self.raw_line_endings && token.contains('\r') {
+            let token = token.replace("\r\n", "\n").replace('\r', "\n");
+            return Ok((Cow::Owned(token), stop));
+        }
+        Ok((Cow::Borrowed(token), stop))
+    }
+}
+
+/// A source fed with chunks of bytes as they arrive instead of reading them
+/// from a `BufRead`.
+///
+/// Running out of what was fed before `close` fails with
+/// `ErrorKind::WouldBlock`. Everything since the last `checkpoint` is kept,
+/// so that `rewind` can go back to it and parse again once more was fed.
+pub(crate) struct ChunkSource {
+    /// Bytes fed but not decoded yet.
+    input: Vec<u8>,
+    buf: DecBuffer,
+    /// The text decoded since the last checkpoint.
+    text: String,
+    pos: usize,
+    /// The state at the start of `text`.
+    state: ReaderState,
+    is_closed: bool,
+    resume: Option<Resume>,
+    raw_line_endings: bool,
+}
+
+/// Where a `read_while` which ran out of text goes on from once more was
+/// fed, instead of asking its rule about the same chars again.
+struct Resume {
+    start: usize,
+    skip: Skip,
+    delim: String,
+    /// Right after a char the rule accepted which isn't a stop byte, where
+    /// the rule is as it was at `start`.
+    at: usize,
+}
+
+impl ChunkSource {
+    /// Creates a source which detects the encoding from the byte order mark
+    /// or the XML declaration, like `SourceReader::from_reader`.
+    pub fn new() -> Self {
+        Self {
+            input: Vec::new(),
+            buf: DecBuffer::detect(),
+            text: String::new(),
+            pos: 0,
+            state: ReaderState::new(),
+            is_closed: false,
+            resume: None,
+            raw_line_endings: false,
+        }
+    }
+
+    /// Keeps `\r\n` and lone `\r` in literals as written instead of
+    /// normalizing them to `\n`.
+    pub fn set_raw_line_endings(&mut self, raw: bool) {
+        self.raw_line_endings = raw;
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<()> {
+        self.input.extend_from_slice(bytes);
+        self.decode()
+    }
+
+    /// Marks the end of the source, after which running out is final.
+    pub fn close(&mut self) -> Result<()> {
+        self.is_closed = true;
+        self.decode()
+    }
+
+    /// Forgets the text before the current position.
+    pub fn checkpoint(&mut self) {
+        let consumed = &self.text[..self.pos];
+
+        if self.pos > 0 {
+            self.state
+                .advance(consumed, |text| self.buf.source_len(text));
+            self.text.drain(..self.pos);
+            self.pos = 0;
+            self.resume = None;
+        }
+    }
+
+    /// Goes back to the last checkpoint.
+    pub fn rewind(&mut self) {
+        self.pos = 0;
+    }
+
+    fn decode(&mut self) -> Result<()> {
+        if self.buf.is_sniffing() {
+            // Enough for a byte order mark and `<?xml` in UTF-16.
+            if self.input.len() < 12 && !self.is_closed {
+                return Ok(());
+            }
+            let mut input = &self.input[..];
+            self.state.position.offset += self.buf.sniff(&mut input)? as u64;
+            self.input.drain(..self.input.len() - input.len());
+        }
+
+        loop {
+            let mut input = &self.input[..];
+            let decoded = if self.is_closed {
+                self.buf.fill(&mut input)?
+            } else {
+                self.buf.fill_available(&mut input)?
+            };
+            let len = decoded.len();
+            self.text.push_str(decoded);
+            self.buf.consume(len);
+
+            let read = self.input.len() - input.len();
+            self.input.drain(..read);
+
+            if len == 0 && read == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Fails unless running out of text is the end of the source.
+    fn check_end(&self) -> Result<()> {
+        if let Some(offset) = self.buf.malformed() {
+            return Err(Error::Malformed { offset });
+        }
+        if !self.is_closed {
+            return Err(Error::Io(ErrorKind::WouldBlock));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ReadSource<'a> for ChunkSource {
+    fn _encoding(&mut self) -> &str {
+        self.buf.encoding()
+    }
+
+    fn declare_encoding(&mut self, label: Option<&str>) -> Result<()> {
+        self.buf.declare(label)?;
+        // What follows the XML declaration waited for its encoding.
+        self.decode()
+    }
+
+    fn has_bom(&self) -> bool {
+        self.buf.has_bom()
+    }
+
+    fn is_empty(&mut self) -> Result<bool> {
+        if self.pos < self.text.len() {
+            return Ok(false);
+        }
+        self.check_end()?;
+        Ok(true)
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn position(&self) -> Position {
+        let mut state = self.state;
+        let consumed = &self.text[..self.pos];
+
+        state.advance(consumed, |text| self.buf.source_len(text));
+        state.position
+    }
+
+    fn go_back(&mut self, n: usize) -> bool {
+        if n <= self.pos {
+            self.pos -= n;
+            return true;
+        }
+        false
+    }
+
+    fn skip_next(&mut self, slice: &str) -> Result<bool> {
+        let rest = &self.text[self.pos..];
+
+        if rest.len() < slice.len() && slice.starts_with(rest) {
+            self.check_end()?;
+        }
+        if rest.starts_with(slice) {
+            self.pos += slice.len();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn read_while(
+        &mut self,
+        mut predicate: impl FnMut(char) -> bool,
+        skip: Skip,
+        delim: &str,
+    ) -> Result<(Cow<'a, str>, Stop)> {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let mut end = None;
+        let mut delim_len = 0;
+        let mut stop = Stop::End;
+
+        let mut pos = match &self.resume {
+            Some(resume) if (resume.start, resume.skip, &*resume.delim) == (start, skip, delim) => {
+                resume.at - start
+            }
+            _ => 0,
+        };
+        let mut resume_at = pos;
+
+        while let Some(ch) = rest[pos..].chars().next() {
+            if !delim.is_empty() {
+                let tail = &rest[pos..];
+
+                if tail.len() < delim.len() && !self.is_closed {
+                    break;
+                }
+                if tail.starts_with(delim) {
+                    end = Some(pos);
+                    delim_len = delim.len();
+                    stop = Stop::Delim;
+                    break;
+                }
+            }
+            if !predicate(ch) {
+                end = Some(pos);
+                stop = Stop::Rejected(ch);
+                break;
+            }
+            pos += ch.len_utf8();
+
+            if !skip.stops_at(ch) {
+                pos += skip_run(&rest[pos..], skip, delim);
+                resume_at = pos;
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                if let Err(err) = self.check_end() {
+                    self.resume = Some(Resume {
+                        start,
+                        skip,
+                        delim: delim.to_owned(),
+                        at: start + resume_at,
+                    });
+                    return Err(err);
+                }
+                rest.len()
+            }
+        };
+        let mut result = rest[..end].to_owned();
+        self.pos += end + delim_len;
+
+        if !self.raw_line_endings && result.contains('\r') {
+            result = result.replace("\r\n", "\n").replace('\r', "\n");
+        }
+        Ok((Cow::Owned(result), stop))
     }
 }
@@ -1,33 +1,174 @@
-use std::str;
+#[cfg(feature = "tokio")]
+pub mod async_reader;
+mod att_value;
+pub mod dtd;
+mod entity;
+pub mod ns;
+pub mod path;
+pub mod push;
+
+pub use entity::EntityLimits;
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::error::{Result, SyntaxError};
+use crate::escape;
+use crate::parse::{Parse, ParseSource, Parser, SliceParser};
+use crate::token::rules::{accept_as_att_value, Skip, ATT_VALUE_SKIP};
+use crate::token::{
+    self, AttValue, CData, Comment, Literal, Name, PiData, Punctuation, Text, Token, S,
+};
+use crate::{Position, Token};
+use att_value::AttTypes;
+use dtd::DocTypeDecl;
+use entity::Entities;
+
+/// The quotes around a literal.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum Quote {
+    #[default]
+    Double,
+    Single,
+}
+
+impl Quote {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Double => token::DQuote::PUNCT,
+            Self::Single => token::SQuote::PUNCT,
+        }
+    }
+}
+
+/// How the markup of an event was spelled, beyond what the event itself
+/// keeps, so that `EventWriter::write_spelled` can write it back unchanged.
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct Layout {
+    /// The white space within the markup in the order it appeared, with an
+    /// empty string wherever optional white space was left out.
+    pub spaces: Vec<String>,
+    /// The quotes of the literals within the markup, in order.
+    pub quotes: Vec<Quote>,
+    /// The event is the first of a document starting with a byte order mark.
+    pub bom: bool,
+}
+
+impl Layout {
+    fn parse_s<'a>(&mut self, input: &mut impl ParseSource<'a>) -> Result<()> {
+        let s = input.parse::<S>()?;
+        self.spaces.push(s.into_value().into_owned());
+
+        Ok(())
+    }
+
+    fn opt_parse_s<'a>(&mut self, input: &mut impl ParseSource<'a>) -> Result<bool> {
+        let s = input.opt_parse::<S>()?;
+        let is_some = s.is_some();
+        self.spaces
+            .push(s.map(|s| s.into_value().into_owned()).unwrap_or_default());
+
+        Ok(is_some)
+    }
+
+    /// Parses a literal in either quotes, as the `define_literals!` ones
+    /// `in DQuote | SQuote` do.
+    fn parse_quoted<'a>(
+        &mut self,
+        input: &mut impl ParseSource<'a>,
+        rule: impl FnMut(char) -> bool,
+        skip: Skip,
+        display: &'static str,
+    ) -> Result<Cow<'a, str>> {
+        let quote = if token::opt_parse_punct(input, token::DQuote::PUNCT)?.is_some() {
+            Quote::Double
+        } else if token::opt_parse_punct(input, token::SQuote::PUNCT)?.is_some() {
+            Quote::Single
+        } else {
+            return Err(SyntaxError::MismatchedToken(display).into());
+        };
+        self.quotes.push(quote);
+
+        match quote {
+            Quote::Double => input.parse_lit_until::<token::end_delim::DQuote>(rule, skip),
+            Quote::Single => input.parse_lit_until::<token::end_delim::SQuote>(rule, skip),
+        }
+    }
+
+    fn opt_parse_spelled<'a, P: ParseSpelled<'a>>(
+        &mut self,
+        input: &mut impl ParseSource<'a>,
+    ) -> Result<Option<P>> {
+        let Some(spelled) = input.opt_parse::<Spelled<P>>()? else {
+            return Ok(None);
+        };
+        self.spaces.extend(spelled.layout.spaces);
+        self.quotes.extend(spelled.layout.quotes);
+
+        Ok(Some(spelled.value))
+    }
+}
+
+/// Parsing that records the spelling of what it parses in a `Layout`.
+trait ParseSpelled<'a>: Sized {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self>;
+}
+
+struct Spelled<P> {
+    value: P,
+    layout: Layout,
+}
 
-use crate::error::Result;
-use crate::parse::Parser;
-use crate::parse::{Parse, ParseSource};
-use crate::token::{self, AttValue, Comment, Name, Text, S};
-use crate::Token;
+impl<'a, P: ParseSpelled<'a>> Parse<'a> for Spelled<P> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let mut layout = Layout::default();
+        let value = P::parse_spelled(input, &mut layout)?;
+
+        Ok(Self { value, layout })
+    }
+}
 
 #[derive(PartialEq, Debug)]
 struct Eq;
 
-impl Parse for Eq {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
-        input.opt_parse::<S>()?;
+impl<'a> ParseSpelled<'a> for Eq {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
+        layout.opt_parse_s(input)?;
         input.parse::<Token![=]>()?;
-        input.opt_parse::<S>()?;
+        layout.opt_parse_s(input)?;
 
         Ok(Self)
     }
 }
 
 #[derive(PartialEq, Debug)]
-pub struct Attribute<'a>(Name<'a>, AttValue<'a>);
+pub struct Attribute<'a> {
+    pub name: Name<'a>,
+    pub value: AttValue<'a>,
+}
 
-impl<'a> Parse for Attribute<'a> {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
+impl<'a> ParseSpelled<'a> for Attribute<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
         let name = input.parse::<Name>()?;
-        input.parse::<Eq>()?;
+        Eq::parse_spelled(input, layout)?;
+        let value = layout.parse_quoted(
+            input,
+            accept_as_att_value,
+            ATT_VALUE_SKIP,
+            AttValue::display(),
+        )?;
 
-        Ok(Self(name, input.parse::<AttValue>()?))
+        Ok(Self {
+            name,
+            value: AttValue::new(value),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for Attribute<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
     }
 }
 
@@ -46,32 +187,39 @@ mod xml_decl_token {
     }
 }
 
-impl<'a> Parse for XmlDecl<'a> {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
+impl<'a> XmlDecl<'a> {
+    /// Parses the `Eq`, the value and the white space after it of a
+    /// pseudo-attribute.
+    fn parse_value(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<AttValue<'a>> {
+        Eq::parse_spelled(input, layout)?;
+        let value = layout.parse_quoted(
+            input,
+            accept_as_att_value,
+            ATT_VALUE_SKIP,
+            AttValue::display(),
+        )?;
+        layout.opt_parse_s(input)?;
+
+        Ok(AttValue::new(value))
+    }
+}
+
+impl<'a> ParseSpelled<'a> for XmlDecl<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
         let mut content = input.delimited::<token::XmlDecl>()?;
-        content.parse::<S>()?;
+        layout.parse_s(&mut content)?;
 
-        let version = {
-            content.parse::<xml_decl_token::Ver>()?;
-            content.parse::<Eq>()?;
-            content.parse::<AttValue>()?
-        };
-        content.opt_parse::<S>()?;
+        content.parse::<xml_decl_token::Ver>()?;
+        let version = Self::parse_value(&mut content, layout)?;
 
         let encoding = if content.opt_parse::<xml_decl_token::Enc>()?.is_some() {
-            content.parse::<Eq>()?;
-            let value = content.parse::<AttValue>()?;
-            content.opt_parse::<S>()?;
-            Some(value)
+            Some(Self::parse_value(&mut content, layout)?)
         } else {
             None
         };
 
         let standalone = if content.opt_parse::<xml_decl_token::StAl>()?.is_some() {
-            content.parse::<Eq>()?;
-            let value = content.parse::<AttValue>()?;
-            content.opt_parse::<S>()?;
-            Some(value)
+            Some(Self::parse_value(&mut content, layout)?)
         } else {
             None
         };
@@ -84,24 +232,77 @@ impl<'a> Parse for XmlDecl<'a> {
     }
 }
 
+impl<'a> Parse<'a> for XmlDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Pi<'a> {
-    target: Name<'a>,
+    pub target: Name<'a>,
+    pub data: Option<PiData<'a>>,
 }
 
-impl Parse for Pi<'_> {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
+impl Pi<'_> {
+    /// Parses `data` as the pseudo-attributes used by `<?xml-stylesheet?>`
+    /// and similar instructions, e.g. `href="style.css" type="text/css"`.
+    pub fn pseudo_attributes(&self) -> Result<Vec<Attribute<'static>>> {
+        let data = self.data.as_ref().map_or("", |data| data.value());
+        let mut input = Parser::new(data.as_bytes(), "UTF-8");
+        let mut attrs = Vec::new();
+
+        input.opt_parse::<S>()?;
+
+        while !input.is_empty()? {
+            let Attribute { name, value } = input.parse::<Attribute>()?;
+            let value = AttValue::new(escape::unescape(value.value())?.into_owned());
+
+            attrs.push(Attribute { name, value });
+
+            if !input.is_empty()? {
+                input.parse::<S>()?;
+            }
+        }
+
+        Ok(attrs)
+    }
+}
+
+impl<'a> ParseSpelled<'a> for Pi<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
         let mut content = input.delimited::<token::Pi>()?;
         let target = content.parse::<Name>()?;
 
-        Ok(Self { target })
+        if target.value().eq_ignore_ascii_case("xml") {
+            return Err(SyntaxError::ReservedPiTarget(target.value().to_owned()).into());
+        }
+
+        let data = if layout.opt_parse_s(&mut content)? {
+            content.opt_parse::<PiData>()?
+        } else if content.is_empty()? {
+            None
+        } else {
+            return Err(SyntaxError::MismatchedToken(S::display()).into());
+        };
+
+        Ok(Self { target, data })
+    }
+}
+
+impl<'a> Parse<'a> for Pi<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
     }
 }
 
-fn try_parse_misc<'a>(input: &mut impl ParseSource) -> Result<Option<XmlEvent<'a>>> {
+fn try_parse_misc<'a>(
+    input: &mut impl ParseSource<'a>,
+    layout: &mut Layout,
+) -> Result<Option<XmlEvent<'a>>> {
     if let Some(s) = input.opt_parse::<S>()? {
         Ok(Some(XmlEvent::S(s)))
-    } else if let Some(pi) = input.opt_parse::<Pi>()? {
+    } else if let Some(pi) = layout.opt_parse_spelled::<Pi>(input)? {
         Ok(Some(XmlEvent::Pi(pi)))
     } else if let Some(comm) = input.opt_parse::<Comment>()? {
         Ok(Some(XmlEvent::Comment(comm)))
@@ -116,21 +317,19 @@ pub struct StartTag<'a> {
     pub attrs: Vec<Attribute<'a>>,
 }
 
-impl<'a> Parse for StartTag<'a> {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
-        let mut content = input.delimited::<token::STag>()?;
-        let name = content.parse::<Name>()?;
-        let mut attrs = Vec::new();
-
-        while !content.is_empty()? {
-            content.parse::<S>()?;
+impl<'a> StartTag<'a> {
+    /// Returns the value of the attribute called `name`.
+    pub fn attr(&self, name: &str) -> Option<&AttValue<'a>> {
+        find_attr(&self.attrs, name)
+    }
+}
 
-            if let Some(att) = content.opt_parse::<Attribute>()? {
-                attrs.push(att);
-            }
+impl<'a> Parse<'a> for StartTag<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        match input.parse::<ElemTag>()? {
+            ElemTag::Start(s_tag) => Ok(s_tag),
+            ElemTag::Empty(_) => Err(SyntaxError::MismatchedToken(token::STag::display()).into()),
         }
-
-        Ok(Self { name, attrs })
     }
 }
 
@@ -139,129 +338,539 @@ pub struct EndTag<'a> {
     pub name: Name<'a>,
 }
 
-impl<'a> Parse for EndTag<'a> {
-    fn parse(input: &mut impl ParseSource) -> Result<Self> {
+impl<'a> ParseSpelled<'a> for EndTag<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
         let mut content = input.delimited::<token::ETag>()?;
         let name = content.parse::<Name>()?;
-        content.is_empty()?;
+        layout.opt_parse_s(&mut content)?;
 
+        if !content.is_empty()? {
+            return Err(SyntaxError::MismatchedToken(token::end_delim::ETag::display()).into());
+        }
         Ok(Self { name })
     }
 }
 
+impl<'a> Parse<'a> for EndTag<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct EmptyElem<'a> {
-    pub name: &'a str,
+    pub name: Name<'a>,
+    pub attrs: Vec<Attribute<'a>>,
+}
+
+impl<'a> EmptyElem<'a> {
+    /// Returns the value of the attribute called `name`.
+    pub fn attr(&self, name: &str) -> Option<&AttValue<'a>> {
+        find_attr(&self.attrs, name)
+    }
+}
+
+/// Appends `event` to `events`, merging it into the text before it.
+fn push_event<'a>(events: &mut Vec<XmlEvent<'a>>, event: XmlEvent<'a>) {
+    match (events.last_mut(), event) {
+        (Some(XmlEvent::Text(last)), XmlEvent::Text(text)) => {
+            let mut merged = mem::replace(last, Text::new("")).into_value().into_owned();
+            merged.push_str(text.value());
+            *last = Text::new(merged);
+        }
+        (_, event) => events.push(event),
+    }
+}
+
+/// Splits `raw` into the text before `start` and the text from `end`.
+fn split_around(raw: Cow<'_, str>, start: usize, end: usize) -> (Cow<'_, str>, Cow<'_, str>) {
+    match raw {
+        Cow::Borrowed(raw) => (Cow::Borrowed(&raw[..start]), Cow::Borrowed(&raw[end..])),
+        Cow::Owned(raw) => (
+            Cow::Owned(raw[..start].to_owned()),
+            Cow::Owned(raw[end..].to_owned()),
+        ),
+    }
+}
+
+fn find_attr<'v, 'a>(attrs: &'v [Attribute<'a>], name: &str) -> Option<&'v AttValue<'a>> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.is(name))
+        .map(|attr| &attr.value)
+}
+
+impl<'a> Parse<'a> for EmptyElem<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        match input.parse::<ElemTag>()? {
+            ElemTag::Empty(empty_elem) => Ok(empty_elem),
+            ElemTag::Start(_) => {
+                Err(SyntaxError::MismatchedToken(token::EmptyElemTag::display()).into())
+            }
+        }
+    }
 }
 
-impl<'a> Parse for EmptyElem<'a> {
-    fn parse(_input: &mut impl ParseSource) -> Result<Self> {
-        todo!()
+/// `STag` and `EmptyElemTag` share everything up to the closing delimiter,
+/// so both are parsed in one pass.
+enum ElemTag<'a> {
+    Start(StartTag<'a>),
+    Empty(EmptyElem<'a>),
+}
+
+impl<'a> ParseSpelled<'a> for ElemTag<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
+        let mut content = input.delimited::<token::STag>()?;
+        let name = content.parse::<Name>()?;
+        let mut attrs = Vec::new();
+
+        loop {
+            let is_after_s = layout.opt_parse_s(&mut content)?;
+
+            if content.is_empty()? {
+                return Ok(Self::Start(StartTag { name, attrs }));
+            }
+            if content
+                .opt_parse::<token::end_delim::EmptyElemTag>()?
+                .is_some()
+            {
+                return Ok(Self::Empty(EmptyElem { name, attrs }));
+            }
+            if !is_after_s {
+                return Err(SyntaxError::MismatchedToken(S::display()).into());
+            }
+            let attr = Attribute::parse_spelled(&mut content, layout)?;
+
+            if find_attr(&attrs, attr.name.value()).is_some() {
+                return Err(SyntaxError::DuplicateAttribute(attr.name.value().to_owned()).into());
+            }
+            attrs.push(attr);
+        }
+    }
+}
+
+impl<'a> Parse<'a> for ElemTag<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
     }
 }
 
 #[derive(PartialEq, Debug)]
 pub enum XmlEvent<'a> {
     Xml(XmlDecl<'a>),
+    DocType(DocTypeDecl<'a>),
     Pi(Pi<'a>),
     STag(StartTag<'a>),
     ETag(EndTag<'a>),
     EmptyElem(EmptyElem<'a>),
     Text(Text<'a>),
-    CData,
+    CData(CData<'a>),
     S(S<'a>),
     Comment(Comment<'a>),
     Eof,
 }
 
+#[derive(Clone, Copy)]
 pub enum State {
     Start,
     AfterXml,
+    AfterDocType,
     InElem,
     AfterText,
     AfterRoot,
     Eof,
 }
 
+#[derive(Clone, Default, Debug)]
+pub struct ReaderConfig {
+    /// Report every empty element as a `STag` immediately followed by an `ETag`,
+    /// so `<br/>` reads exactly like `<br></br>`.
+    pub expand_empty_elements: bool,
+    /// Report the content of CDATA sections as character data, merged with
+    /// any `Text` around it, instead of as separate `CData` events.
+    pub merge_cdata: bool,
+    /// Leave entity and character references in `Text` and `AttValue`
+    /// exactly as written instead of decoding them.
+    pub raw_references: bool,
+    /// Keep the declarations of the internal subset of the document type
+    /// declaration in `DocTypeDecl::markup_decls`. The subset is parsed, and
+    /// its errors reported, either way, for the entities and attribute
+    /// types it declares.
+    pub parse_internal_subset: bool,
+    pub entity_limits: EntityLimits,
+}
+
 pub struct EventReader<'a, T> {
     src: T,
     st: State,
     path: Vec<Name<'a>>,
+    config: ReaderConfig,
+    pending_e_tag: Option<Name<'a>>,
+    /// Events parsed ahead along with the text before them.
+    queued: VecDeque<XmlEvent<'a>>,
+    start: Position,
+    entities: Entities,
+    att_types: AttTypes,
+    layout: Layout,
+    /// How much of the document was read when the entity whose replacement
+    /// text this reader parses was referenced.
+    entity_ref_offset: Option<u64>,
 }
 
 impl<'a, T> EventReader<'a, T> {
     pub fn new(src: T) -> Self {
+        Self::with_config(src, ReaderConfig::default())
+    }
+
+    pub fn with_config(src: T, config: ReaderConfig) -> Self {
         EventReader {
             src,
             st: State::Start,
             path: Vec::new(),
+            config,
+            pending_e_tag: None,
+            queued: VecDeque::new(),
+            start: Position::default(),
+            entities: Entities::default(),
+            att_types: AttTypes::default(),
+            layout: Layout::default(),
+            entity_ref_offset: None,
         }
     }
+
+    pub fn config(&self) -> &ReaderConfig {
+        &self.config
+    }
+
+    /// Returns where the event last returned by `next_event` started.
+    pub fn position(&self) -> Position {
+        self.start
+    }
+
+    /// Returns how the markup of the event last returned by `next_event`
+    /// was spelled.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Returns the names of the elements left open by the event last
+    /// returned by `next_event`, outermost first.
+    pub fn path(&self) -> &[Name<'a>] {
+        &self.path
+    }
 }
 
-impl<'a, T: ParseSource> EventReader<'a, T> {
-    pub fn next_event(&mut self) -> Result<XmlEvent> {
+impl<'a, T: ParseSource<'a>> EventReader<'a, T> {
+    fn unescape<'v>(&mut self, raw: Cow<'v, str>, in_att_value: bool) -> Result<Cow<'v, str>> {
+        if self.entities.is_empty() || !raw.contains('&') {
+            return Ok(match escape::unescape(&raw)? {
+                Cow::Owned(value) => Cow::Owned(value),
+                Cow::Borrowed(_) => raw,
+            });
+        }
+        let doc_len = self.doc_len();
+
+        Ok(Cow::Owned(self.entities.expand(
+            &raw,
+            in_att_value,
+            doc_len,
+            &self.config.entity_limits,
+        )?))
+    }
+
+    fn doc_len(&self) -> u64 {
+        self.entity_ref_offset
+            .unwrap_or_else(|| self.src.position().offset)
+    }
+
+    fn unescape_text(&mut self, raw: Cow<'a, str>) -> Result<Cow<'a, str>> {
+        if self.config.raw_references {
+            return Ok(raw);
+        }
+        self.unescape(raw, false)
+    }
+
+    /// Decodes and normalizes attribute values as described in section 3.3.3.
+    fn unescape_attrs(&mut self, elem: &Name, attrs: &mut [Attribute<'a>]) -> Result<()> {
+        if self.config.raw_references {
+            return Ok(());
+        }
+        for attr in attrs {
+            let raw = mem::replace(&mut attr.value, AttValue::new("")).into_value();
+            let raw = match att_value::normalize_white_space(&raw) {
+                Cow::Owned(normalized) => Cow::Owned(normalized),
+                Cow::Borrowed(_) => raw,
+            };
+            let mut value = self.unescape(raw, true)?;
+
+            if self.att_types.is_tokenized(elem.value(), attr.name.value()) {
+                value = Cow::Owned(att_value::collapse_spaces(&value));
+            }
+            attr.value = AttValue::new(value);
+        }
+        Ok(())
+    }
+
+    fn tag_event(&mut self, tag: ElemTag<'a>) -> Result<XmlEvent<'a>> {
+        match tag {
+            ElemTag::Start(mut s_tag) => {
+                self.unescape_attrs(&s_tag.name, &mut s_tag.attrs)?;
+                self.path.push(s_tag.name.clone());
+
+                Ok(XmlEvent::STag(s_tag))
+            }
+            ElemTag::Empty(mut empty_elem) => {
+                self.unescape_attrs(&empty_elem.name, &mut empty_elem.attrs)?;
+
+                if self.path.is_empty() && self.entity_ref_offset.is_none() {
+                    self.st = State::AfterRoot;
+                }
+                Ok(self.empty_elem_event(empty_elem))
+            }
+        }
+    }
+
+    fn empty_elem_event(&mut self, empty_elem: EmptyElem<'a>) -> XmlEvent<'a> {
+        if self.config.expand_empty_elements {
+            self.path.push(empty_elem.name.clone());
+            self.pending_e_tag = Some(empty_elem.name.clone());
+
+            XmlEvent::STag(StartTag {
+                name: empty_elem.name,
+                attrs: empty_elem.attrs,
+            })
+        } else {
+            XmlEvent::EmptyElem(empty_elem)
+        }
+    }
+
+    /// Parses character data into `queued`, along with the events of the
+    /// entities it references whose replacement text holds markup.
+    fn parse_text(&mut self) -> Result<()> {
+        let mut events = Vec::new();
+
+        loop {
+            if let Some(text) = self.src.opt_parse::<Text>()? {
+                self.push_text(text.into_value(), &mut events)?;
+            } else if !self.config.merge_cdata {
+                break;
+            } else if let Some(cdata) = self.src.opt_parse::<CData>()? {
+                push_event(&mut events, XmlEvent::Text(Text::new(cdata.into_value())));
+            } else {
+                break;
+            }
+            if !self.config.merge_cdata {
+                break;
+            }
+        }
+        if self.config.merge_cdata {
+            events
+                .retain(|event| !matches!(event, XmlEvent::Text(text) if text.value().is_empty()));
+        }
+        self.queued.extend(events);
+
+        Ok(())
+    }
+
+    /// Decodes `raw` into `events`, parsing the replacement text of the
+    /// entities it references as content where it holds markup.
+    fn push_text(&mut self, mut raw: Cow<'a, str>, events: &mut Vec<XmlEvent<'a>>) -> Result<()> {
+        while !self.config.raw_references {
+            let Some((start, name, replacement)) = self.entities.find_markup_ref(&raw) else {
+                break;
+            };
+            let name = name.to_owned();
+            let (before, after) = split_around(raw, start, start + name.len() + 2);
+
+            if !before.is_empty() {
+                let before = self.unescape(before, false)?;
+                push_event(events, XmlEvent::Text(Text::new(before)));
+            }
+            for event in self.parse_entity(&name, &replacement)? {
+                push_event(events, event);
+            }
+            if after.is_empty() {
+                return Ok(());
+            }
+            raw = after;
+        }
+        let text = self.unescape_text(raw)?;
+        push_event(events, XmlEvent::Text(Text::new(text)));
+
+        Ok(())
+    }
+
+    /// Parses the replacement text of the entity `name` as content, as
+    /// section 4.4.2 includes it where it is referenced.
+    fn parse_entity(&mut self, name: &str, replacement: &str) -> Result<Vec<XmlEvent<'a>>> {
+        let doc_len = self.doc_len();
+        self.entities
+            .enter(name, replacement.len(), doc_len, &self.config.entity_limits)?;
+
+        // Line endings were normalized where the entity was declared.
+        let src = Parser::new(replacement.as_bytes(), "UTF-8").raw_line_endings();
+        let mut reader = EventReader::<'static, _>::with_config(src, self.config.clone());
+        reader.st = State::InElem;
+        reader.entity_ref_offset = Some(doc_len);
+        reader.entities = mem::take(&mut self.entities);
+        reader.att_types = mem::take(&mut self.att_types);
+
+        let mut events = Vec::new();
+        let result = loop {
+            match reader.read_event() {
+                Ok(XmlEvent::Eof) => break Ok(()),
+                Ok(event) => events.push(event),
+                Err(err) => break Err(err),
+            }
+        };
+        self.entities = reader.entities;
+        self.att_types = reader.att_types;
+        self.entities.leave();
+
+        result.map(|()| events)
+    }
+
+    fn opt_parse_doctype(&mut self) -> Result<Option<DocTypeDecl<'a>>> {
+        if matches!(self.st, State::AfterDocType) {
+            return Ok(None);
+        }
+        let Some(mut doctype) = self
+            .layout
+            .opt_parse_spelled::<DocTypeDecl>(&mut self.src)?
+        else {
+            return Ok(None);
+        };
+
+        // Parsed whatever `parse_internal_subset` says, for the declarations
+        // the reader itself needs.
+        let markup_decls = doctype.parse_internal_subset()?;
+        self.entities.declare(&markup_decls)?;
+        self.att_types.declare(&markup_decls);
+
+        if self.config.parse_internal_subset {
+            doctype.markup_decls = markup_decls;
+        }
+        Ok(Some(doctype))
+    }
+
+    pub fn next_event(&mut self) -> Result<XmlEvent<'a>> {
+        self.read_event().map_err(|err| err.at(self.src.position()))
+    }
+
+    fn read_event(&mut self) -> Result<XmlEvent<'a>> {
+        if let Some(name) = self.pending_e_tag.take() {
+            self.path.pop();
+            return Ok(XmlEvent::ETag(EndTag { name }));
+        }
+        if let Some(event) = self.queued.pop_front() {
+            match &event {
+                XmlEvent::STag(s_tag) => self.path.push(s_tag.name.clone()),
+                XmlEvent::ETag(_) => {
+                    self.path.pop();
+                }
+                _ => {}
+            }
+            return Ok(event);
+        }
+        self.start = self.src.position();
+        self.layout = Layout::default();
+
         match self.st {
             State::Start => {
                 self.st = State::AfterXml;
+                let xml_decl = self.layout.opt_parse_spelled::<XmlDecl>(&mut self.src)?;
 
-                if let Some(xml_decl) = self.src.opt_parse::<XmlDecl>()? {
-                    Ok(XmlEvent::Xml(xml_decl))
+                let encoding = xml_decl.as_ref().and_then(|decl| decl.encoding.as_ref());
+                self.src
+                    .declare_encoding(encoding.map(|encoding| encoding.value()))?;
+                let has_bom = self.src.has_bom();
+
+                let event = if let Some(xml_decl) = xml_decl {
+                    XmlEvent::Xml(xml_decl)
                 } else {
-                    self.next_event()
-                }
+                    self.read_event()?
+                };
+                self.layout.bom = has_bom;
+
+                Ok(event)
             }
-            State::AfterXml => {
-                if let Some(misc) = try_parse_misc(&mut self.src)? {
+            State::AfterXml | State::AfterDocType => {
+                if let Some(misc) = try_parse_misc(&mut self.src, &mut self.layout)? {
                     Ok(misc)
-                } else if let Some(s_tag) = self.src.opt_parse::<StartTag>()? {
+                } else if let Some(doctype) = self.opt_parse_doctype()? {
+                    self.st = State::AfterDocType;
+                    Ok(XmlEvent::DocType(doctype))
+                } else if let Some(tag) = self.layout.opt_parse_spelled::<ElemTag>(&mut self.src)? {
                     self.st = State::InElem;
-                    self.path.push(s_tag.name.clone());
-                    Ok(XmlEvent::STag(s_tag))
+                    self.tag_event(tag)
+                } else if self.src.is_empty()? {
+                    Err(SyntaxError::MissingRootElement.into())
                 } else {
-                    todo!("error")
+                    Err(SyntaxError::ContentBeforeRoot.into())
                 }
             }
             State::InElem => {
                 self.st = State::AfterText;
-
-                if let Some(text) = self.src.opt_parse::<Text>()? {
-                    Ok(XmlEvent::Text(text))
-                } else {
-                    self.next_event()
-                }
+                self.parse_text()?;
+                self.read_event()
             }
             State::AfterText => {
                 self.st = State::InElem;
 
-                if let Some(s_tag) = self.src.opt_parse::<StartTag>()? {
-                    self.path.push(s_tag.name.clone());
-                    Ok(XmlEvent::STag(s_tag))
-                } else if let Some(e_tag) = self.src.opt_parse::<EndTag>()? {
-                    if self.path.pop().is_some_and(|t| t == e_tag.name) {
-                        if self.path.is_empty() {
-                            self.st = State::AfterRoot;
+                if let Some(tag) = self.layout.opt_parse_spelled::<ElemTag>(&mut self.src)? {
+                    self.tag_event(tag)
+                } else if let Some(e_tag) =
+                    self.layout.opt_parse_spelled::<EndTag>(&mut self.src)?
+                {
+                    match self.path.pop() {
+                        Some(name) if name == e_tag.name => {
+                            if self.path.is_empty() && self.entity_ref_offset.is_none() {
+                                self.st = State::AfterRoot;
+                            }
+                            Ok(XmlEvent::ETag(e_tag))
+                        }
+                        Some(name) => Err(SyntaxError::MismatchedEndTag {
+                            expected: name.value().to_owned(),
+                            found: e_tag.name.value().to_owned(),
+                        }
+                        .into()),
+                        // Only the replacement text of an entity runs out of
+                        // open elements before its end.
+                        None => {
+                            Err(SyntaxError::EndTagInEntity(e_tag.name.value().to_owned()).into())
                         }
-                        Ok(XmlEvent::ETag(e_tag))
-                    } else {
-                        todo!("error")
                     }
-                } else if let Some(pi) = self.src.opt_parse::<Pi>()? {
+                } else if let Some(pi) = self.layout.opt_parse_spelled::<Pi>(&mut self.src)? {
                     Ok(XmlEvent::Pi(pi))
                 } else if let Some(comment) = self.src.opt_parse::<Comment>()? {
                     Ok(XmlEvent::Comment(comment))
+                } else if let Some(cdata) = self.src.opt_parse::<CData>()? {
+                    Ok(XmlEvent::CData(cdata))
+                } else if self.src.is_empty()? {
+                    if self.path.is_empty() && self.entity_ref_offset.is_some() {
+                        self.st = State::Eof;
+                        return Ok(XmlEvent::Eof);
+                    }
+                    let name = self.path.last().map(|name| name.value().to_owned());
+                    Err(SyntaxError::UnclosedElement(name.unwrap_or_default()).into())
+                } else if self.src.opt_parse::<token::end_delim::STag>()?.is_some() {
+                    // `Text` stops right before the `>` of a `]]>` it ran into.
+                    Err(
+                        SyntaxError::UnexpectedDelimiter(token::end_delim::CDSect::display())
+                            .into(),
+                    )
                 } else {
-                    todo!("error")
+                    Err(SyntaxError::InvalidMarkup.into())
                 }
             }
             State::AfterRoot => {
                 if self.src.is_empty()? {
                     self.st = State::Eof;
                     Ok(XmlEvent::Eof)
-                } else if let Some(misc) = try_parse_misc(&mut self.src)? {
+                } else if let Some(misc) = try_parse_misc(&mut self.src, &mut self.layout)? {
                     Ok(misc)
                 } else {
-                    todo!("error")
+                    Err(SyntaxError::ContentAfterRoot.into())
                 }
             }
             State::Eof => Ok(XmlEvent::Eof),
@@ -269,8 +878,14 @@ impl<'a, T: ParseSource> EventReader<'a, T> {
     }
 }
 
-impl<'a> From<&'a [u8]> for EventReader<'a, Parser<&'a [u8]>> {
+impl<'a> From<&'a [u8]> for EventReader<'a, SliceParser<'a>> {
     fn from(src: &'a [u8]) -> Self {
-        EventReader::new(Parser::new(src, "UTF8"))
+        EventReader::new(SliceParser::new(src))
+    }
+}
+
+impl<'a> From<&'a str> for EventReader<'a, SliceParser<'a>> {
+    fn from(src: &'a str) -> Self {
+        EventReader::new(SliceParser::new(src.as_bytes()))
     }
 }
@@ -0,0 +1,83 @@
+use super::push::PushReader;
+use super::{Layout, ReaderConfig, XmlEvent};
+use crate::error::Result;
+use crate::token::Name;
+use crate::Position;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Reads the events of a document from a tokio `AsyncBufRead`, just like an
+/// `EventReader` reads them from a `BufRead`.
+///
+/// The encoding is detected as `Parser::from_reader` does. An event split
+/// across reads is parsed again from its start once more of it arrived.
+pub struct AsyncEventReader<R> {
+    reader: R,
+    events: PushReader,
+}
+
+impl<R> AsyncEventReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_config(reader, ReaderConfig::default())
+    }
+
+    pub fn with_config(reader: R, config: ReaderConfig) -> Self {
+        Self {
+            reader,
+            events: PushReader::with_config(config),
+        }
+    }
+
+    /// Keeps line endings as written, see `PushReader::raw_line_endings`.
+    pub fn raw_line_endings(mut self) -> Self {
+        self.events = self.events.raw_line_endings();
+        self
+    }
+
+    pub fn config(&self) -> &ReaderConfig {
+        self.events.config()
+    }
+
+    /// Returns where the event last returned by `next_event` started.
+    pub fn position(&self) -> Position {
+        self.events.position()
+    }
+
+    /// Returns how the markup of the event last returned by `next_event`
+    /// was spelled.
+    pub fn layout(&self) -> &Layout {
+        self.events.layout()
+    }
+
+    /// Returns the names of the elements left open by the event last
+    /// returned by `next_event`, outermost first.
+    pub fn path(&self) -> &[Name<'static>] {
+        self.events.path()
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncEventReader<R> {
+    pub async fn next_event(&mut self) -> Result<XmlEvent<'static>> {
+        loop {
+            if let Some(event) = self.events.next_event()? {
+                return Ok(event);
+            }
+            let bytes = self.reader.fill_buf().await?;
+
+            if bytes.is_empty() {
+                self.events.finish()?;
+            } else {
+                let len = bytes.len();
+                self.events.feed(bytes)?;
+                self.reader.consume(len);
+            }
+        }
+    }
+}
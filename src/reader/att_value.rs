@@ -0,0 +1,60 @@
+use super::dtd::{AttType, MarkupDecl};
+use crate::token::rules::accept_as_white_space;
+use crate::token::Literal;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Which attributes the internal subset declares with a tokenized type,
+/// i.e. any type but `CDATA`.
+#[derive(Default)]
+pub(crate) struct AttTypes {
+    tokenized: HashMap<String, HashMap<String, bool>>,
+}
+
+impl AttTypes {
+    pub fn declare(&mut self, decls: &[MarkupDecl]) {
+        for decl in decls {
+            let MarkupDecl::AttList(decl) = decl else {
+                continue;
+            };
+            let attrs = self
+                .tokenized
+                .entry(decl.name.value().to_owned())
+                .or_default();
+
+            // The first declaration of an attribute is binding.
+            for def in &decl.att_defs {
+                attrs
+                    .entry(def.name.value().to_owned())
+                    .or_insert(!matches!(def.att_type, AttType::CData));
+            }
+        }
+    }
+
+    pub fn is_tokenized(&self, elem: &str, attr: &str) -> bool {
+        self.tokenized
+            .get(elem)
+            .and_then(|attrs| attrs.get(attr))
+            .is_some_and(|&tokenized| tokenized)
+    }
+}
+
+/// Replaces every white space character with a space, as done to the
+/// literal text of attribute values and the entities referenced in them.
+pub(crate) fn normalize_white_space(raw: &str) -> Cow<'_, str> {
+    if raw.chars().any(|ch| ch != ' ' && accept_as_white_space(ch)) {
+        Cow::Owned(raw.replace(accept_as_white_space, " "))
+    } else {
+        Cow::Borrowed(raw)
+    }
+}
+
+/// Drops leading and trailing spaces and collapses runs of spaces, as done
+/// to the values of tokenized attributes.
+pub(crate) fn collapse_spaces(value: &str) -> String {
+    value
+        .split(' ')
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
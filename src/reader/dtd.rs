@@ -0,0 +1,565 @@
+use super::{Layout, ParseSpelled, Pi, Spelled};
+use crate::error::{Result, SyntaxError};
+use crate::parse::{Parse, ParseSource, Parser};
+use crate::token::rules::{accept_as_char, accept_as_pubid_char, Skip, CHAR_SKIP};
+use crate::token::{
+    self, AttValue, Comment, EntityValue, IntSubset, Literal, Name, Nmtoken, PubidLiteral,
+    SystemLiteral, Token, S,
+};
+use crate::Token;
+
+mod dtd_token {
+    crate::define_punctuation! {
+        System "SYSTEM",
+        Public "PUBLIC",
+        Empty "EMPTY",
+        Any "ANY",
+        PcData "#PCDATA",
+        CData "CDATA",
+        IdRefs "IDREFS",
+        IdRef "IDREF",
+        Id "ID",
+        Entities "ENTITIES",
+        Entity "ENTITY",
+        NmTokens "NMTOKENS",
+        NmToken "NMTOKEN",
+        Notation "NOTATION",
+        Required "#REQUIRED",
+        Implied "#IMPLIED",
+        Fixed "#FIXED",
+        NData "NDATA",
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct DocTypeDecl<'a> {
+    pub name: Name<'a>,
+    pub external_id: Option<ExternalId<'a>>,
+    pub internal_subset: Option<IntSubset<'a>>,
+    /// The declarations of `internal_subset`, only filled in when
+    /// `ReaderConfig::parse_internal_subset` is set.
+    pub markup_decls: Vec<MarkupDecl<'a>>,
+}
+
+impl DocTypeDecl<'_> {
+    pub fn parse_internal_subset(&self) -> Result<Vec<MarkupDecl<'static>>> {
+        let subset = self.internal_subset.as_ref().map_or("", |s| s.value());
+        let mut input = Parser::new(subset.as_bytes(), "UTF-8");
+        let mut decls = Vec::new();
+
+        loop {
+            input.opt_parse::<S>()?;
+
+            if input.is_empty()? {
+                return Ok(decls);
+            }
+            decls.push(input.parse::<MarkupDecl>()?);
+        }
+    }
+}
+
+impl<'a> ParseSpelled<'a> for DocTypeDecl<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
+        let mut content = input.delimited::<token::DocTypeDecl>()?;
+        layout.parse_s(&mut content)?;
+        let name = content.parse::<Name>()?;
+
+        let external_id = if layout.opt_parse_s(&mut content)? {
+            let external_id = layout.opt_parse_spelled::<ExternalId>(&mut content)?;
+
+            if external_id.is_some() {
+                layout.opt_parse_s(&mut content)?;
+            }
+            external_id
+        } else {
+            None
+        };
+
+        let internal_subset = if content.opt_parse::<token::Subset>()?.is_some() {
+            let subset = content.opt_parse::<IntSubset>()?;
+            content.parse::<token::end_delim::Subset>()?;
+            layout.opt_parse_s(&mut content)?;
+
+            Some(subset.unwrap_or_else(|| IntSubset::new("")))
+        } else {
+            None
+        };
+
+        if !content.is_empty()? {
+            return Err(
+                SyntaxError::MismatchedToken(token::end_delim::DocTypeDecl::display()).into(),
+            );
+        }
+
+        Ok(Self {
+            name,
+            external_id,
+            internal_subset,
+            markup_decls: Vec::new(),
+        })
+    }
+}
+
+impl<'a> Parse<'a> for DocTypeDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ExternalId<'a> {
+    System(SystemLiteral<'a>),
+    /// The system literal can only be missing in a `NotationDecl`.
+    Public(PubidLiteral<'a>, Option<SystemLiteral<'a>>),
+}
+
+impl<'a> ParseSpelled<'a> for ExternalId<'a> {
+    fn parse_spelled(input: &mut impl ParseSource<'a>, layout: &mut Layout) -> Result<Self> {
+        if input.opt_parse::<dtd_token::System>()?.is_some() {
+            layout.parse_s(input)?;
+            return Ok(Self::System(parse_system_literal(input, layout)?));
+        }
+        input.parse::<dtd_token::Public>()?;
+        layout.parse_s(input)?;
+        let pubid = layout.parse_quoted(
+            input,
+            accept_as_pubid_char,
+            Skip::Nothing,
+            PubidLiteral::display(),
+        )?;
+        layout.parse_s(input)?;
+
+        Ok(Self::Public(
+            PubidLiteral::new(pubid),
+            Some(parse_system_literal(input, layout)?),
+        ))
+    }
+}
+
+fn parse_system_literal<'a>(
+    input: &mut impl ParseSource<'a>,
+    layout: &mut Layout,
+) -> Result<SystemLiteral<'a>> {
+    let system = layout.parse_quoted(input, accept_as_char, CHAR_SKIP, SystemLiteral::display())?;
+
+    Ok(SystemLiteral::new(system))
+}
+
+impl<'a> Parse<'a> for ExternalId<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(input.parse::<Spelled<Self>>()?.value)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum MarkupDecl<'a> {
+    Element(ElementDecl<'a>),
+    AttList(AttListDecl<'a>),
+    Entity(EntityDecl<'a>),
+    Notation(NotationDecl<'a>),
+    Pi(Pi<'a>),
+    Comment(Comment<'a>),
+    PeReference(Name<'a>),
+}
+
+impl<'a> Parse<'a> for MarkupDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        if let Some(decl) = input.opt_parse::<ElementDecl>()? {
+            Ok(Self::Element(decl))
+        } else if let Some(decl) = input.opt_parse::<AttListDecl>()? {
+            Ok(Self::AttList(decl))
+        } else if let Some(decl) = input.opt_parse::<EntityDecl>()? {
+            Ok(Self::Entity(decl))
+        } else if let Some(decl) = input.opt_parse::<NotationDecl>()? {
+            Ok(Self::Notation(decl))
+        } else if let Some(pi) = input.opt_parse::<Pi>()? {
+            Ok(Self::Pi(pi))
+        } else if let Some(comment) = input.opt_parse::<Comment>()? {
+            Ok(Self::Comment(comment))
+        } else if input.opt_parse::<token::PeReference>()?.is_some() {
+            let name = input.parse::<Name>()?;
+            input.parse::<token::end_delim::PeReference>()?;
+            Ok(Self::PeReference(name))
+        } else {
+            Err(SyntaxError::MismatchedToken("markup declaration").into())
+        }
+    }
+}
+
+fn parse_decl_end<'a>(content: &mut impl ParseSource<'a>, end: &'static str) -> Result<()> {
+    content.opt_parse::<S>()?;
+
+    if !content.is_empty()? {
+        return Err(SyntaxError::MismatchedToken(end).into());
+    }
+    Ok(())
+}
+
+#[derive(PartialEq, Debug)]
+pub struct ElementDecl<'a> {
+    pub name: Name<'a>,
+    pub content_spec: ContentSpec<'a>,
+}
+
+impl<'a> Parse<'a> for ElementDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let mut content = input.delimited::<token::ElementDecl>()?;
+        content.parse::<S>()?;
+        let name = content.parse::<Name>()?;
+        content.parse::<S>()?;
+        let content_spec = content.parse::<ContentSpec>()?;
+        parse_decl_end(&mut content, token::end_delim::ElementDecl::display())?;
+
+        Ok(Self { name, content_spec })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ContentSpec<'a> {
+    Empty,
+    Any,
+    /// `(#PCDATA | a | b)*`, listing the element names.
+    Mixed(Vec<Name<'a>>),
+    Children(ContentParticle<'a>),
+}
+
+impl<'a> Parse<'a> for ContentSpec<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        if input.opt_parse::<dtd_token::Empty>()?.is_some() {
+            return Ok(Self::Empty);
+        }
+        if input.opt_parse::<dtd_token::Any>()?.is_some() {
+            return Ok(Self::Any);
+        }
+        input.parse::<token::Group>()?;
+        input.opt_parse::<S>()?;
+
+        if input.opt_parse::<dtd_token::PcData>()?.is_none() {
+            let particles = parse_group_content(input)?;
+            let repetition = input.parse::<Repetition>()?;
+
+            return Ok(Self::Children(ContentParticle {
+                particles,
+                repetition,
+            }));
+        }
+
+        let mut names = Vec::new();
+        loop {
+            input.opt_parse::<S>()?;
+
+            if input.opt_parse::<token::end_delim::Group>()?.is_some() {
+                break;
+            }
+            input.parse::<Token![|]>()?;
+            input.opt_parse::<S>()?;
+            names.push(input.parse::<Name>()?);
+        }
+
+        if input.opt_parse::<Token![*]>()?.is_none() && !names.is_empty() {
+            return Err(SyntaxError::MismatchedToken(<Token![*]>::display()).into());
+        }
+        Ok(Self::Mixed(names))
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct ContentParticle<'a> {
+    pub particles: Particles<'a>,
+    pub repetition: Repetition,
+}
+
+impl<'a> Parse<'a> for ContentParticle<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let particles = if input.opt_parse::<token::Group>()?.is_some() {
+            input.opt_parse::<S>()?;
+            parse_group_content(input)?
+        } else {
+            Particles::Name(input.parse::<Name>()?)
+        };
+
+        Ok(Self {
+            particles,
+            repetition: input.parse::<Repetition>()?,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Particles<'a> {
+    Name(Name<'a>),
+    Choice(Vec<ContentParticle<'a>>),
+    Seq(Vec<ContentParticle<'a>>),
+}
+
+/// Parses a `choice` or `seq` right after its opening parenthesis.
+fn parse_group_content<'a>(input: &mut impl ParseSource<'a>) -> Result<Particles<'a>> {
+    let mut particles = vec![input.parse::<ContentParticle>()?];
+    let mut is_choice = None;
+
+    loop {
+        input.opt_parse::<S>()?;
+
+        if input.opt_parse::<token::end_delim::Group>()?.is_some() {
+            break;
+        }
+        let choice = if input.opt_parse::<Token![|]>()?.is_some() {
+            true
+        } else if input.opt_parse::<Token![,]>()?.is_some() {
+            false
+        } else {
+            return Err(SyntaxError::MismatchedToken(token::end_delim::Group::display()).into());
+        };
+
+        if *is_choice.get_or_insert(choice) != choice {
+            let sep = if choice {
+                <Token![,]>::display()
+            } else {
+                <Token![|]>::display()
+            };
+            return Err(SyntaxError::MismatchedToken(sep).into());
+        }
+        input.opt_parse::<S>()?;
+        particles.push(input.parse::<ContentParticle>()?);
+    }
+
+    Ok(match is_choice {
+        Some(true) => Particles::Choice(particles),
+        _ => Particles::Seq(particles),
+    })
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Repetition {
+    Once,
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+}
+
+impl<'a> Parse<'a> for Repetition {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        Ok(if input.opt_parse::<Token![?]>()?.is_some() {
+            Self::Optional
+        } else if input.opt_parse::<Token![*]>()?.is_some() {
+            Self::ZeroOrMore
+        } else if input.opt_parse::<Token![+]>()?.is_some() {
+            Self::OneOrMore
+        } else {
+            Self::Once
+        })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct AttListDecl<'a> {
+    pub name: Name<'a>,
+    pub att_defs: Vec<AttDef<'a>>,
+}
+
+impl<'a> Parse<'a> for AttListDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let mut content = input.delimited::<token::AttlistDecl>()?;
+        content.parse::<S>()?;
+        let name = content.parse::<Name>()?;
+        let mut att_defs = Vec::new();
+
+        loop {
+            let s = content.opt_parse::<S>()?;
+
+            if content.is_empty()? {
+                return Ok(Self { name, att_defs });
+            }
+            if s.is_none() {
+                return Err(SyntaxError::MismatchedToken(S::display()).into());
+            }
+            att_defs.push(content.parse::<AttDef>()?);
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct AttDef<'a> {
+    pub name: Name<'a>,
+    pub att_type: AttType<'a>,
+    pub default: DefaultDecl<'a>,
+}
+
+impl<'a> Parse<'a> for AttDef<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let name = input.parse::<Name>()?;
+        input.parse::<S>()?;
+        let att_type = input.parse::<AttType>()?;
+        input.parse::<S>()?;
+        let default = input.parse::<DefaultDecl>()?;
+
+        Ok(Self {
+            name,
+            att_type,
+            default,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum AttType<'a> {
+    CData,
+    Id,
+    IdRef,
+    IdRefs,
+    Entity,
+    Entities,
+    NmToken,
+    NmTokens,
+    Notation(Vec<Name<'a>>),
+    Enumeration(Vec<Nmtoken<'a>>),
+}
+
+impl<'a> Parse<'a> for AttType<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        if input.opt_parse::<dtd_token::CData>()?.is_some() {
+            Ok(Self::CData)
+        } else if input.opt_parse::<dtd_token::IdRefs>()?.is_some() {
+            Ok(Self::IdRefs)
+        } else if input.opt_parse::<dtd_token::IdRef>()?.is_some() {
+            Ok(Self::IdRef)
+        } else if input.opt_parse::<dtd_token::Id>()?.is_some() {
+            Ok(Self::Id)
+        } else if input.opt_parse::<dtd_token::Entities>()?.is_some() {
+            Ok(Self::Entities)
+        } else if input.opt_parse::<dtd_token::Entity>()?.is_some() {
+            Ok(Self::Entity)
+        } else if input.opt_parse::<dtd_token::NmTokens>()?.is_some() {
+            Ok(Self::NmTokens)
+        } else if input.opt_parse::<dtd_token::NmToken>()?.is_some() {
+            Ok(Self::NmToken)
+        } else if input.opt_parse::<dtd_token::Notation>()?.is_some() {
+            input.parse::<S>()?;
+            Ok(Self::Notation(parse_enumeration::<Name>(input)?))
+        } else {
+            Ok(Self::Enumeration(parse_enumeration::<Nmtoken>(input)?))
+        }
+    }
+}
+
+/// Parses `( a | b | c )`.
+fn parse_enumeration<'a, P: Parse<'a>>(input: &mut impl ParseSource<'a>) -> Result<Vec<P>> {
+    input.parse::<token::Group>()?;
+    let mut values = Vec::new();
+
+    loop {
+        input.opt_parse::<S>()?;
+        values.push(input.parse::<P>()?);
+        input.opt_parse::<S>()?;
+
+        if input.opt_parse::<token::end_delim::Group>()?.is_some() {
+            return Ok(values);
+        }
+        input.parse::<Token![|]>()?;
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum DefaultDecl<'a> {
+    Required,
+    Implied,
+    Fixed(AttValue<'a>),
+    Value(AttValue<'a>),
+}
+
+impl<'a> Parse<'a> for DefaultDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        if input.opt_parse::<dtd_token::Required>()?.is_some() {
+            Ok(Self::Required)
+        } else if input.opt_parse::<dtd_token::Implied>()?.is_some() {
+            Ok(Self::Implied)
+        } else if input.opt_parse::<dtd_token::Fixed>()?.is_some() {
+            input.parse::<S>()?;
+            Ok(Self::Fixed(input.parse::<AttValue>()?))
+        } else {
+            Ok(Self::Value(input.parse::<AttValue>()?))
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct EntityDecl<'a> {
+    /// Whether this declares a parameter entity (`<!ENTITY % name ...>`).
+    pub parameter: bool,
+    pub name: Name<'a>,
+    pub def: EntityDef<'a>,
+}
+
+impl<'a> Parse<'a> for EntityDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let mut content = input.delimited::<token::EntityDecl>()?;
+        content.parse::<S>()?;
+        let parameter = content.opt_parse::<Token![%]>()?.is_some();
+
+        if parameter {
+            content.parse::<S>()?;
+        }
+        let name = content.parse::<Name>()?;
+        content.parse::<S>()?;
+
+        let def = if let Some(value) = content.opt_parse::<EntityValue>()? {
+            EntityDef::Internal(value)
+        } else {
+            let external_id = content.parse::<ExternalId>()?;
+            let ndata = if content.opt_parse::<S>()?.is_some()
+                && content.opt_parse::<dtd_token::NData>()?.is_some()
+            {
+                content.parse::<S>()?;
+                Some(content.parse::<Name>()?)
+            } else {
+                None
+            };
+            EntityDef::External(external_id, ndata)
+        };
+        parse_decl_end(&mut content, token::end_delim::EntityDecl::display())?;
+
+        Ok(Self {
+            parameter,
+            name,
+            def,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum EntityDef<'a> {
+    Internal(EntityValue<'a>),
+    /// An external entity, with the notation name of an unparsed one.
+    External(ExternalId<'a>, Option<Name<'a>>),
+}
+
+#[derive(PartialEq, Debug)]
+pub struct NotationDecl<'a> {
+    pub name: Name<'a>,
+    pub external_id: ExternalId<'a>,
+}
+
+impl<'a> Parse<'a> for NotationDecl<'a> {
+    fn parse(input: &mut impl ParseSource<'a>) -> Result<Self> {
+        let mut content = input.delimited::<token::NotationDecl>()?;
+        content.parse::<S>()?;
+        let name = content.parse::<Name>()?;
+        content.parse::<S>()?;
+
+        let external_id = if content.opt_parse::<dtd_token::Public>()?.is_some() {
+            content.parse::<S>()?;
+            let pubid = content.parse::<PubidLiteral>()?;
+            let system = if content.opt_parse::<S>()?.is_some() {
+                content.opt_parse::<SystemLiteral>()?
+            } else {
+                None
+            };
+            ExternalId::Public(pubid, system)
+        } else {
+            content.parse::<ExternalId>()?
+        };
+        parse_decl_end(&mut content, token::end_delim::NotationDecl::display())?;
+
+        Ok(Self { name, external_id })
+    }
+}
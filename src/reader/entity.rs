@@ -0,0 +1,272 @@
+use super::att_value::normalize_white_space;
+use super::dtd::{EntityDef, MarkupDecl};
+use crate::error::{Result, SyntaxError};
+use crate::escape;
+use crate::token::Literal;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Bounds on the expansion of entities declared in the internal subset,
+/// guarding against "billion laughs" documents.
+#[derive(Clone, Debug)]
+pub struct EntityLimits {
+    /// How deep entity references may nest within replacement texts.
+    pub max_depth: usize,
+    /// How many bytes of replacement text may be expanded in total.
+    pub max_expanded_len: usize,
+    /// How many times larger than the document read so far the expanded
+    /// text may grow, once it is over `amplification_threshold` bytes.
+    pub max_amplification: f64,
+    /// How many bytes of replacement text may be expanded in total before
+    /// `max_amplification` is checked, so that small documents can use
+    /// entities freely.
+    pub amplification_threshold: usize,
+}
+
+impl Default for EntityLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 16,
+            max_expanded_len: 16 << 20,
+            max_amplification: 100.0,
+            amplification_threshold: 1 << 20,
+        }
+    }
+}
+
+enum Entity {
+    Internal {
+        replacement: Rc<str>,
+        /// The replacement text holds markup, directly or through the
+        /// entities it references, and must be parsed as content.
+        has_markup: bool,
+    },
+    External,
+}
+
+#[derive(Default)]
+pub(crate) struct Entities {
+    decls: HashMap<String, Entity>,
+    expanded_len: usize,
+    expanding: Vec<String>,
+}
+
+impl Entities {
+    pub fn is_empty(&self) -> bool {
+        self.decls.is_empty()
+    }
+
+    pub fn declare(&mut self, decls: &[MarkupDecl]) -> Result<()> {
+        for decl in decls {
+            let MarkupDecl::Entity(decl) = decl else {
+                continue;
+            };
+            if decl.parameter || self.decls.contains_key(decl.name.value()) {
+                continue;
+            }
+
+            let entity = match &decl.def {
+                EntityDef::Internal(value) => {
+                    // Only character references are decoded on declaration,
+                    // entity references are left for the expansion.
+                    let mut replacement = String::with_capacity(value.value().len());
+
+                    escape::unescape_into(value.value(), &mut replacement, &mut |name, out| {
+                        out.push('&');
+                        out.push_str(name);
+                        out.push(';');
+                        Ok(true)
+                    })?;
+                    Entity::Internal {
+                        replacement: replacement.into(),
+                        has_markup: false,
+                    }
+                }
+                EntityDef::External(..) => Entity::External,
+            };
+            self.decls.insert(decl.name.value().to_owned(), entity);
+        }
+        self.flag_markup();
+
+        Ok(())
+    }
+
+    /// Flags the entities whose replacement text holds markup, directly or
+    /// through the entities it references.
+    fn flag_markup(&mut self) {
+        let mut referrers: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut pending = Vec::new();
+
+        for (name, entity) in &self.decls {
+            let Entity::Internal { replacement, .. } = entity else {
+                continue;
+            };
+            if replacement.contains('<') {
+                pending.push(name.as_str());
+            }
+            for reference in references(replacement) {
+                referrers.entry(reference).or_default().push(name);
+            }
+        }
+        let mut flagged = HashSet::new();
+
+        while let Some(name) = pending.pop() {
+            if flagged.insert(name) {
+                pending.extend(referrers.get(name).into_iter().flatten());
+            }
+        }
+        let flagged: Vec<String> = flagged.into_iter().map(str::to_owned).collect();
+
+        for name in flagged {
+            if let Some(Entity::Internal { has_markup, .. }) = self.decls.get_mut(&name) {
+                *has_markup = true;
+            }
+        }
+    }
+
+    /// Finds the first reference in `raw` to an entity whose replacement
+    /// text must be parsed as content, returning where it starts, its name
+    /// and that text.
+    pub fn find_markup_ref<'r>(&self, raw: &'r str) -> Option<(usize, &'r str, Rc<str>)> {
+        raw.match_indices('&').find_map(|(start, _)| {
+            let (name, _) = raw[start + 1..].split_once(';')?;
+
+            match self.decls.get(name) {
+                Some(Entity::Internal {
+                    replacement,
+                    has_markup: true,
+                }) => Some((start, name, replacement.clone())),
+                _ => None,
+            }
+        })
+    }
+
+    /// Starts expanding `name`, whose replacement text is `len` bytes long,
+    /// checking that it doesn't reference itself and that the limits hold.
+    pub fn enter(
+        &mut self,
+        name: &str,
+        len: usize,
+        doc_len: u64,
+        limits: &EntityLimits,
+    ) -> Result<()> {
+        if self.expanding.iter().any(|expanding| expanding == name) {
+            return Err(SyntaxError::RecursiveEntity(name.to_owned()).into());
+        }
+        self.expanded_len = self.expanded_len.saturating_add(len);
+
+        if self.expanding.len() >= limits.max_depth {
+            return Err(SyntaxError::EntityExpansionLimit("max_depth").into());
+        }
+        if self.expanded_len > limits.max_expanded_len {
+            return Err(SyntaxError::EntityExpansionLimit("max_expanded_len").into());
+        }
+        if self.expanded_len > limits.amplification_threshold {
+            let doc_len = doc_len.max(1) as f64;
+
+            if (doc_len + self.expanded_len as f64) / doc_len > limits.max_amplification {
+                return Err(SyntaxError::EntityExpansionLimit("max_amplification").into());
+            }
+        }
+        self.expanding.push(name.to_owned());
+
+        Ok(())
+    }
+
+    /// Returns how many bytes of replacement text were expanded so far.
+    pub fn expanded_len(&self) -> usize {
+        self.expanded_len
+    }
+
+    /// Forgets the expansions since `expanded_len` returned `len`, for an
+    /// event that is parsed again.
+    pub fn rewind(&mut self, len: usize) {
+        self.expanded_len = len;
+    }
+
+    /// Ends expanding the entity `enter` started last.
+    pub fn leave(&mut self) {
+        self.expanding.pop();
+    }
+
+    /// Decodes all references in `raw`, `doc_len` being how many bytes of
+    /// the document have been read so far. Replacement texts get their white
+    /// space normalized if `raw` is an attribute value.
+    pub fn expand(
+        &mut self,
+        raw: &str,
+        in_att_value: bool,
+        doc_len: u64,
+        limits: &EntityLimits,
+    ) -> Result<String> {
+        let mut result = String::with_capacity(raw.len());
+        let mut expansion = Expansion {
+            entities: self,
+            in_att_value,
+            doc_len,
+            limits,
+        };
+
+        expansion.expand_into(raw, &mut result)?;
+        Ok(result)
+    }
+}
+
+struct Expansion<'e> {
+    entities: &'e mut Entities,
+    in_att_value: bool,
+    doc_len: u64,
+    limits: &'e EntityLimits,
+}
+
+impl Expansion<'_> {
+    fn expand_into(&mut self, raw: &str, result: &mut String) -> Result<()> {
+        escape::unescape_into(raw, result, &mut |name, result| {
+            if escape::push_predefined_entity(name, result) {
+                return Ok(true);
+            }
+            self.expand_entity(name, result)
+        })
+    }
+
+    fn expand_entity(&mut self, name: &str, result: &mut String) -> Result<bool> {
+        let replacement = match self.entities.decls.get(name) {
+            Some(Entity::Internal { replacement, .. }) => replacement.clone(),
+            Some(Entity::External) => {
+                return Err(SyntaxError::UnsupportedExternalEntity(name.to_owned()).into());
+            }
+            None => return Ok(false),
+        };
+
+        // Content references to entities with markup are parsed instead, so
+        // this only rejects them in attribute values.
+        if replacement.contains('<') {
+            return Err(SyntaxError::MarkupInEntity(name.to_owned()).into());
+        }
+        self.entities
+            .enter(name, replacement.len(), self.doc_len, self.limits)?;
+
+        let replacement = if self.in_att_value {
+            normalize_white_space(&replacement)
+        } else {
+            Cow::Borrowed(&*replacement)
+        };
+
+        let expanded = self.expand_into(&replacement, result);
+        self.entities.leave();
+
+        expanded.map(|()| true)
+    }
+}
+
+/// The names of the entities `replacement` references, its character
+/// references being decoded already.
+fn references(replacement: &str) -> impl Iterator<Item = &str> {
+    replacement
+        .split('&')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(';'))
+        .map(|(name, _)| name)
+        .filter(|name| !name.starts_with('#'))
+}
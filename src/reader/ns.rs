@@ -0,0 +1,274 @@
+use super::{Attribute, EventReader, ReaderConfig, XmlEvent};
+use crate::error::{Result, SyntaxError};
+use crate::parse::{ParseSource, SliceParser};
+use crate::token::{AttValue, Literal, Name};
+use crate::Position;
+use std::mem;
+use std::rc::Rc;
+
+/// The namespace bound to the `xml` prefix.
+pub const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+/// The namespace of `xmlns` attributes.
+pub const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
+
+const XMLNS: &str = "xmlns";
+
+/// A `Name` together with the namespace its prefix resolved to.
+#[derive(PartialEq, Clone, Debug)]
+pub struct QName<'a> {
+    pub name: Name<'a>,
+    pub namespace: Option<Rc<str>>,
+}
+
+impl QName<'_> {
+    pub fn prefix(&self) -> Option<&str> {
+        self.name.prefix()
+    }
+
+    pub fn local_name(&self) -> &str {
+        self.name.local_name()
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct NsAttribute<'a> {
+    pub name: QName<'a>,
+    pub value: AttValue<'a>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct NsStartTag<'a> {
+    pub name: QName<'a>,
+    pub attrs: Vec<NsAttribute<'a>>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct NsEndTag<'a> {
+    pub name: QName<'a>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum NsEvent<'a> {
+    STag(NsStartTag<'a>),
+    ETag(NsEndTag<'a>),
+    EmptyElem(NsStartTag<'a>),
+    /// Any event without names to resolve.
+    Other(XmlEvent<'a>),
+}
+
+struct Binding {
+    /// `None` for the default namespace.
+    prefix: Option<String>,
+    /// `None` when `xmlns=""` undeclares the default namespace.
+    namespace: Option<Rc<str>>,
+}
+
+struct Scopes {
+    bindings: Vec<Binding>,
+    /// How many bindings were in scope before each open element.
+    starts: Vec<usize>,
+}
+
+impl Default for Scopes {
+    fn default() -> Self {
+        let binding = |prefix: &str, namespace: &str| Binding {
+            prefix: Some(prefix.to_owned()),
+            namespace: Some(namespace.into()),
+        };
+
+        Self {
+            bindings: vec![
+                binding("xml", XML_NAMESPACE),
+                binding(XMLNS, XMLNS_NAMESPACE),
+            ],
+            starts: Vec::new(),
+        }
+    }
+}
+
+impl Scopes {
+    fn lookup(&self, prefix: Option<&str>) -> Option<&Binding> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|binding| binding.prefix.as_deref() == prefix)
+    }
+
+    fn push(&mut self, attrs: &[Attribute]) -> Result<()> {
+        self.starts.push(self.bindings.len());
+
+        for attr in attrs {
+            let name = &attr.name;
+            let prefix = match (name.prefix(), name.local_name()) {
+                (None, XMLNS) => None,
+                (Some(XMLNS), prefix) => Some(prefix),
+                _ => continue,
+            };
+            let namespace = attr.value.value();
+            let reserved = namespace == XML_NAMESPACE || namespace == XMLNS_NAMESPACE;
+
+            let valid = match prefix {
+                None => !reserved,
+                Some(XMLNS) => false,
+                Some("xml") => namespace == XML_NAMESPACE,
+                Some(_) => !reserved && !namespace.is_empty(),
+            };
+            if !valid {
+                return Err(SyntaxError::InvalidNamespaceDecl(name.value().to_owned()).into());
+            }
+
+            self.bindings.push(Binding {
+                prefix: prefix.map(str::to_owned),
+                namespace: (!namespace.is_empty()).then(|| namespace.into()),
+            });
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) {
+        if let Some(start) = self.starts.pop() {
+            self.bindings.truncate(start);
+        }
+    }
+
+    fn resolve<'a>(&self, name: Name<'a>, is_attr: bool) -> Result<QName<'a>> {
+        let value = name.value();
+
+        if value.starts_with(':') || value.ends_with(':') || name.local_name().contains(':') {
+            return Err(SyntaxError::InvalidQName(value.to_owned()).into());
+        }
+
+        let namespace = match name.prefix() {
+            // Unprefixed attributes are in no namespace, except `xmlns` itself.
+            None if is_attr && value == XMLNS => Some(XMLNS_NAMESPACE.into()),
+            None if is_attr => None,
+            None => self
+                .lookup(None)
+                .and_then(|binding| binding.namespace.clone()),
+            Some(prefix) => match self.lookup(Some(prefix)) {
+                Some(binding) => binding.namespace.clone(),
+                None => return Err(SyntaxError::UnboundPrefix(prefix.to_owned()).into()),
+            },
+        };
+
+        Ok(QName { name, namespace })
+    }
+
+    fn resolve_attrs<'a>(&self, attrs: Vec<Attribute<'a>>) -> Result<Vec<NsAttribute<'a>>> {
+        let mut resolved: Vec<NsAttribute<'a>> = Vec::with_capacity(attrs.len());
+
+        for Attribute { name, value } in attrs {
+            let name = self.resolve(name, true)?;
+
+            // `a:id` and `b:id` may still be bound to the same namespace.
+            if resolved.iter().any(|attr| {
+                attr.name.namespace.is_some()
+                    && attr.name.namespace == name.namespace
+                    && attr.name.local_name() == name.local_name()
+            }) {
+                return Err(SyntaxError::DuplicateAttribute(name.name.value().to_owned()).into());
+            }
+            resolved.push(NsAttribute { name, value });
+        }
+        Ok(resolved)
+    }
+}
+
+/// Wraps an `EventReader`, resolving the names of elements and attributes
+/// against the `xmlns` declarations in scope.
+pub struct NsReader<'a, T> {
+    reader: EventReader<'a, T>,
+    scopes: Scopes,
+    /// The element of the last event is closed, but its bindings stay in
+    /// scope until the next event.
+    pending_pop: bool,
+}
+
+impl<'a, T> NsReader<'a, T> {
+    pub fn new(src: T) -> Self {
+        Self::with_config(src, ReaderConfig::default())
+    }
+
+    pub fn with_config(src: T, config: ReaderConfig) -> Self {
+        NsReader {
+            reader: EventReader::with_config(src, config),
+            scopes: Scopes::default(),
+            pending_pop: false,
+        }
+    }
+
+    pub fn config(&self) -> &ReaderConfig {
+        self.reader.config()
+    }
+
+    /// Returns where the event last returned by `next_event` started.
+    pub fn position(&self) -> Position {
+        self.reader.position()
+    }
+
+    /// Returns the namespace `prefix`, or the default namespace for `None`,
+    /// is bound to for the event last returned by `next_event`.
+    pub fn namespace(&self, prefix: Option<&str>) -> Option<&str> {
+        self.scopes
+            .lookup(prefix)
+            .and_then(|binding| binding.namespace.as_deref())
+    }
+}
+
+impl<'a, T: ParseSource<'a>> NsReader<'a, T> {
+    pub fn next_event(&mut self) -> Result<NsEvent<'a>> {
+        if mem::take(&mut self.pending_pop) {
+            self.scopes.pop();
+        }
+        let event = self.reader.next_event()?;
+
+        self.resolve_event(event)
+            .map_err(|err| err.at(self.reader.position()))
+    }
+
+    fn resolve_event(&mut self, event: XmlEvent<'a>) -> Result<NsEvent<'a>> {
+        match event {
+            XmlEvent::STag(tag) => {
+                self.scopes.push(&tag.attrs)?;
+
+                Ok(NsEvent::STag(NsStartTag {
+                    name: self.scopes.resolve(tag.name, false)?,
+                    attrs: self.scopes.resolve_attrs(tag.attrs)?,
+                }))
+            }
+            XmlEvent::EmptyElem(tag) => {
+                self.scopes.push(&tag.attrs)?;
+                self.pending_pop = true;
+
+                Ok(NsEvent::EmptyElem(NsStartTag {
+                    name: self.scopes.resolve(tag.name, false)?,
+                    attrs: self.scopes.resolve_attrs(tag.attrs)?,
+                }))
+            }
+            XmlEvent::ETag(tag) => {
+                self.pending_pop = true;
+
+                Ok(NsEvent::ETag(NsEndTag {
+                    name: self.scopes.resolve(tag.name, false)?,
+                }))
+            }
+            event => Ok(NsEvent::Other(event)),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for NsReader<'a, SliceParser<'a>> {
+    fn from(src: &'a [u8]) -> Self {
+        NsReader::new(SliceParser::new(src))
+    }
+}
+
+impl<'a> From<&'a str> for NsReader<'a, SliceParser<'a>> {
+    fn from(src: &'a str) -> Self {
+        NsReader::new(SliceParser::new(src.as_bytes()))
+    }
+}
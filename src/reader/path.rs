@@ -0,0 +1,311 @@
+use super::{Attribute, EventReader, XmlEvent};
+use crate::error::{Error, Result, SyntaxError};
+use crate::parse::ParseSource;
+use crate::token::rules::accept_as_name;
+use crate::token::{Literal, Name, Token};
+use crate::Position;
+use std::mem;
+
+/// An attribute an element must have for a step to match it.
+#[derive(Clone, Debug)]
+struct AttrTest {
+    name: String,
+    value: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct PathStep {
+    /// Whether the step is separated from the one before by `//` rather
+    /// than `/`, or the pattern starts with `//` or no `/` at all.
+    is_descendant: bool,
+    /// `None` for `*`.
+    name: Option<String>,
+    attrs: Vec<AttrTest>,
+}
+
+impl PathStep {
+    fn matches(&self, name: &Name, attrs: &[Attribute]) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|step_name| name.is(step_name))
+            && self.attrs.iter().all(|test| {
+                attrs.iter().any(|attr| {
+                    attr.name.is(&test.name)
+                        && test.value.as_ref().is_none_or(|value| attr.value.is(value))
+                })
+            })
+    }
+}
+
+/// A pattern matching elements by the names of their ancestors, such as
+/// `/CATALOG/CD/TITLE`, `//PRICE` or `CD[@id='1']/TITLE`.
+///
+/// Steps are element names or `*`, separated by `/` for a child or `//` for
+/// any descendant, each followed by any number of `[@name]` or
+/// `[@name='value']` predicates. A pattern that doesn't start with `/`
+/// matches at any depth, like one starting with `//`.
+#[derive(Clone, Debug)]
+pub struct PathPattern {
+    steps: Vec<PathStep>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut steps = Vec::new();
+        let mut rest = pattern;
+
+        let error = |rest: &str, err: SyntaxError| {
+            let offset = pattern.len() - rest.len();
+
+            Error::from(err).at(Position {
+                line: 1,
+                column: pattern[..offset].chars().count() as u64 + 1,
+                byte_column: offset as u64 + 1,
+                offset: offset as u64,
+            })
+        };
+
+        let mut is_descendant = !rest.starts_with('/');
+        loop {
+            if let Some(after) = rest.strip_prefix("//") {
+                is_descendant = true;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('/') {
+                rest = after;
+            }
+
+            let name = match rest.strip_prefix('*') {
+                Some(after) => {
+                    rest = after;
+                    None
+                }
+                None => {
+                    let len = name_len(rest);
+                    if len == 0 {
+                        return Err(error(rest, SyntaxError::MismatchedToken(Name::display())));
+                    }
+                    let name = rest[..len].to_owned();
+                    rest = &rest[len..];
+                    Some(name)
+                }
+            };
+
+            let mut attrs = Vec::new();
+            while let Some(after) = rest.strip_prefix("[@") {
+                rest = after.trim_start();
+                let len = name_len(rest);
+                if len == 0 {
+                    return Err(error(rest, SyntaxError::MismatchedToken(Name::display())));
+                }
+                let name = rest[..len].to_owned();
+                rest = rest[len..].trim_start();
+
+                let value = match rest.strip_prefix('=') {
+                    Some(after) => {
+                        rest = after.trim_start();
+                        let quote = match rest.chars().next() {
+                            Some(quote @ ('"' | '\'')) => quote,
+                            _ => return Err(error(rest, SyntaxError::MismatchedToken("`'`"))),
+                        };
+                        let Some(len) = rest[1..].find(quote) else {
+                            let delim = if quote == '"' { "`\"`" } else { "`'`" };
+                            return Err(error(rest, SyntaxError::UnclosedDelimiter(delim)));
+                        };
+                        let value = rest[1..len + 1].to_owned();
+                        rest = rest[len + 2..].trim_start();
+                        Some(value)
+                    }
+                    None => None,
+                };
+                rest = rest
+                    .strip_prefix(']')
+                    .ok_or_else(|| error(rest, SyntaxError::MismatchedToken("`]`")))?;
+
+                attrs.push(AttrTest { name, value });
+            }
+
+            steps.push(PathStep {
+                is_descendant,
+                name,
+                attrs,
+            });
+            is_descendant = false;
+
+            if rest.is_empty() {
+                return Ok(Self { steps });
+            }
+            if !rest.starts_with('/') {
+                let token = rest.chars().next().unwrap_or_default();
+                return Err(error(rest, SyntaxError::UnexpectedToken(token.to_string())));
+            }
+        }
+    }
+
+    /// Tells whether the last element of `open` matches, given which steps
+    /// every open element matches on its own for the pattern at `index`.
+    fn matches(&self, open: &[Vec<Vec<bool>>], index: usize) -> bool {
+        // The open elements the steps so far match a path down to, which
+        // takes one pass over `open` per step.
+        let mut reached: Vec<bool> = Vec::new();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            // The document itself is above every element for the first step.
+            let mut is_below_reached = i == 0;
+            let mut next = Vec::with_capacity(open.len());
+
+            for (elem, matches) in open.iter().enumerate() {
+                let is_child = match i {
+                    0 => elem == 0,
+                    _ => elem > 0 && reached[elem - 1],
+                };
+                let is_descendant = step.is_descendant && is_below_reached;
+
+                next.push(matches[index][i] && (is_child || is_descendant));
+                is_below_reached |= i > 0 && reached[elem];
+            }
+            reached = next;
+        }
+        reached.last() == Some(&true)
+    }
+}
+
+fn name_len(rest: &str) -> usize {
+    let mut rule = accept_as_name();
+
+    rest.find(|ch| !rule(ch)).unwrap_or(rest.len())
+}
+
+/// Wraps an `EventReader`, passing on only the events of elements matching
+/// one of its patterns, from their start tag to their end tag.
+///
+/// Only which steps each open element matches is kept, so memory doesn't
+/// grow with the size of the document. Elements matching inside a matching
+/// element are part of its events and don't start a match of their own.
+pub struct PathReader<'a, T> {
+    reader: EventReader<'a, T>,
+    patterns: Vec<PathPattern>,
+    /// For every open element, which steps of every pattern it matches.
+    open: Vec<Vec<Vec<bool>>>,
+    /// The pattern the current match is for, and how many elements were
+    /// open outside it.
+    current: Option<(usize, usize)>,
+    /// The current match ended with the last event.
+    pending_end: bool,
+}
+
+impl<'a, T> PathReader<'a, T> {
+    pub fn new(reader: EventReader<'a, T>, patterns: Vec<PathPattern>) -> Self {
+        PathReader {
+            reader,
+            patterns,
+            open: Vec::new(),
+            current: None,
+            pending_end: false,
+        }
+    }
+
+    pub fn patterns(&self) -> &[PathPattern] {
+        &self.patterns
+    }
+
+    /// Returns the index of the pattern that the event last returned by
+    /// `next_event` or `next_text` was matched by.
+    pub fn matched(&self) -> Option<usize> {
+        self.current.map(|(pattern, _)| pattern)
+    }
+
+    /// Returns where the event last returned by `next_event` started.
+    pub fn position(&self) -> Position {
+        self.reader.position()
+    }
+
+    /// Returns the names of the elements left open by the event last
+    /// returned by `next_event`, outermost first.
+    pub fn path(&self) -> &[Name<'a>] {
+        self.reader.path()
+    }
+
+    pub fn into_inner(self) -> EventReader<'a, T> {
+        self.reader
+    }
+
+    fn open_elem(&mut self, name: &Name, attrs: &[Attribute]) {
+        let matches = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                pattern
+                    .steps
+                    .iter()
+                    .map(|step| step.matches(name, attrs))
+                    .collect()
+            })
+            .collect();
+        self.open.push(matches);
+
+        if self.current.is_none() {
+            self.current = self
+                .patterns
+                .iter()
+                .enumerate()
+                .find(|(index, pattern)| pattern.matches(&self.open, *index))
+                .map(|(index, _)| (index, self.open.len() - 1));
+        }
+    }
+
+    fn close_elem(&mut self) {
+        self.open.pop();
+
+        if self
+            .current
+            .is_some_and(|(_, depth)| depth == self.open.len())
+        {
+            self.pending_end = true;
+        }
+    }
+}
+
+impl<'a, T: ParseSource<'a>> PathReader<'a, T> {
+    /// Returns the next event of a matching element, or `XmlEvent::Eof`
+    /// once there are no more.
+    pub fn next_event(&mut self) -> Result<XmlEvent<'a>> {
+        loop {
+            if mem::take(&mut self.pending_end) {
+                self.current = None;
+            }
+            let event = self.reader.next_event()?;
+
+            match &event {
+                XmlEvent::STag(tag) => self.open_elem(&tag.name, &tag.attrs),
+                XmlEvent::EmptyElem(elem) => {
+                    self.open_elem(&elem.name, &elem.attrs);
+                    self.close_elem();
+                }
+                XmlEvent::ETag(_) => self.close_elem(),
+                XmlEvent::Eof => return Ok(event),
+                _ => {}
+            }
+            if self.current.is_some() {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Returns the text and CDATA sections of the next matching element,
+    /// or `None` once there are no more.
+    pub fn next_text(&mut self) -> Result<Option<String>> {
+        let mut text = String::new();
+
+        loop {
+            match self.next_event()? {
+                XmlEvent::Text(chunk) => text.push_str(chunk.value()),
+                XmlEvent::CData(chunk) => text.push_str(chunk.value()),
+                XmlEvent::Eof => return Ok(None),
+                _ => {}
+            }
+            if self.pending_end {
+                return Ok(Some(text));
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::{EventReader, Layout, ReaderConfig, XmlEvent};
+use crate::error::{Error, Result};
+use crate::read::ChunkSource;
+use crate::token::Name;
+use crate::Position;
+use std::io::ErrorKind;
+use std::mem;
+
+/// Parses a document handed over in chunks of bytes as they arrive, doing
+/// no I/O of its own.
+///
+/// A chunk may end anywhere, even within a character, a delimiter or an
+/// attribute value. `next_event` returns `None` until the next event was fed
+/// in full, and parses it again from its start once more of it was fed. The
+/// encoding is detected as `Parser::from_reader` does.
+pub struct PushReader {
+    events: EventReader<'static, ChunkSource>,
+}
+
+impl PushReader {
+    pub fn new() -> Self {
+        Self::with_config(ReaderConfig::default())
+    }
+
+    pub fn with_config(config: ReaderConfig) -> Self {
+        Self {
+            events: EventReader::with_config(ChunkSource::new(), config),
+        }
+    }
+
+    /// Keeps `\r\n` and lone `\r` in literals as written instead of
+    /// normalizing them to `\n`, like `Parser::raw_line_endings`.
+    pub fn raw_line_endings(mut self) -> Self {
+        self.events.src.set_raw_line_endings(true);
+        self
+    }
+
+    pub fn config(&self) -> &ReaderConfig {
+        self.events.config()
+    }
+
+    /// Returns where the event last returned by `next_event` started.
+    pub fn position(&self) -> Position {
+        self.events.position()
+    }
+
+    /// Returns how the markup of the event last returned by `next_event`
+    /// was spelled.
+    pub fn layout(&self) -> &Layout {
+        self.events.layout()
+    }
+
+    /// Returns the names of the elements left open by the event last
+    /// returned by `next_event`, outermost first.
+    pub fn path(&self) -> &[Name<'static>] {
+        self.events.path()
+    }
+
+    /// Hands over the next chunk of the document.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<()> {
+        self.events.src.feed(chunk)
+    }
+
+    /// Marks the end of the document, after which `next_event` no longer
+    /// returns `None`.
+    pub fn finish(&mut self) -> Result<()> {
+        self.events.src.close()
+    }
+
+    /// Returns the next event, or `None` if more of the document must be
+    /// fed first.
+    pub fn next_event(&mut self) -> Result<Option<XmlEvent<'static>>> {
+        let events = &mut self.events;
+        events.src.checkpoint();
+
+        let (st, start) = (events.st, events.start);
+        let layout = mem::take(&mut events.layout);
+        let expanded_len = events.entities.expanded_len();
+
+        match events.next_event() {
+            Err(Error::Io(ErrorKind::WouldBlock)) => {
+                events.src.rewind();
+                events.st = st;
+                events.start = start;
+                events.layout = layout;
+                events.entities.rewind(expanded_len);
+
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    }
+}
+
+impl Default for PushReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
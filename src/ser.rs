@@ -0,0 +1,736 @@
+use crate::error::{Error, Result, SyntaxError};
+use crate::reader::{Attribute, EmptyElem, EndTag, StartTag, XmlEvent};
+use crate::token::rules::accept_as_name;
+use crate::token::{AttValue, Literal, Name, Text};
+use crate::writer::EventWriter;
+use serde::ser::{
+    self, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+    SerializeStructVariant, SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
+};
+use std::fmt::Display;
+use std::io::Write;
+
+impl ser::Error for Error {
+    fn custom<M: Display>(msg: M) -> Self {
+        SyntaxError::Custom(msg.to_string()).into()
+    }
+}
+
+/// Serializes `value` as a document named after its type to `writer`.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    Serializer::new(EventWriter::new(writer)).serialize(value)
+}
+
+/// Serializes `value` as a document named after its type to a string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    let mut out = Vec::new();
+    to_writer(&mut out, value)?;
+
+    Ok(String::from_utf8(out).expect("the writer writes UTF-8 by default"))
+}
+
+fn unsupported(what: &str) -> Error {
+    <Error as ser::Error>::custom(format!("{what} can't be serialized as XML"))
+}
+
+fn check_name(name: &str) -> Result<Name<'static>> {
+    let mut rule = accept_as_name();
+
+    if name.is_empty() || !name.chars().all(&mut rule) {
+        return Err(<Error as ser::Error>::custom(format!(
+            "`{name}` isn't a valid XML name"
+        )));
+    }
+    Ok(Name::new(name.to_owned()))
+}
+
+/// Writes a type implementing `serde::Serialize` as a document through an
+/// `EventWriter`, following the conventions of `de::Deserializer`.
+///
+/// A field renamed to `@` followed by a name is written as an attribute and
+/// one renamed to `$text` as text, any other as a child element. Attributes
+/// must come before the other fields. A sequence is written as elements
+/// repeating the name of its field, a tuple as values separated by spaces,
+/// and `None` not at all. A unit variant is written as text, any other
+/// variant as a child element named after it.
+pub struct Serializer<W: Write> {
+    writer: EventWriter<W>,
+    root: Option<String>,
+}
+
+impl<W: Write> Serializer<W> {
+    /// Names the root element after the type of the value serialized.
+    pub fn new(writer: EventWriter<W>) -> Self {
+        Self { writer, root: None }
+    }
+
+    /// Names the root element `root`, which values without a type name such
+    /// as maps or strings need.
+    pub fn with_root(writer: EventWriter<W>, root: impl Into<String>) -> Self {
+        Self {
+            writer,
+            root: Some(root.into()),
+        }
+    }
+
+    pub fn into_inner(self) -> EventWriter<W> {
+        self.writer
+    }
+
+    /// Writes `value` as the root element and ends the document.
+    pub fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(ElemSerializer {
+            writer: &mut self.writer,
+            name: self.root.clone(),
+            is_root: true,
+        })?;
+        self.writer.write_event(&XmlEvent::Eof)
+    }
+}
+
+/// Writes a value as an element, or as nothing for `None`.
+struct ElemSerializer<'w, W: Write> {
+    writer: &'w mut EventWriter<W>,
+    /// `None` for a root element named after the type of its value.
+    name: Option<String>,
+    is_root: bool,
+}
+
+impl<'w, W: Write> ElemSerializer<'w, W> {
+    fn name(&self, type_name: Option<&str>) -> Result<Name<'static>> {
+        match (self.name.as_deref(), type_name) {
+            (Some(name), _) | (None, Some(name)) => check_name(name),
+            (None, None) => Err(<Error as ser::Error>::custom(
+                "a value without a type name needs a root name",
+            )),
+        }
+    }
+
+    fn write_text(self, type_name: Option<&str>, text: Option<String>) -> Result<()> {
+        let name = self.name(type_name)?;
+        let Some(text) = text else {
+            return Ok(());
+        };
+
+        self.writer.write_event(&XmlEvent::STag(StartTag {
+            name: name.clone(),
+            attrs: Vec::new(),
+        }))?;
+        if !text.is_empty() {
+            self.writer.write_event(&XmlEvent::Text(Text::new(text)))?;
+        }
+        self.writer.write_event(&XmlEvent::ETag(EndTag { name }))
+    }
+
+    fn write_primitive(self, value: impl Display) -> Result<()> {
+        self.write_text(None, Some(value.to_string()))
+    }
+
+    /// Writes the start tag of the element wrapping a variant.
+    fn start_variant(self, type_name: &str) -> Result<(&'w mut EventWriter<W>, Name<'static>)> {
+        let name = self.name(Some(type_name))?;
+
+        self.writer.write_event(&XmlEvent::STag(StartTag {
+            name: name.clone(),
+            attrs: Vec::new(),
+        }))?;
+        Ok((self.writer, name))
+    }
+
+    fn into_struct(
+        self,
+        name: Name<'static>,
+        wrapper: Option<Name<'static>>,
+    ) -> StructSerializer<'w, W> {
+        StructSerializer {
+            writer: self.writer,
+            name,
+            attrs: Vec::new(),
+            is_started: false,
+            key: None,
+            wrapper,
+        }
+    }
+}
+
+macro_rules! serialize_primitives {
+    ($($method:ident($ty:ty))*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<()> {
+                self.write_primitive(value)
+            }
+        )*
+    };
+}
+
+impl<'w, W: Write> ser::Serializer for ElemSerializer<'w, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'w, W>;
+    type SerializeTuple = TupleSerializer<'w, W>;
+    type SerializeTupleStruct = TupleSerializer<'w, W>;
+    type SerializeTupleVariant = TupleSerializer<'w, W>;
+    type SerializeMap = StructSerializer<'w, W>;
+    type SerializeStruct = StructSerializer<'w, W>;
+    type SerializeStructVariant = StructSerializer<'w, W>;
+
+    serialize_primitives! {
+        serialize_bool(bool) serialize_i8(i8) serialize_i16(i16) serialize_i32(i32)
+        serialize_i64(i64) serialize_i128(i128) serialize_u8(u8) serialize_u16(u16)
+        serialize_u32(u32) serialize_u64(u64) serialize_u128(u128) serialize_f32(f32)
+        serialize_f64(f64) serialize_char(char) serialize_str(&str)
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<()> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.write_text(None, None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        let name = self.name(None)?;
+        self.writer.write_event(&XmlEvent::EmptyElem(EmptyElem {
+            name,
+            attrs: Vec::new(),
+        }))
+    }
+
+    fn serialize_unit_struct(self, type_name: &'static str) -> Result<()> {
+        let name = self.name(Some(type_name))?;
+        self.writer.write_event(&XmlEvent::EmptyElem(EmptyElem {
+            name,
+            attrs: Vec::new(),
+        }))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        type_name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.write_text(Some(type_name), Some(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        type_name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        if self.name.is_none() {
+            return value.serialize(ElemSerializer {
+                name: Some(type_name.to_owned()),
+                ..self
+            });
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        type_name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        let (writer, name) = self.start_variant(type_name)?;
+
+        value.serialize(ElemSerializer {
+            writer: &mut *writer,
+            name: Some(variant.to_owned()),
+            is_root: false,
+        })?;
+        writer.write_event(&XmlEvent::ETag(EndTag { name }))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        if self.is_root {
+            return Err(<Error as ser::Error>::custom(
+                "a sequence can't be the root element",
+            ));
+        }
+        Ok(SeqSerializer {
+            writer: self.writer,
+            name: self.name.unwrap_or_default(),
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        let name = self.name(None)?;
+        Ok(TupleSerializer::new(self.writer, name, None))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        type_name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        let name = self.name(Some(type_name))?;
+        Ok(TupleSerializer::new(self.writer, name, None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        type_name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        let (writer, wrapper) = self.start_variant(type_name)?;
+        Ok(TupleSerializer::new(
+            writer,
+            check_name(variant)?,
+            Some(wrapper),
+        ))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        let name = self.name(None)?;
+        Ok(self.into_struct(name, None))
+    }
+
+    fn serialize_struct(
+        self,
+        type_name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct> {
+        let name = self.name(Some(type_name))?;
+        Ok(self.into_struct(name, None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        type_name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        let (writer, wrapper) = self.start_variant(type_name)?;
+        let elem = ElemSerializer {
+            writer,
+            name: None,
+            is_root: false,
+        };
+        Ok(elem.into_struct(check_name(variant)?, Some(wrapper)))
+    }
+}
+
+/// Writes every item as an element with the same name.
+struct SeqSerializer<'w, W: Write> {
+    writer: &'w mut EventWriter<W>,
+    name: String,
+}
+
+impl<W: Write> SerializeSeq for SeqSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(ElemSerializer {
+            writer: &mut *self.writer,
+            name: Some(self.name.clone()),
+            is_root: false,
+        })
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the items as the text of an element, separated by spaces.
+struct TupleSerializer<'w, W: Write> {
+    writer: &'w mut EventWriter<W>,
+    name: Name<'static>,
+    items: Vec<String>,
+    /// The element wrapping a tuple variant.
+    wrapper: Option<Name<'static>>,
+}
+
+impl<'w, W: Write> TupleSerializer<'w, W> {
+    fn new(
+        writer: &'w mut EventWriter<W>,
+        name: Name<'static>,
+        wrapper: Option<Name<'static>>,
+    ) -> Self {
+        Self {
+            writer,
+            name,
+            items: Vec::new(),
+            wrapper,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        if let Some(item) = value.serialize(TextSerializer)? {
+            self.items.push(item);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        let elem = ElemSerializer {
+            writer: &mut *self.writer,
+            name: Some(self.name.value().to_owned()),
+            is_root: false,
+        };
+        elem.write_text(None, Some(self.items.join(" ")))?;
+
+        match self.wrapper {
+            Some(name) => self.writer.write_event(&XmlEvent::ETag(EndTag { name })),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> SerializeTuple for TupleSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: Write> SerializeTupleStruct for TupleSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: Write> SerializeTupleVariant for TupleSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+/// Writes the fields of a struct or the entries of a map as attributes,
+/// text and child elements.
+struct StructSerializer<'w, W: Write> {
+    writer: &'w mut EventWriter<W>,
+    name: Name<'static>,
+    /// The attributes of a start tag not written yet.
+    attrs: Vec<Attribute<'static>>,
+    is_started: bool,
+    /// The key of the map entry whose value comes next.
+    key: Option<String>,
+    /// The element wrapping a struct variant.
+    wrapper: Option<Name<'static>>,
+}
+
+impl<W: Write> StructSerializer<'_, W> {
+    fn start(&mut self) -> Result<()> {
+        if self.is_started {
+            return Ok(());
+        }
+        self.is_started = true;
+
+        self.writer.write_event(&XmlEvent::STag(StartTag {
+            name: self.name.clone(),
+            attrs: std::mem::take(&mut self.attrs),
+        }))
+    }
+
+    fn write_field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        if let Some(attr_name) = key.strip_prefix('@') {
+            if self.is_started {
+                return Err(<Error as ser::Error>::custom(format!(
+                    "attribute `{attr_name}` follows the content of `<{}>`",
+                    self.name.value()
+                )));
+            }
+            if let Some(value) = value.serialize(TextSerializer)? {
+                self.attrs.push(Attribute {
+                    name: check_name(attr_name)?,
+                    value: AttValue::new(value),
+                });
+            }
+            return Ok(());
+        }
+        self.start()?;
+
+        if key == "$text" {
+            return match value.serialize(TextSerializer)? {
+                Some(text) if !text.is_empty() => {
+                    self.writer.write_event(&XmlEvent::Text(Text::new(text)))
+                }
+                _ => Ok(()),
+            };
+        }
+        value.serialize(ElemSerializer {
+            writer: &mut *self.writer,
+            name: Some(key.to_owned()),
+            is_root: false,
+        })
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.is_started {
+            self.writer.write_event(&XmlEvent::ETag(EndTag {
+                name: self.name.clone(),
+            }))?;
+        } else {
+            self.writer.write_event(&XmlEvent::EmptyElem(EmptyElem {
+                name: self.name.clone(),
+                attrs: std::mem::take(&mut self.attrs),
+            }))?;
+        }
+
+        match self.wrapper {
+            Some(name) => self.writer.write_event(&XmlEvent::ETag(EndTag { name })),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> SerializeStruct for StructSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: Write> SerializeStructVariant for StructSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: Write> SerializeMap for StructSerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        let key = key
+            .serialize(TextSerializer)?
+            .ok_or_else(|| unsupported("`None` as a map key"))?;
+        self.key = Some(key);
+
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().expect("`serialize_key` comes first");
+        self.write_field(&key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+/// Turns a value into the text of an attribute or element, or `None` for
+/// `None`.
+struct TextSerializer;
+
+macro_rules! primitives_to_text {
+    ($($method:ident($ty:ty))*) => {
+        $(
+            fn $method(self, value: $ty) -> Result<Option<String>> {
+                Ok(Some(value.to_string()))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for TextSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+    type SerializeSeq = TextList;
+    type SerializeTuple = TextList;
+    type SerializeTupleStruct = TextList;
+    type SerializeTupleVariant = Impossible<Option<String>, Error>;
+    type SerializeMap = Impossible<Option<String>, Error>;
+    type SerializeStruct = Impossible<Option<String>, Error>;
+    type SerializeStructVariant = Impossible<Option<String>, Error>;
+
+    primitives_to_text! {
+        serialize_bool(bool) serialize_i8(i8) serialize_i16(i16) serialize_i32(i32)
+        serialize_i64(i64) serialize_i128(i128) serialize_u8(u8) serialize_u16(u16)
+        serialize_u32(u32) serialize_u64(u64) serialize_u128(u128) serialize_f32(f32)
+        serialize_f64(f64) serialize_char(char) serialize_str(&str)
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<Option<String>> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<String>> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<String>> {
+        Ok(Some(String::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<String>> {
+        Ok(Some(String::new()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Option<String>> {
+        Ok(Some(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<String>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Option<String>> {
+        Err(unsupported("a newtype variant in text"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<TextList> {
+        Ok(TextList(Vec::new()))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<TextList> {
+        Ok(TextList(Vec::new()))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<TextList> {
+        Ok(TextList(Vec::new()))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(unsupported("a tuple variant in text"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(unsupported("a map in text"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(unsupported("a struct in text"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported("a struct variant in text"))
+    }
+}
+
+/// Values separated by spaces, like `xs:list`.
+struct TextList(Vec<String>);
+
+impl TextList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        if let Some(item) = value.serialize(TextSerializer)? {
+            self.0.push(item);
+        }
+        Ok(())
+    }
+}
+
+impl SerializeSeq for TextList {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>> {
+        Ok(Some(self.0.join(" ")))
+    }
+}
+
+impl SerializeTuple for TextList {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>> {
+        Ok(Some(self.0.join(" ")))
+    }
+}
+
+impl SerializeTupleStruct for TextList {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>> {
+        Ok(Some(self.0.join(" ")))
+    }
+}
@@ -0,0 +1,513 @@
+mod format;
+
+pub use format::{FormatConfig, Formatter};
+
+use crate::encoding::EncWriter;
+use crate::error::{Result, SyntaxError, WriteError};
+use crate::escape;
+use crate::reader::dtd::{DocTypeDecl, ExternalId};
+use crate::reader::{Attribute, Layout, Pi, Quote, XmlDecl, XmlEvent};
+use crate::token::rules::{accept_as_char, accept_as_name, accept_as_white_space};
+use crate::token::{AttValue, Literal, Name};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use std::borrow::Cow;
+use std::io::Write;
+use std::{iter, slice};
+
+/// How the writer spells elements without content.
+#[derive(PartialEq, Eq, Clone, Copy, Default, Debug)]
+pub enum EmptyElements {
+    /// `EmptyElem` as `<a/>`, a `STag` directly followed by its `ETag` as
+    /// `<a></a>`.
+    #[default]
+    AsWritten,
+    /// Both as `<a/>`.
+    SelfClosing,
+    /// Both as `<a></a>`.
+    Expanded,
+}
+
+#[derive(Clone, Debug)]
+pub struct WriterConfig {
+    pub empty_elements: EmptyElements,
+    /// The label of the encoding to write in, as understood by `encoding_rs`.
+    pub encoding: String,
+    /// Write `Text` and `AttValue` exactly as they are, expecting them read
+    /// with `ReaderConfig::raw_references`.
+    pub raw_references: bool,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            empty_elements: EmptyElements::default(),
+            encoding: "UTF-8".to_owned(),
+            raw_references: false,
+        }
+    }
+}
+
+/// The part of a `Layout` that is still to be written.
+#[derive(Default)]
+struct Spelling<'l> {
+    spaces: slice::Iter<'l, String>,
+    quotes: slice::Iter<'l, Quote>,
+}
+
+impl<'l> Spelling<'l> {
+    fn new(layout: &'l Layout) -> Self {
+        Self {
+            spaces: layout.spaces.iter(),
+            quotes: layout.quotes.iter(),
+        }
+    }
+
+    /// The next white space of the layout, or `default` past its end.
+    fn space<'s>(&mut self, default: &'s str) -> &'s str
+    where
+        'l: 's,
+    {
+        self.spaces.next().map_or(default, String::as_str)
+    }
+
+    /// The last white space of the layout, which stays in place when
+    /// attributes are added or removed.
+    fn last_space<'s>(&mut self, default: &'s str) -> &'s str
+    where
+        'l: 's,
+    {
+        self.spaces.next_back().map_or(default, String::as_str)
+    }
+
+    /// The next quote of the layout, unless `literal` contains it.
+    fn quote(&mut self, literal: &str) -> &'static str {
+        let quote = self.quotes.next().copied().unwrap_or_default();
+
+        match quote {
+            Quote::Double if literal.contains('"') => Quote::Single,
+            Quote::Single if literal.contains('\'') => Quote::Double,
+            quote => quote,
+        }
+        .as_str()
+    }
+}
+
+/// Writes events as well-formed XML, escaping text and attribute values
+/// and checking that tags are balanced. Values are expected decoded, as
+/// `EventReader` returns them by default, unless
+/// `WriterConfig::raw_references` is set.
+pub struct EventWriter<W: Write> {
+    out: EncWriter<W>,
+    config: WriterConfig,
+    path: Vec<String>,
+    /// The `>` of the last start tag is still to be written.
+    is_in_s_tag: bool,
+    is_started: bool,
+    is_after_root: bool,
+}
+
+impl<W: Write> EventWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_config(writer, WriterConfig::default()).expect("UTF-8 is supported")
+    }
+
+    /// Fails if `config.encoding` isn't an encoding `encoding_rs` can write.
+    pub fn with_config(writer: W, config: WriterConfig) -> Result<Self> {
+        Ok(Self {
+            out: EncWriter::new(writer, &config.encoding)?,
+            config,
+            path: Vec::new(),
+            is_in_s_tag: false,
+            is_started: false,
+            is_after_root: false,
+        })
+    }
+
+    pub fn config(&self) -> &WriterConfig {
+        &self.config
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.out.get_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner()
+    }
+
+    /// Writes `event`. `XmlEvent::Eof` checks that the document is complete
+    /// and flushes the output.
+    pub fn write_event(&mut self, event: &XmlEvent) -> Result<()> {
+        self.write_spelled(event, &Layout::default())
+    }
+
+    /// Writes `event` spelled as `layout` tells, as `EventReader::layout`
+    /// returns it. A document read with `ReaderConfig::raw_references` from
+    /// a `Parser` with `raw_line_endings`, and written in the same encoding
+    /// with `WriterConfig::raw_references`, comes out byte for byte as it
+    /// went in.
+    pub fn write_spelled(&mut self, event: &XmlEvent, layout: &Layout) -> Result<()> {
+        let sp = &mut Spelling::new(layout);
+
+        if layout.bom {
+            self.out.write_bom()?;
+        }
+
+        if let XmlEvent::ETag(e_tag) = event {
+            return self.write_e_tag(&e_tag.name, sp);
+        }
+        self.close_s_tag()?;
+
+        match event {
+            XmlEvent::Xml(xml_decl) => self.write_xml_decl(xml_decl, sp),
+            XmlEvent::DocType(doctype) => self.write_doctype(doctype, sp),
+            XmlEvent::Pi(pi) => self.write_pi(pi, sp),
+            XmlEvent::STag(s_tag) => self.write_s_tag(&s_tag.name, &s_tag.attrs, " ", sp),
+            XmlEvent::EmptyElem(empty_elem) => {
+                self.write_empty_elem(&empty_elem.name, &empty_elem.attrs, " ", sp)
+            }
+            XmlEvent::ETag(_) => unreachable!(),
+            XmlEvent::Text(text) => {
+                self.check_content(text.value().chars().all(accept_as_white_space))?;
+                check_chars(text.value())?;
+
+                if self.config.raw_references {
+                    self.write_escaped(text.value())
+                } else {
+                    self.write_escaped(&escape::escape_text(text.value()))
+                }
+            }
+            XmlEvent::CData(cdata) => {
+                self.check_content(false)?;
+                check_chars(cdata.value())?;
+                // `]]>` can only be written by splitting the section.
+                let cdata = cdata.value().replace("]]>", "]]]]><![CDATA[>");
+
+                self.write_markup("<![CDATA[")?;
+                self.write_markup(&cdata)?;
+                self.write_markup("]]>")
+            }
+            XmlEvent::S(s) => self.write_markup(s.value()),
+            XmlEvent::Comment(comment) => {
+                let comment = comment.value();
+
+                if comment.contains("--") || comment.ends_with('-') {
+                    return Err(SyntaxError::UnexpectedDelimiter("`--`").into());
+                }
+                check_chars(comment)?;
+                self.write_markup("<!--")?;
+                self.write_markup(comment)?;
+                self.write_markup("-->")
+            }
+            XmlEvent::Eof => self.finish(),
+        }
+    }
+
+    fn write_markup(&mut self, markup: &str) -> Result<()> {
+        self.is_started = true;
+        self.out.write_str(markup, false)
+    }
+
+    fn write_escaped(&mut self, escaped: &str) -> Result<()> {
+        self.is_started = true;
+        self.out.write_str(escaped, true)
+    }
+
+    /// Only white space may appear outside the root element.
+    fn check_content(&self, is_blank: bool) -> Result<()> {
+        if !self.path.is_empty() || is_blank {
+            Ok(())
+        } else if self.is_after_root {
+            Err(SyntaxError::ContentAfterRoot.into())
+        } else {
+            Err(SyntaxError::ContentBeforeRoot.into())
+        }
+    }
+
+    fn write_xml_decl(&mut self, xml_decl: &XmlDecl, sp: &mut Spelling) -> Result<()> {
+        if self.is_started {
+            return Err(WriteError::MisplacedXmlDecl.into());
+        }
+        if let Some(encoding) = &xml_decl.encoding {
+            let declared = Encoding::for_label(encoding.value().as_bytes());
+            let is_utf16 = |encoding| encoding == UTF_16LE || encoding == UTF_16BE;
+            let written = self.out.encoding();
+
+            let conflicts = match declared {
+                Some(declared) => declared != written && !(is_utf16(declared) && is_utf16(written)),
+                None => true,
+            };
+            if conflicts {
+                return Err(SyntaxError::EncodingConflict {
+                    detected: written.name(),
+                    declared: encoding.value().to_owned(),
+                }
+                .into());
+            }
+        }
+        for value in iter::once(&xml_decl.version)
+            .chain(&xml_decl.encoding)
+            .chain(&xml_decl.standalone)
+        {
+            check_quotable(value.value())?;
+        }
+        let is_last = |next: Option<&AttValue>| next.is_none() && xml_decl.standalone.is_none();
+
+        self.write_markup("<?xml")?;
+        self.write_markup(sp.space(" "))?;
+        self.write_markup("version")?;
+        self.write_pseudo_attr(&xml_decl.version, is_last(xml_decl.encoding.as_ref()), sp)?;
+
+        if let Some(encoding) = &xml_decl.encoding {
+            self.write_markup("encoding")?;
+            self.write_pseudo_attr(encoding, is_last(None), sp)?;
+        }
+        if let Some(standalone) = &xml_decl.standalone {
+            self.write_markup("standalone")?;
+            self.write_pseudo_attr(standalone, true, sp)?;
+        }
+        self.write_markup("?>")
+    }
+
+    /// Writes the `Eq` and the value of a pseudo-attribute, and the white
+    /// space after it.
+    fn write_pseudo_attr(
+        &mut self,
+        value: &AttValue,
+        is_last: bool,
+        sp: &mut Spelling,
+    ) -> Result<()> {
+        self.write_eq(sp)?;
+        self.write_quoted(value.value(), sp)?;
+        self.write_markup(sp.space(if is_last { "" } else { " " }))
+    }
+
+    fn write_eq(&mut self, sp: &mut Spelling) -> Result<()> {
+        self.write_markup(sp.space(""))?;
+        self.write_markup("=")?;
+        self.write_markup(sp.space(""))
+    }
+
+    fn write_doctype(&mut self, doctype: &DocTypeDecl, sp: &mut Spelling) -> Result<()> {
+        if !self.path.is_empty() || self.is_after_root {
+            return Err(SyntaxError::InvalidMarkup.into());
+        }
+        check_name(doctype.name.value())?;
+
+        match &doctype.external_id {
+            Some(ExternalId::System(system)) => check_quotable(system.value())?,
+            Some(ExternalId::Public(pubid, system)) => {
+                check_quotable(pubid.value())?;
+                system
+                    .iter()
+                    .try_for_each(|system| check_quotable(system.value()))?;
+            }
+            None => {}
+        }
+        let has_subset = doctype.internal_subset.is_some();
+        let separator = |is_followed: bool| if is_followed { " " } else { "" };
+
+        self.write_markup("<!DOCTYPE")?;
+        self.write_markup(sp.space(" "))?;
+        self.write_markup(doctype.name.value())?;
+        self.write_markup(sp.space(separator(doctype.external_id.is_some() || has_subset)))?;
+
+        if let Some(external_id) = &doctype.external_id {
+            match external_id {
+                ExternalId::System(system) => {
+                    self.write_markup("SYSTEM")?;
+                    self.write_markup(sp.space(" "))?;
+                    self.write_quoted(system.value(), sp)?;
+                }
+                ExternalId::Public(pubid, system) => {
+                    self.write_markup("PUBLIC")?;
+                    self.write_markup(sp.space(" "))?;
+                    self.write_quoted(pubid.value(), sp)?;
+
+                    if let Some(system) = system {
+                        self.write_markup(sp.space(" "))?;
+                        self.write_quoted(system.value(), sp)?;
+                    }
+                }
+            }
+            self.write_markup(sp.space(separator(has_subset)))?;
+        }
+        if let Some(subset) = &doctype.internal_subset {
+            self.write_markup("[")?;
+            self.write_markup(subset.value())?;
+            self.write_markup("]")?;
+            self.write_markup(sp.space(""))?;
+        }
+        self.write_markup(">")
+    }
+
+    /// Writes a literal in the quotes of the layout, or in whichever quotes
+    /// it doesn't contain, as `check_quotable` made sure there is one.
+    fn write_quoted(&mut self, literal: &str, sp: &mut Spelling) -> Result<()> {
+        let quote = sp.quote(literal);
+
+        self.write_markup(quote)?;
+        self.write_markup(literal)?;
+        self.write_markup(quote)
+    }
+
+    fn write_pi(&mut self, pi: &Pi, sp: &mut Spelling) -> Result<()> {
+        let target = pi.target.value();
+
+        if target.eq_ignore_ascii_case("xml") {
+            return Err(SyntaxError::ReservedPiTarget(target.to_owned()).into());
+        }
+        check_name(target)?;
+
+        if let Some(data) = &pi.data {
+            if data.value().contains("?>") {
+                return Err(SyntaxError::UnexpectedDelimiter("`?>`").into());
+            }
+            check_chars(data.value())?;
+        }
+        self.write_markup("<?")?;
+        self.write_markup(target)?;
+
+        if let Some(data) = &pi.data {
+            self.write_markup(sp.space(" "))?;
+            self.write_markup(data.value())?;
+        } else {
+            self.write_markup(sp.space(""))?;
+        }
+        self.write_markup("?>")
+    }
+
+    /// Writes a start tag up to its `>`, with `separator` before every
+    /// attribute the layout has no white space for.
+    fn write_s_tag(
+        &mut self,
+        name: &Name,
+        attrs: &[Attribute],
+        separator: &str,
+        sp: &mut Spelling,
+    ) -> Result<()> {
+        if self.is_after_root {
+            return Err(SyntaxError::ContentAfterRoot.into());
+        }
+        check_name(name.value())?;
+
+        for (i, attr) in attrs.iter().enumerate() {
+            check_name(attr.name.value())?;
+            check_chars(attr.value.value())?;
+
+            if attrs[..i]
+                .iter()
+                .any(|other| other.name.is(attr.name.value()))
+            {
+                return Err(SyntaxError::DuplicateAttribute(attr.name.value().to_owned()).into());
+            }
+        }
+        let trailing = sp.last_space("");
+
+        self.write_markup("<")?;
+        self.write_markup(name.value())?;
+
+        for attr in attrs {
+            let value = if self.config.raw_references {
+                Cow::Borrowed(attr.value.value())
+            } else {
+                escape::escape_att_value(attr.value.value())
+            };
+            let quote = sp.quote(&value);
+
+            self.write_markup(sp.space(separator))?;
+            self.write_markup(attr.name.value())?;
+            self.write_eq(sp)?;
+            self.write_markup(quote)?;
+            self.write_escaped(&value)?;
+            self.write_markup(quote)?;
+        }
+        self.write_markup(trailing)?;
+        self.path.push(name.value().to_owned());
+        self.is_in_s_tag = true;
+
+        Ok(())
+    }
+
+    fn write_empty_elem(
+        &mut self,
+        name: &Name,
+        attrs: &[Attribute],
+        separator: &str,
+        sp: &mut Spelling,
+    ) -> Result<()> {
+        self.write_s_tag(name, attrs, separator, sp)?;
+
+        if self.config.empty_elements == EmptyElements::Expanded {
+            return self.write_e_tag(name, sp);
+        }
+        self.is_in_s_tag = false;
+        self.path.pop();
+        self.is_after_root = self.path.is_empty();
+        self.write_markup("/>")
+    }
+
+    fn close_s_tag(&mut self) -> Result<()> {
+        if !self.is_in_s_tag {
+            return Ok(());
+        }
+        self.is_in_s_tag = false;
+        self.write_markup(">")
+    }
+
+    fn write_e_tag(&mut self, name: &Name, sp: &mut Spelling) -> Result<()> {
+        match self.path.last() {
+            Some(open) if name.is(open) => {}
+            open => {
+                return Err(SyntaxError::MismatchedEndTag {
+                    expected: open.cloned().unwrap_or_default(),
+                    found: name.value().to_owned(),
+                }
+                .into())
+            }
+        }
+        self.path.pop();
+        self.is_after_root = self.path.is_empty();
+
+        if self.is_in_s_tag && self.config.empty_elements == EmptyElements::SelfClosing {
+            self.is_in_s_tag = false;
+            return self.write_markup("/>");
+        }
+        self.close_s_tag()?;
+        self.write_markup("</")?;
+        self.write_markup(name.value())?;
+        self.write_markup(sp.space(""))?;
+        self.write_markup(">")
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(open) = self.path.last() {
+            return Err(SyntaxError::UnclosedElement(open.clone()).into());
+        }
+        if !self.is_after_root {
+            return Err(SyntaxError::MissingRootElement.into());
+        }
+        self.out.finish()
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(accept_as_name()) {
+        return Err(WriteError::InvalidName(name.to_owned()).into());
+    }
+    Ok(())
+}
+
+fn check_quotable(literal: &str) -> Result<()> {
+    if literal.contains('\'') && literal.contains('"') {
+        return Err(WriteError::UnquotableLiteral(literal.to_owned()).into());
+    }
+    Ok(())
+}
+
+fn check_chars(text: &str) -> Result<()> {
+    match text.chars().find(|&ch| !accept_as_char(ch)) {
+        Some(ch) => Err(WriteError::InvalidChar(ch).into()),
+        None => Ok(()),
+    }
+}
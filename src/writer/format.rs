@@ -0,0 +1,250 @@
+use super::{EventWriter, Spelling};
+use crate::error::{Result, SyntaxError};
+use crate::escape;
+use crate::reader::{Attribute, EmptyElem, StartTag, XmlEvent};
+use crate::token::rules::accept_as_white_space;
+use crate::token::{Literal, Name, Text, S};
+use std::io::Write;
+use std::mem;
+
+#[derive(Clone, Debug)]
+pub struct FormatConfig {
+    /// Written once per level of nesting at the start of every line.
+    pub indent: String,
+    /// Start tags longer than this, indentation included, get every
+    /// attribute on a line of its own.
+    pub line_width: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent: "  ".to_owned(),
+            line_width: 80,
+        }
+    }
+}
+
+/// How the content of an open element is written.
+enum Content<'a> {
+    /// Not known until the first child element, text or CDATA section, so
+    /// the white space, comments and PIs before it are held back.
+    Undecided(Vec<XmlEvent<'a>>),
+    /// Every child on a line of its own, white space left out.
+    Indented { is_empty: bool },
+    /// Mixed content, or content under `xml:space="preserve"`, as it is.
+    Verbatim,
+}
+
+/// Re-indents the events it is given before passing them to an
+/// `EventWriter`. An element has mixed content if its first child element,
+/// text or CDATA section is text or CDATA, so only what comes before that
+/// is held back. Mixed content and content under `xml:space="preserve"` are
+/// written as they are, and so is the rest of an element once text follows
+/// one of its child elements.
+pub struct Formatter<'a, W: Write> {
+    writer: EventWriter<W>,
+    config: FormatConfig,
+    /// The content of every open element, outermost first.
+    open: Vec<Content<'a>>,
+    /// The names of the open elements, for the error on an early `Eof`.
+    path: Vec<Name<'a>>,
+    is_started: bool,
+}
+
+impl<'a, W: Write> Formatter<'a, W> {
+    pub fn new(writer: EventWriter<W>, config: FormatConfig) -> Self {
+        Self {
+            writer,
+            config,
+            open: Vec::new(),
+            path: Vec::new(),
+            is_started: false,
+        }
+    }
+
+    pub fn config(&self) -> &FormatConfig {
+        &self.config
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.get_ref()
+    }
+
+    pub fn into_inner(self) -> EventWriter<W> {
+        self.writer
+    }
+
+    pub fn write_event(&mut self, event: XmlEvent<'a>) -> Result<()> {
+        let depth = self.open.len();
+
+        // The held back events would never reach the writer.
+        if let (XmlEvent::Eof, Some(name)) = (&event, self.path.last()) {
+            return Err(SyntaxError::UnclosedElement(name.value().to_owned()).into());
+        }
+
+        let Some(content) = self.open.last_mut() else {
+            return self.write_top_level(event);
+        };
+        match content {
+            Content::Verbatim => self.write_verbatim(event),
+            Content::Undecided(held) => match event {
+                XmlEvent::S(_) | XmlEvent::Comment(_) | XmlEvent::Pi(_) => {
+                    held.push(event);
+                    Ok(())
+                }
+                XmlEvent::Text(text) if is_blank(&text) => {
+                    held.push(XmlEvent::Text(text));
+                    Ok(())
+                }
+                XmlEvent::Text(_) | XmlEvent::CData(_) => {
+                    let held = mem::take(held);
+                    *content = Content::Verbatim;
+
+                    for event in held {
+                        self.writer.write_event(&event)?;
+                    }
+                    self.write_verbatim(event)
+                }
+                event => {
+                    let held = mem::take(held);
+                    *content = Content::Indented { is_empty: true };
+
+                    for event in held {
+                        self.write_indented(event, depth)?;
+                    }
+                    self.write_indented(event, depth)
+                }
+            },
+            Content::Indented { .. } => self.write_indented(event, depth),
+        }
+    }
+
+    /// Writes a node of the prolog, the start of the root element or a node
+    /// of the epilog on a line of its own.
+    fn write_top_level(&mut self, event: XmlEvent<'a>) -> Result<()> {
+        match event {
+            XmlEvent::S(_) => Ok(()),
+            XmlEvent::Text(text) if is_blank(&text) => Ok(()),
+            XmlEvent::ETag(_) => self.writer.write_event(&event),
+            XmlEvent::Eof => {
+                self.write_s("\n")?;
+                self.writer.write_event(&XmlEvent::Eof)
+            }
+            event => {
+                if self.is_started {
+                    self.write_s("\n")?;
+                }
+                self.is_started = true;
+
+                self.write_child(event, 0)
+            }
+        }
+    }
+
+    /// Writes an event of the element-only content of the element open at
+    /// `depth`, which starts at 1.
+    fn write_indented(&mut self, event: XmlEvent<'a>, depth: usize) -> Result<()> {
+        match event {
+            XmlEvent::S(_) => Ok(()),
+            XmlEvent::Text(text) if is_blank(&text) => Ok(()),
+            // Text after a child element makes the rest mixed content.
+            XmlEvent::Text(_) | XmlEvent::CData(_) => {
+                self.open[depth - 1] = Content::Verbatim;
+                self.writer.write_event(&event)
+            }
+            XmlEvent::ETag(_) => {
+                if let Content::Indented { is_empty: false } = self.open[depth - 1] {
+                    self.write_s(&self.line_start(depth - 1))?;
+                }
+                self.write_verbatim(event)
+            }
+            event => {
+                self.open[depth - 1] = Content::Indented { is_empty: false };
+                self.write_s(&self.line_start(depth))?;
+                self.write_child(event, depth)
+            }
+        }
+    }
+
+    /// Writes an event of an element's content as it is.
+    fn write_verbatim(&mut self, event: XmlEvent<'a>) -> Result<()> {
+        match &event {
+            XmlEvent::STag(s_tag) => {
+                self.path.push(s_tag.name.clone());
+                self.open.push(Content::Verbatim);
+            }
+            XmlEvent::ETag(_) => {
+                self.path.pop();
+                self.open.pop();
+            }
+            _ => {}
+        }
+        self.writer.write_event(&event)
+    }
+
+    /// Writes a node at `depth`, breaking the attributes of a start tag
+    /// over lines if they don't fit.
+    fn write_child(&mut self, event: XmlEvent<'a>, depth: usize) -> Result<()> {
+        let (XmlEvent::STag(StartTag { name, attrs })
+        | XmlEvent::EmptyElem(EmptyElem { name, attrs })) = &event
+        else {
+            return self.writer.write_event(&event);
+        };
+        let separator = self.attr_separator(name, attrs, depth);
+
+        self.writer.close_s_tag()?;
+        if let XmlEvent::EmptyElem(_) = event {
+            return self
+                .writer
+                .write_empty_elem(name, attrs, &separator, &mut Spelling::default());
+        }
+        self.writer
+            .write_s_tag(name, attrs, &separator, &mut Spelling::default())?;
+
+        let preserve = attrs
+            .iter()
+            .any(|attr| attr.name.is("xml:space") && attr.value.is("preserve"));
+
+        self.path.push(name.clone());
+        self.open.push(if preserve {
+            Content::Verbatim
+        } else {
+            Content::Undecided(Vec::new())
+        });
+        Ok(())
+    }
+
+    fn write_s(&mut self, s: &str) -> Result<()> {
+        self.writer.write_event(&XmlEvent::S(S::new(s.to_owned())))
+    }
+
+    fn line_start(&self, depth: usize) -> String {
+        format!("\n{}", self.config.indent.repeat(depth))
+    }
+
+    /// Puts attributes on lines of their own if the start tag wouldn't fit
+    /// on one.
+    fn attr_separator(&self, name: &Name, attrs: &[Attribute], depth: usize) -> String {
+        let width = self.config.indent.chars().count() * depth
+            + name.value().chars().count()
+            + attrs
+                .iter()
+                .map(|attr| {
+                    let value = escape::escape_att_value(attr.value.value());
+                    attr.name.value().chars().count() + value.chars().count() + 4
+                })
+                .sum::<usize>()
+            + 3;
+
+        if attrs.len() > 1 && width > self.config.line_width {
+            self.line_start(depth + 1)
+        } else {
+            " ".to_owned()
+        }
+    }
+}
+
+fn is_blank(text: &Text) -> bool {
+    text.value().chars().all(accept_as_white_space)
+}
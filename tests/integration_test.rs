@@ -1,6 +1,34 @@
-use std::error::Error;
+use xenna::dom::xpath::{Context, NodeRef, Value, XPath};
+use xenna::dom::{Document, Element, Node};
+use xenna::error::{Error, SyntaxError, WriteError};
+use xenna::parse::{ParseSource, Parser, SliceParser};
+#[cfg(feature = "tokio")]
+use xenna::reader::async_reader::AsyncEventReader;
+use xenna::reader::dtd::{
+    AttDef, AttListDecl, AttType, ContentParticle, ContentSpec, DefaultDecl, DocTypeDecl,
+    ElementDecl, EntityDecl, EntityDef, ExternalId, MarkupDecl, NotationDecl, Particles,
+    Repetition,
+};
+use xenna::reader::ns::{NsAttribute, NsEndTag, NsEvent, NsReader, NsStartTag, QName};
+use xenna::reader::path::{PathPattern, PathReader};
+use xenna::reader::push::PushReader;
+use xenna::reader::{
+    Attribute, EmptyElem, EndTag, EntityLimits, EventReader, Layout, Pi, Quote, ReaderConfig,
+    StartTag, XmlDecl, XmlEvent,
+};
+#[cfg(feature = "serde")]
+use xenna::ser::Serializer;
+use xenna::token::{
+    AttValue, CData, Comment, EntityValue, Literal, Name, Nmtoken, PiData, PubidLiteral,
+    SystemLiteral, Text,
+};
+use xenna::writer::{EmptyElements, EventWriter, FormatConfig, Formatter, WriterConfig};
+use xenna::Position;
 
-use xenna::reader::{EventReader, XmlEvent};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::BufReader;
 
 const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 <note>
@@ -12,7 +40,7 @@ const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 "#;
 
 #[test]
-fn parse_simple_xml() -> Result<(), Box<dyn Error>> {
+fn parse_simple_xml() -> Result<(), Box<dyn std::error::Error>> {
     let mut reader = EventReader::from(XML.as_bytes());
 
     loop {
@@ -24,3 +52,2534 @@ fn parse_simple_xml() -> Result<(), Box<dyn Error>> {
         }
     }
 }
+
+#[test]
+fn parse_empty_elem() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(r#"<list><br/><item id="1" name='' /></list>"#.as_bytes());
+
+    assert!(matches!(reader.next_event()?, XmlEvent::STag(_)));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::EmptyElem(EmptyElem {
+            name: Name::new("br"),
+            attrs: vec![],
+        })
+    );
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::EmptyElem(EmptyElem {
+            name: Name::new("item"),
+            attrs: vec![
+                Attribute {
+                    name: Name::new("id"),
+                    value: AttValue::new("1"),
+                },
+                Attribute {
+                    name: Name::new("name"),
+                    value: AttValue::new(""),
+                },
+            ],
+        })
+    );
+    assert!(matches!(reader.next_event()?, XmlEvent::ETag(_)));
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    Ok(())
+}
+
+#[test]
+fn expand_empty_elem() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ReaderConfig {
+        expand_empty_elements: true,
+        ..Default::default()
+    };
+    let mut reader =
+        EventReader::with_config(Parser::new(r#"<item id="1"/>"#.as_bytes(), "UTF-8"), config);
+
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::STag(StartTag {
+            name: Name::new("item"),
+            attrs: vec![Attribute {
+                name: Name::new("id"),
+                value: AttValue::new("1"),
+            }],
+        })
+    );
+    assert_eq!(reader.path(), [Name::new("item")]);
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::ETag(EndTag {
+            name: Name::new("item"),
+        })
+    );
+    assert_eq!(reader.path(), []);
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    Ok(())
+}
+
+const CDATA_XML: &str = "<script>if (a <![CDATA[< b && c >]]> d)<![CDATA[]]></script>";
+
+#[test]
+fn parse_cdata() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(CDATA_XML.as_bytes());
+
+    assert!(matches!(reader.next_event()?, XmlEvent::STag(_)));
+    assert_eq!(reader.next_event()?, XmlEvent::Text(Text::new("if (a ")));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::CData(CData::new("< b && c >"))
+    );
+    assert_eq!(reader.next_event()?, XmlEvent::Text(Text::new(" d)")));
+    assert_eq!(reader.next_event()?, XmlEvent::CData(CData::new("")));
+    assert!(matches!(reader.next_event()?, XmlEvent::ETag(_)));
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    assert_eq!(
+        read_to_end("<a><![CDATA[abc"),
+        Err(SyntaxError::UnclosedDelimiter("`]]>`"))
+    );
+    assert_eq!(
+        read_to_end("<a><![CDATA[\u{1}]]></a>"),
+        Err(SyntaxError::InvalidChar('\u{1}'))
+    );
+    assert_eq!(read_to_end("<a><![CDATA[--]]></a>"), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn merge_cdata_into_text() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ReaderConfig {
+        merge_cdata: true,
+        ..Default::default()
+    };
+    let mut reader = EventReader::with_config(Parser::new(CDATA_XML.as_bytes(), "UTF-8"), config);
+
+    assert!(matches!(reader.next_event()?, XmlEvent::STag(_)));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("if (a < b && c > d)"))
+    );
+    assert!(matches!(reader.next_event()?, XmlEvent::ETag(_)));
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    Ok(())
+}
+
+const REFERENCES_XML: &str = r#"<show title="&lt;&#65;&#x42;&quot;">Tom &amp; Jerry</show>"#;
+
+#[test]
+fn decode_references() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(REFERENCES_XML.as_bytes());
+
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::STag(StartTag {
+            name: Name::new("show"),
+            attrs: vec![Attribute {
+                name: Name::new("title"),
+                value: AttValue::new("<AB\""),
+            }],
+        })
+    );
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("Tom & Jerry"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn keep_raw_references() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ReaderConfig {
+        raw_references: true,
+        ..Default::default()
+    };
+    let mut reader =
+        EventReader::with_config(Parser::new(REFERENCES_XML.as_bytes(), "UTF-8"), config);
+
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::STag(StartTag {
+            name: Name::new("show"),
+            attrs: vec![Attribute {
+                name: Name::new("title"),
+                value: AttValue::new("&lt;&#65;&#x42;&quot;"),
+            }],
+        })
+    );
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("Tom &amp; Jerry"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_references() {
+    let read_text = |xml: &str| {
+        let mut reader = EventReader::from(xml.as_bytes());
+        reader.next_event().map_err(syntax_error)?;
+        reader.next_event().map(|_| ()).map_err(syntax_error)
+    };
+
+    assert_eq!(
+        read_text("<a>&#0;</a>"),
+        Err(SyntaxError::InvalidCharRef("#0".to_owned()))
+    );
+    assert_eq!(
+        read_text("<a>&#xD800;</a>"),
+        Err(SyntaxError::InvalidCharRef("#xD800".to_owned()))
+    );
+    assert_eq!(
+        read_text("<a>&nbsp;</a>"),
+        Err(SyntaxError::UndefinedEntity("nbsp".to_owned()))
+    );
+    assert_eq!(
+        read_text("<a>Tom & Jerry</a>"),
+        Err(SyntaxError::UnclosedDelimiter("`;`"))
+    );
+}
+
+fn syntax_error(err: Error) -> SyntaxError {
+    match err {
+        Error::Syntax(err, _) => err,
+        err => panic!("expected a syntax error, got {err:?}"),
+    }
+}
+
+fn write_error(err: Error) -> WriteError {
+    match err {
+        Error::Write(err) => err,
+        err => panic!("expected a write error, got {err:?}"),
+    }
+}
+
+fn read_to_end(xml: &str) -> Result<(), SyntaxError> {
+    let mut reader = EventReader::from(xml.as_bytes());
+
+    loop {
+        match reader.next_event() {
+            Ok(XmlEvent::Eof) => return Ok(()),
+            Ok(_) => {}
+            Err(err) => return Err(syntax_error(err)),
+        }
+    }
+}
+
+#[test]
+fn report_well_formedness_errors() {
+    let mismatched = SyntaxError::MismatchedEndTag {
+        expected: "b".to_owned(),
+        found: "a".to_owned(),
+    };
+
+    assert_eq!(read_to_end("<a><b></a>"), Err(mismatched));
+    assert_eq!(
+        read_to_end("<a><b>"),
+        Err(SyntaxError::UnclosedElement("b".to_owned()))
+    );
+    assert_eq!(
+        read_to_end("<!-- no root -->"),
+        Err(SyntaxError::MissingRootElement)
+    );
+    assert_eq!(read_to_end("junk<a/>"), Err(SyntaxError::ContentBeforeRoot));
+    assert_eq!(read_to_end("<a/><b/>"), Err(SyntaxError::ContentAfterRoot));
+    assert_eq!(read_to_end("<a/>junk"), Err(SyntaxError::ContentAfterRoot));
+    assert_eq!(
+        read_to_end("<a>]]></a>"),
+        Err(SyntaxError::UnexpectedDelimiter("`]]>`"))
+    );
+    assert_eq!(
+        read_to_end("<a>some text]]></a>"),
+        Err(SyntaxError::UnexpectedDelimiter("`]]>`"))
+    );
+    assert_eq!(read_to_end("<a>< b/></a>"), Err(SyntaxError::InvalidMarkup));
+    assert_eq!(
+        read_to_end("<a/><!-- abc"),
+        Err(SyntaxError::UnclosedDelimiter("`-->`"))
+    );
+    assert_eq!(
+        read_to_end("<a><!--\u{1}--></a>"),
+        Err(SyntaxError::InvalidChar('\u{1}'))
+    );
+    assert_eq!(
+        read_to_end("<a><!-- x -- y --></a>"),
+        Err(SyntaxError::UnexpectedChar('-'))
+    );
+    assert_eq!(
+        EventReader::from("<a b='abc")
+            .next_event()
+            .map_err(syntax_error),
+        Err(SyntaxError::UnclosedDelimiter("`'`"))
+    );
+    assert_eq!(
+        read_to_end("<a b='abc'"),
+        Err(SyntaxError::UnclosedDelimiter("`>`"))
+    );
+}
+
+#[test]
+fn track_positions() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = "<?xml version=\"1.0\"?>\n<note>\r\n  <to>Tové</to>\r  <from a='1' b>";
+    let mut reader = EventReader::from(xml.as_bytes());
+    let mut positions = Vec::new();
+
+    let err = loop {
+        match reader.next_event() {
+            Ok(_) => {
+                let Position {
+                    line,
+                    column,
+                    byte_column,
+                    offset,
+                } = reader.position();
+                positions.push((line, column, byte_column, offset));
+            }
+            Err(err) => break err,
+        }
+    };
+
+    assert_eq!(
+        positions,
+        [
+            (1, 1, 1, 0),
+            (1, 22, 22, 21),
+            (2, 1, 1, 22),
+            (2, 7, 7, 28),
+            (3, 3, 3, 32),
+            (3, 7, 7, 36),
+            (3, 11, 12, 41),
+            (3, 16, 17, 46),
+        ]
+    );
+    assert_eq!(
+        err,
+        Error::Syntax(
+            SyntaxError::MismatchedToken("`=`"),
+            Position {
+                line: 4,
+                column: 17,
+                byte_column: 17,
+                offset: 63,
+            }
+        )
+    );
+    assert_eq!(err.to_string(), "expected `=` at 4:17");
+
+    Ok(())
+}
+
+#[test]
+fn count_offset_in_source_bytes() -> Result<(), Box<dyn std::error::Error>> {
+    let utf16: Vec<u8> = "<a>é</a>"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let mut reader = EventReader::new(Parser::new(utf16.as_slice(), "UTF-16LE"));
+
+    reader.next_event()?;
+    reader.next_event()?;
+    assert!(matches!(reader.next_event()?, XmlEvent::ETag(_)));
+    assert_eq!(
+        reader.position(),
+        Position {
+            line: 1,
+            column: 5,
+            byte_column: 9,
+            offset: 8,
+        }
+    );
+
+    let utf16: Vec<u8> = "<a>\r\n日本<b/></a>"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    let mut reader = EventReader::new(Parser::new(utf16.as_slice(), "UTF-16BE"));
+    let utf8 = "<a>\r\n日本<b/></a>";
+    let mut pushed = PushReader::new();
+    pushed.feed(utf8.as_bytes())?;
+    pushed.finish()?;
+
+    for _ in 0..3 {
+        reader.next_event()?;
+        pushed.next_event()?;
+    }
+    assert_eq!(
+        reader.position(),
+        Position {
+            line: 2,
+            column: 3,
+            byte_column: 5,
+            offset: 14,
+        }
+    );
+    assert_eq!(
+        pushed.position(),
+        Position {
+            line: 2,
+            column: 3,
+            byte_column: 7,
+            offset: 11,
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_pi_data() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = r#"<?xml-stylesheet   href="style.css?v=1" title='A &amp; B'?><?build?><doc/>"#;
+    let mut reader = EventReader::from(xml.as_bytes());
+
+    let XmlEvent::Pi(pi) = reader.next_event()? else {
+        panic!("expected a processing instruction");
+    };
+    assert_eq!(pi.target, Name::new("xml-stylesheet"));
+    assert_eq!(
+        pi.data,
+        Some(PiData::new(r#"href="style.css?v=1" title='A &amp; B'"#))
+    );
+    assert_eq!(
+        pi.pseudo_attributes()?,
+        [
+            Attribute {
+                name: Name::new("href"),
+                value: AttValue::new("style.css?v=1"),
+            },
+            Attribute {
+                name: Name::new("title"),
+                value: AttValue::new("A & B"),
+            },
+        ]
+    );
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Pi(Pi {
+            target: Name::new("build"),
+            data: None,
+        })
+    );
+
+    assert_eq!(
+        read_to_end("<a/><?pi abc"),
+        Err(SyntaxError::UnclosedDelimiter("`?>`"))
+    );
+    assert_eq!(
+        read_to_end("<a/><?pi"),
+        Err(SyntaxError::UnclosedDelimiter("`?>`"))
+    );
+    assert_eq!(
+        read_to_end("<a><?p \u{1}?></a>"),
+        Err(SyntaxError::InvalidChar('\u{1}'))
+    );
+    assert_eq!(read_to_end("<a><?p --?></a>"), Ok(()));
+
+    Ok(())
+}
+
+#[test]
+fn reject_misplaced_xml_decl() {
+    assert_eq!(
+        read_to_end(r#" <?xml version="1.0"?><doc/>"#),
+        Err(SyntaxError::ReservedPiTarget("xml".to_owned()))
+    );
+    assert_eq!(
+        read_to_end("<doc><?XML?></doc>"),
+        Err(SyntaxError::ReservedPiTarget("XML".to_owned()))
+    );
+}
+
+const DOCTYPE_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE note PUBLIC "-//W3C//DTD Note//EN" 'note.dtd' [
+    <!ELEMENT note (to+, (from | sender)?, body*)>
+    <!ELEMENT body (#PCDATA | b)*>
+    <!-- not the end ] -->
+    <!ATTLIST note id ID #REQUIRED kind (memo|letter) "memo">
+    <!ENTITY sig "[Jani]">
+    <!NOTATION gif PUBLIC "image/gif">
+]>
+<note id="n1"/>"#;
+
+#[test]
+fn parse_doctype() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(DOCTYPE_XML.as_bytes());
+
+    assert!(matches!(reader.next_event()?, XmlEvent::Xml(_)));
+    assert!(matches!(reader.next_event()?, XmlEvent::S(_)));
+
+    let XmlEvent::DocType(doctype) = reader.next_event()? else {
+        panic!("expected a document type declaration");
+    };
+    assert_eq!(doctype.name, Name::new("note"));
+    assert_eq!(
+        doctype.external_id,
+        Some(ExternalId::Public(
+            PubidLiteral::new("-//W3C//DTD Note//EN"),
+            Some(SystemLiteral::new("note.dtd"))
+        ))
+    );
+    assert!(doctype.internal_subset.as_ref().is_some_and(|subset| subset
+        .value()
+        .trim_end()
+        .ends_with(r#"<!NOTATION gif PUBLIC "image/gif">"#)));
+    assert!(doctype.markup_decls.is_empty());
+
+    assert!(matches!(reader.next_event()?, XmlEvent::S(_)));
+    assert!(matches!(reader.next_event()?, XmlEvent::EmptyElem(_)));
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    Ok(())
+}
+
+#[test]
+fn parse_internal_subset() -> Result<(), Box<dyn std::error::Error>> {
+    let config = ReaderConfig {
+        parse_internal_subset: true,
+        ..Default::default()
+    };
+    let mut reader = EventReader::with_config(Parser::new(DOCTYPE_XML.as_bytes(), "UTF-8"), config);
+
+    let doctype = loop {
+        if let XmlEvent::DocType(doctype) = reader.next_event()? {
+            break doctype;
+        }
+    };
+    let particle = |particles, repetition| ContentParticle {
+        particles,
+        repetition,
+    };
+    let element = |name| particle(Particles::Name(Name::new(name)), Repetition::Once);
+
+    assert_eq!(
+        doctype.markup_decls,
+        [
+            MarkupDecl::Element(ElementDecl {
+                name: Name::new("note"),
+                content_spec: ContentSpec::Children(particle(
+                    Particles::Seq(vec![
+                        particle(Particles::Name(Name::new("to")), Repetition::OneOrMore),
+                        particle(
+                            Particles::Choice(vec![element("from"), element("sender")]),
+                            Repetition::Optional
+                        ),
+                        particle(Particles::Name(Name::new("body")), Repetition::ZeroOrMore),
+                    ]),
+                    Repetition::Once
+                )),
+            }),
+            MarkupDecl::Element(ElementDecl {
+                name: Name::new("body"),
+                content_spec: ContentSpec::Mixed(vec![Name::new("b")]),
+            }),
+            MarkupDecl::Comment(Comment::new(" not the end ] ")),
+            MarkupDecl::AttList(AttListDecl {
+                name: Name::new("note"),
+                att_defs: vec![
+                    AttDef {
+                        name: Name::new("id"),
+                        att_type: AttType::Id,
+                        default: DefaultDecl::Required,
+                    },
+                    AttDef {
+                        name: Name::new("kind"),
+                        att_type: AttType::Enumeration(vec![
+                            Nmtoken::new("memo"),
+                            Nmtoken::new("letter")
+                        ]),
+                        default: DefaultDecl::Value(AttValue::new("memo")),
+                    },
+                ],
+            }),
+            MarkupDecl::Entity(EntityDecl {
+                parameter: false,
+                name: Name::new("sig"),
+                def: EntityDef::Internal(EntityValue::new("[Jani]")),
+            }),
+            MarkupDecl::Notation(NotationDecl {
+                name: Name::new("gif"),
+                external_id: ExternalId::Public(PubidLiteral::new("image/gif"), None),
+            }),
+        ]
+    );
+
+    Ok(())
+}
+
+const ENTITIES_XML: &str = r#"<!DOCTYPE note [
+    <!ENTITY name "Jani">
+    <!ENTITY sig "&#8212; &name;">
+    <!ENTITY name "ignored">
+]>
+<note by="&sig;">&sig; &amp; friends</note>"#;
+
+#[test]
+fn expand_internal_entities() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(ENTITIES_XML.as_bytes());
+
+    while !matches!(reader.next_event()?, XmlEvent::DocType(_)) {}
+    reader.next_event()?;
+
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::STag(StartTag {
+            name: Name::new("note"),
+            attrs: vec![Attribute {
+                name: Name::new("by"),
+                value: AttValue::new("\u{2014} Jani"),
+            }],
+        })
+    );
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("\u{2014} Jani & friends"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_entities_with_markup() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = r#"<!DOCTYPE a [
+    <!ENTITY e '<b x="&amp;&t;">&n;</b>'>
+    <!ENTITY n "&#60;i>&#38;#38;</i>">
+    <!ENTITY m "x&e;y">
+    <!ENTITY t "t">
+    <!ENTITY n "ignored">
+]><a>1&e;2&m;3&amp;</a>"#;
+    let mut reader = EventReader::from(xml.as_bytes());
+    let mut events = Vec::new();
+    let mut depths = Vec::new();
+
+    while !matches!(reader.next_event()?, XmlEvent::DocType(_)) {}
+    loop {
+        match reader.next_event()? {
+            XmlEvent::Eof => break,
+            event => events.push(event),
+        }
+        depths.push(reader.path().len());
+    }
+
+    let s_tag = |name, attrs| {
+        XmlEvent::STag(StartTag {
+            name: Name::new(name),
+            attrs,
+        })
+    };
+    let e_tag = |name| {
+        XmlEvent::ETag(EndTag {
+            name: Name::new(name),
+        })
+    };
+    let text = |text| XmlEvent::Text(Text::new(text));
+    let e = || {
+        [
+            s_tag(
+                "b",
+                vec![Attribute {
+                    name: Name::new("x"),
+                    value: AttValue::new("&t"),
+                }],
+            ),
+            s_tag("i", vec![]),
+            text("&"),
+            e_tag("i"),
+            e_tag("b"),
+        ]
+    };
+    let mut expected = vec![s_tag("a", vec![]), text("1")];
+    expected.extend(e());
+    expected.push(text("2x"));
+    expected.extend(e());
+    expected.extend([text("y3&"), e_tag("a")]);
+
+    assert_eq!(events, expected);
+    assert_eq!(depths, [1, 1, 2, 3, 3, 2, 1, 1, 2, 3, 3, 2, 1, 1, 0]);
+    assert_eq!(
+        read_pushed(xml.as_bytes().chunks(1)),
+        read_blocking(xml.as_bytes())
+    );
+
+    let xml = "<!DOCTYPE a [<!ENTITY e '<b/>'>]><a>&e;</a>";
+    let config = ReaderConfig {
+        expand_empty_elements: true,
+        merge_cdata: true,
+        ..Default::default()
+    };
+    let mut reader = EventReader::with_config(SliceParser::new(xml.as_bytes()), config);
+    while !matches!(reader.next_event()?, XmlEvent::DocType(_)) {}
+
+    assert_eq!(reader.next_event()?, s_tag("a", vec![]));
+    assert_eq!(reader.next_event()?, s_tag("b", vec![]));
+    assert_eq!(reader.next_event()?, e_tag("b"));
+    assert_eq!(reader.next_event()?, e_tag("a"));
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    Ok(())
+}
+
+const LAUGHS_XML: &str = r#"<!DOCTYPE lolz [
+    <!ENTITY lol "lol">
+    <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+    <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+    <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+    <!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+]>
+<lolz>&lol4;</lolz>"#;
+
+#[test]
+fn limit_entity_expansion() {
+    let read_with_limits = |xml: &str, entity_limits| {
+        let config = ReaderConfig {
+            entity_limits,
+            ..Default::default()
+        };
+        let mut reader = EventReader::with_config(Parser::new(xml.as_bytes(), "UTF-8"), config);
+
+        loop {
+            match reader.next_event() {
+                Ok(XmlEvent::Eof) => return Ok(()),
+                Ok(_) => {}
+                Err(err) => return Err(syntax_error(err)),
+            }
+        }
+    };
+
+    assert_eq!(
+        read_with_limits(LAUGHS_XML, EntityLimits::default()),
+        Ok(())
+    );
+    assert_eq!(
+        read_with_limits(
+            LAUGHS_XML,
+            EntityLimits {
+                max_expanded_len: 10_000,
+                ..Default::default()
+            }
+        ),
+        Err(SyntaxError::EntityExpansionLimit("max_expanded_len"))
+    );
+    assert_eq!(
+        read_with_limits(
+            LAUGHS_XML,
+            EntityLimits {
+                max_depth: 3,
+                ..Default::default()
+            }
+        ),
+        Err(SyntaxError::EntityExpansionLimit("max_depth"))
+    );
+    assert_eq!(
+        read_with_limits(
+            LAUGHS_XML,
+            EntityLimits {
+                max_amplification: 10.0,
+                amplification_threshold: 1_000,
+                ..Default::default()
+            }
+        ),
+        Err(SyntaxError::EntityExpansionLimit("max_amplification"))
+    );
+
+    // Retrying an event that was fed only in part mustn't count its
+    // entities again.
+    let xml = LAUGHS_XML.replace("</lolz>", "<![CDATA[lol]]></lolz>");
+    let mut reader = PushReader::with_config(ReaderConfig {
+        merge_cdata: true,
+        entity_limits: EntityLimits {
+            max_expanded_len: 200_000,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    for byte in xml.as_bytes() {
+        reader.feed(&[*byte]).unwrap();
+        while reader.next_event().unwrap().is_some() {}
+    }
+    reader.finish().unwrap();
+    assert_eq!(reader.next_event(), Ok(Some(XmlEvent::Eof)));
+}
+
+#[test]
+fn reject_unexpandable_entities() {
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY a "&b;"><!ENTITY b "&a;">]><a>&a;</a>"#),
+        Err(SyntaxError::RecursiveEntity("a".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b "<b/>">]><a x="&b;"/>"#),
+        Err(SyntaxError::MarkupInEntity("b".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b "<b>">]><a>&b;</b></a>"#),
+        Err(SyntaxError::UnclosedElement("b".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b "</a><a>">]><a>&b;</a>"#),
+        Err(SyntaxError::EndTagInEntity("a".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b "<b/>&b;">]><a>&b;</a>"#),
+        Err(SyntaxError::RecursiveEntity("b".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b SYSTEM "b.xml">]><a>&b;</a>"#),
+        Err(SyntaxError::UnsupportedExternalEntity("b".to_owned()))
+    );
+    assert_eq!(
+        read_to_end(r#"<!DOCTYPE a [<!ENTITY b "b">]><a>&c;</a>"#),
+        Err(SyntaxError::UndefinedEntity("c".to_owned()))
+    );
+}
+
+const NS_XML: &str = r#"<root xmlns="urn:root" xmlns:x="urn:x">
+<x:item x:id="1" id="2"/>
+<item xmlns=""><x:item/></item>
+</root>"#;
+
+#[test]
+fn resolve_namespaces() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = NsReader::from(NS_XML.as_bytes());
+    let qname = |name, namespace: Option<&str>| QName {
+        name: Name::new(name),
+        namespace: namespace.map(Into::into),
+    };
+    let mut next_tag = || loop {
+        match reader.next_event()? {
+            NsEvent::Other(XmlEvent::Text(_)) => {}
+            event => return Ok::<_, xenna::error::Error>(event),
+        }
+    };
+
+    let NsEvent::STag(root) = next_tag()? else {
+        panic!("expected the root start tag");
+    };
+    assert_eq!(root.name, qname("root", Some("urn:root")));
+    assert_eq!(
+        root.attrs[1].name,
+        qname("xmlns:x", Some("http://www.w3.org/2000/xmlns/"))
+    );
+
+    let NsEvent::EmptyElem(item) = next_tag()? else {
+        panic!("expected an empty element");
+    };
+    assert_eq!(item.name.prefix(), Some("x"));
+    assert_eq!(item.name.local_name(), "item");
+    assert_eq!(
+        item,
+        NsStartTag {
+            name: qname("x:item", Some("urn:x")),
+            attrs: vec![
+                NsAttribute {
+                    name: qname("x:id", Some("urn:x")),
+                    value: AttValue::new("1"),
+                },
+                NsAttribute {
+                    name: qname("id", None),
+                    value: AttValue::new("2"),
+                },
+            ],
+        }
+    );
+
+    assert!(matches!(next_tag()?, NsEvent::STag(tag) if tag.name == qname("item", None)));
+    assert!(
+        matches!(next_tag()?, NsEvent::EmptyElem(tag) if tag.name == qname("x:item", Some("urn:x")))
+    );
+    assert_eq!(
+        next_tag()?,
+        NsEvent::ETag(NsEndTag {
+            name: qname("item", None)
+        })
+    );
+    assert_eq!(
+        next_tag()?,
+        NsEvent::ETag(NsEndTag {
+            name: qname("root", Some("urn:root"))
+        })
+    );
+    assert_eq!(reader.namespace(None), Some("urn:root"));
+    assert_eq!(
+        reader.namespace(Some("xml")),
+        Some("http://www.w3.org/XML/1998/namespace")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reject_namespace_errors() {
+    let read_ns = |xml: &str| {
+        let mut reader = NsReader::from(xml.as_bytes());
+
+        loop {
+            match reader.next_event() {
+                Ok(NsEvent::Other(XmlEvent::Eof)) => return Ok(()),
+                Ok(_) => {}
+                Err(err) => return Err((syntax_error(err.clone()), err.position())),
+            }
+        }
+    };
+
+    assert_eq!(
+        read_ns("<a>\n  <x:b/>\n</a>"),
+        Err((
+            SyntaxError::UnboundPrefix("x".to_owned()),
+            Some(Position {
+                line: 2,
+                column: 3,
+                byte_column: 3,
+                offset: 6
+            })
+        ))
+    );
+    assert_eq!(
+        read_ns(r#"<a><b xmlns:x="urn:x"/><x:c/></a>"#).map_err(|(err, _)| err),
+        Err(SyntaxError::UnboundPrefix("x".to_owned()))
+    );
+    assert_eq!(
+        read_ns(r#"<a x:id="1"/>"#).map_err(|(err, _)| err),
+        Err(SyntaxError::UnboundPrefix("x".to_owned()))
+    );
+    assert_eq!(
+        read_ns("<a:b:c xmlns:a='urn:a'/>").map_err(|(err, _)| err),
+        Err(SyntaxError::InvalidQName("a:b:c".to_owned()))
+    );
+    assert_eq!(
+        read_ns("<a xmlns:x=''/>").map_err(|(err, _)| err),
+        Err(SyntaxError::InvalidNamespaceDecl("xmlns:x".to_owned()))
+    );
+    assert_eq!(
+        read_ns("<a xmlns:xml='urn:x'/>").map_err(|(err, _)| err),
+        Err(SyntaxError::InvalidNamespaceDecl("xmlns:xml".to_owned()))
+    );
+    assert_eq!(read_ns("<a xml:lang='en'/>"), Ok(()));
+}
+
+fn read_text<'a, T: ParseSource<'a>>(mut reader: EventReader<'a, T>) -> Result<String, Error> {
+    loop {
+        if let XmlEvent::Text(text) = reader.next_event()? {
+            return Ok(text.value().to_owned());
+        }
+    }
+}
+
+#[test]
+fn detect_encoding() -> Result<(), Box<dyn std::error::Error>> {
+    let utf16 = |xml: &str, bom: bool, to_bytes: fn(u16) -> [u8; 2]| {
+        let bom = bom.then_some(0xFEFF);
+        bom.into_iter()
+            .chain(xml.encode_utf16())
+            .flat_map(to_bytes)
+            .collect::<Vec<_>>()
+    };
+    let decl = r#"<?xml version="1.0" encoding="UTF-16"?><a>é</a>"#;
+
+    for xml in [
+        utf16(decl, true, u16::to_le_bytes),
+        utf16(decl, true, u16::to_be_bytes),
+        utf16(decl, false, u16::to_le_bytes),
+        utf16(decl, false, u16::to_be_bytes),
+        utf16("<a>é</a>", true, u16::to_be_bytes),
+    ] {
+        assert_eq!(read_text(EventReader::from(xml.as_slice()))?, "é");
+    }
+
+    let latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>\xE9</a>";
+    assert_eq!(read_text(EventReader::from(latin1.as_slice()))?, "é");
+    assert_eq!(
+        Parser::new(latin1.as_slice(), "ISO-8859-1").encoding(),
+        "windows-1252"
+    );
+
+    let utf8 = "\u{FEFF}<?xml version=\"1.0\"?><a>é</a>";
+    assert_eq!(read_text(EventReader::from(utf8.as_bytes()))?, "é");
+
+    // Short reads split the byte order mark and the XML declaration.
+    let utf16_decl = utf16(decl, true, u16::to_be_bytes);
+    for xml in [latin1.as_slice(), utf8.as_bytes(), &utf16_decl] {
+        for capacity in [1, 3] {
+            let src = BufReader::with_capacity(capacity, xml);
+            assert_eq!(read_text(EventReader::new(Parser::from_reader(src)))?, "é");
+        }
+    }
+
+    let mut reader = EventReader::from(utf8.as_bytes());
+    reader.next_event()?;
+    reader.next_event()?;
+    assert_eq!(reader.position().offset, 24);
+
+    Ok(())
+}
+
+#[test]
+fn reject_conflicting_encodings() {
+    let utf16: Vec<u8> = "\u{FEFF}<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a/>"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+
+    assert_eq!(
+        read_text(EventReader::from(utf16.as_slice())).map_err(syntax_error),
+        Err(SyntaxError::EncodingConflict {
+            detected: "UTF-16LE",
+            declared: "ISO-8859-1".to_owned(),
+        })
+    );
+    assert_eq!(
+        read_to_end("\u{FEFF}<?xml version=\"1.0\" encoding=\"Shift_JIS\"?><a/>"),
+        Err(SyntaxError::EncodingConflict {
+            detected: "UTF-8",
+            declared: "Shift_JIS".to_owned(),
+        })
+    );
+    assert_eq!(
+        read_to_end("<?xml version=\"1.0\" encoding=\"UTF-16\"?><a/>"),
+        Err(SyntaxError::EncodingConflict {
+            detected: "UTF-8",
+            declared: "UTF-16".to_owned(),
+        })
+    );
+    assert_eq!(
+        read_to_end("<?xml version=\"1.0\" encoding=\"klingon\"?><a/>"),
+        Err(SyntaxError::UnsupportedEncoding("klingon".to_owned()))
+    );
+}
+
+#[test]
+fn read_past_buffer_boundaries() -> Result<(), Box<dyn std::error::Error>> {
+    let mut xml = String::from("<list>");
+    for i in 0..2000 {
+        xml.push_str(&format!(
+            "<item id=\"{i}\"><!-- {i} --><![CDATA[é]]>&amp;{i}</item>\n"
+        ));
+    }
+    xml.push_str("</list>");
+
+    let mut reader = EventReader::from(xml.as_bytes());
+    let mut items = 0;
+
+    loop {
+        match reader.next_event()? {
+            XmlEvent::STag(tag) if tag.name.is("item") => items += 1,
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+    assert_eq!(items, 2000);
+
+    Ok(())
+}
+
+#[test]
+fn borrow_tokens_from_slices() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from("<a b='c'>d<!--e--></a>");
+
+    let XmlEvent::STag(tag) = reader.next_event()? else {
+        panic!("expected a start tag");
+    };
+    assert!(matches!(tag.name.into_value(), Cow::Borrowed("a")));
+    assert!(matches!(
+        tag.attrs[0].value.clone().into_value(),
+        Cow::Borrowed("c")
+    ));
+
+    let XmlEvent::Text(text) = reader.next_event()? else {
+        panic!("expected text");
+    };
+    assert!(matches!(text.into_value(), Cow::Borrowed("d")));
+
+    let XmlEvent::Comment(comment) = reader.next_event()? else {
+        panic!("expected a comment");
+    };
+    assert!(matches!(comment.into_value(), Cow::Borrowed("e")));
+
+    // Normalized line endings no longer match the input.
+    let mut reader = EventReader::from("<a>x\r\ny</a>".as_bytes());
+    reader.next_event()?;
+    let XmlEvent::Text(text) = reader.next_event()? else {
+        panic!("expected text");
+    };
+    assert!(matches!(text.into_value(), Cow::Owned(text) if text == "x\ny"));
+
+    Ok(())
+}
+
+#[test]
+fn reject_malformed_sequences() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = b"<a>caf\xC3</a>";
+    let mut reader = EventReader::from(xml.as_slice());
+
+    reader.next_event()?;
+    assert_eq!(reader.next_event(), Err(Error::Malformed { offset: 6 }));
+
+    let mut reader = EventReader::new(Parser::from_reader(xml.as_slice()).lenient());
+
+    reader.next_event()?;
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("caf\u{FFFD}"))
+    );
+
+    let truncated = b"<a/><!-- \xE2\x82";
+    let mut reader = EventReader::from(truncated.as_slice());
+    reader.next_event()?;
+    assert_eq!(reader.next_event(), Err(Error::Malformed { offset: 9 }));
+
+    Ok(())
+}
+
+#[test]
+fn normalize_line_endings() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = "<a b=\"1\r\n2\">x\ry\r\nz<!--\r\r\n--></a>";
+    let mut reader = EventReader::from(xml.as_bytes());
+
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::STag(StartTag {
+            name: Name::new("a"),
+            attrs: vec![Attribute {
+                name: Name::new("b"),
+                value: AttValue::new("1 2"),
+            }],
+        })
+    );
+    assert_eq!(reader.next_event()?, XmlEvent::Text(Text::new("x\ny\nz")));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Comment(Comment::new("\n\n"))
+    );
+
+    let mut reader = EventReader::new(Parser::from_reader(xml.as_bytes()).raw_line_endings());
+
+    reader.next_event()?;
+    assert_eq!(reader.next_event()?, XmlEvent::Text(Text::new("x\ry\r\nz")));
+
+    // The `\r\n` straddles the end of the first buffer fill.
+    let text = format!("{}\r\n", "x".repeat(8188));
+    let xml = format!("<a>{text}</a>");
+    assert_eq!(
+        read_text(EventReader::from(xml.as_bytes()))?,
+        text.replace("\r\n", "\n")
+    );
+
+    Ok(())
+}
+
+const ATT_VALUES_XML: &str = "<!DOCTYPE a [
+    <!ATTLIST a ids IDREFS #IMPLIED note CDATA #IMPLIED>
+    <!ENTITY lf '&#10;'>
+]>
+<a ids=' x\ty\n\n z ' note=' x\ty&#9;z&lf; ' other='&#32; x '/>";
+
+#[test]
+fn normalize_att_values() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(ATT_VALUES_XML.as_bytes());
+
+    let elem = loop {
+        if let XmlEvent::EmptyElem(elem) = reader.next_event()? {
+            break elem;
+        }
+    };
+    assert_eq!(elem.attr("ids"), Some(&AttValue::new("x y z")));
+    assert_eq!(elem.attr("note"), Some(&AttValue::new(" x y\tz  ")));
+    assert_eq!(elem.attr("other"), Some(&AttValue::new("  x ")));
+    assert_eq!(elem.attr("missing"), None);
+
+    Ok(())
+}
+
+#[test]
+fn reject_duplicate_attributes() {
+    assert_eq!(
+        read_to_end("<a x='1' y='2' x='3'/>"),
+        Err(SyntaxError::DuplicateAttribute("x".to_owned()))
+    );
+
+    let mut reader =
+        NsReader::from(r#"<a xmlns:p="urn:x" xmlns:q="urn:x" p:id="1" q:id="2"/>"#.as_bytes());
+    assert_eq!(
+        reader.next_event().map_err(syntax_error),
+        Err(SyntaxError::DuplicateAttribute("q:id".to_owned()))
+    );
+}
+
+fn write_events(events: &[XmlEvent], config: WriterConfig) -> Result<Vec<u8>, Error> {
+    let mut writer = EventWriter::with_config(Vec::new(), config)?;
+
+    for event in events {
+        writer.write_event(event)?;
+    }
+    Ok(writer.into_inner())
+}
+
+#[test]
+fn write_escaped_events() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = EventReader::from(XML.as_bytes());
+    let mut writer = EventWriter::new(Vec::new());
+
+    loop {
+        let event = reader.next_event()?;
+        writer.write_event(&event)?;
+
+        if event == XmlEvent::Eof {
+            break;
+        }
+    }
+    assert_eq!(String::from_utf8(writer.into_inner())?, XML);
+
+    let events = [
+        XmlEvent::STag(StartTag {
+            name: Name::new("a"),
+            attrs: vec![Attribute {
+                name: Name::new("title"),
+                value: AttValue::new("\"Tom\" & <Jerry>\n"),
+            }],
+        }),
+        XmlEvent::Text(Text::new("1 < 2 && ]]> \r")),
+        XmlEvent::CData(CData::new("x]]>y")),
+        XmlEvent::Comment(Comment::new(" note ")),
+        XmlEvent::Pi(Pi {
+            target: Name::new("go"),
+            data: Some(PiData::new("now")),
+        }),
+        XmlEvent::ETag(EndTag {
+            name: Name::new("a"),
+        }),
+        XmlEvent::Eof,
+    ];
+    let written = write_events(&events, WriterConfig::default())?;
+
+    assert_eq!(
+        String::from_utf8(written.clone())?,
+        "<a title=\"&quot;Tom&quot; &amp; &lt;Jerry>&#10;\">1 &lt; 2 &amp;&amp; ]]&gt; &#13;\
+         <![CDATA[x]]]]><![CDATA[>y]]><!-- note --><?go now?></a>"
+    );
+
+    let mut reader = EventReader::from(written.as_slice());
+    let StartTag { attrs, .. } = match reader.next_event()? {
+        XmlEvent::STag(s_tag) => s_tag,
+        event => panic!("expected a start tag, got {event:?}"),
+    };
+    assert_eq!(attrs[0].value, AttValue::new("\"Tom\" & <Jerry>\n"));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::Text(Text::new("1 < 2 && ]]> \r"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn write_empty_elements() -> Result<(), Box<dyn std::error::Error>> {
+    let events = [
+        XmlEvent::STag(StartTag {
+            name: Name::new("a"),
+            attrs: vec![],
+        }),
+        XmlEvent::EmptyElem(EmptyElem {
+            name: Name::new("b"),
+            attrs: vec![],
+        }),
+        XmlEvent::STag(StartTag {
+            name: Name::new("c"),
+            attrs: vec![],
+        }),
+        XmlEvent::ETag(EndTag {
+            name: Name::new("c"),
+        }),
+        XmlEvent::ETag(EndTag {
+            name: Name::new("a"),
+        }),
+    ];
+    let write = |empty_elements| {
+        let config = WriterConfig {
+            empty_elements,
+            ..Default::default()
+        };
+        write_events(&events, config).map(String::from_utf8)
+    };
+
+    assert_eq!(write(EmptyElements::AsWritten)??, "<a><b/><c></c></a>");
+    assert_eq!(write(EmptyElements::SelfClosing)??, "<a><b/><c/></a>");
+    assert_eq!(write(EmptyElements::Expanded)??, "<a><b></b><c></c></a>");
+
+    Ok(())
+}
+
+#[test]
+fn reject_unbalanced_writes() {
+    let s_tag = |name| {
+        XmlEvent::STag(StartTag {
+            name: Name::new(name),
+            attrs: vec![],
+        })
+    };
+    let e_tag = |name| {
+        XmlEvent::ETag(EndTag {
+            name: Name::new(name),
+        })
+    };
+    let write = |events: &[XmlEvent]| {
+        write_events(events, WriterConfig::default())
+            .map(|_| ())
+            .map_err(syntax_error)
+    };
+
+    assert_eq!(
+        write(&[s_tag("a"), s_tag("b"), e_tag("a")]),
+        Err(SyntaxError::MismatchedEndTag {
+            expected: "b".to_owned(),
+            found: "a".to_owned(),
+        })
+    );
+    assert_eq!(
+        write(&[s_tag("a"), XmlEvent::Eof]),
+        Err(SyntaxError::UnclosedElement("a".to_owned()))
+    );
+    assert_eq!(
+        write(&[s_tag("a"), e_tag("a"), s_tag("b")]),
+        Err(SyntaxError::ContentAfterRoot)
+    );
+    assert_eq!(
+        write(&[XmlEvent::Comment(Comment::new("a--b"))]),
+        Err(SyntaxError::UnexpectedDelimiter("`--`"))
+    );
+
+    // A mismatched end tag leaves the element open.
+    let mut writer = EventWriter::new(Vec::new());
+    assert!(writer.write_event(&s_tag("a")).is_ok());
+    assert!(writer.write_event(&e_tag("b")).is_err());
+    assert_eq!(writer.write_event(&e_tag("a")), Ok(()));
+    assert_eq!(writer.write_event(&XmlEvent::Eof), Ok(()));
+    assert_eq!(writer.get_ref(), b"<a></a>");
+
+    let mut formatter = Formatter::new(EventWriter::new(Vec::new()), FormatConfig::default());
+    let events = [
+        s_tag("a"),
+        s_tag("b"),
+        XmlEvent::Text(Text::new("x")),
+        e_tag("b"),
+        XmlEvent::Eof,
+    ];
+    assert_eq!(
+        events
+            .into_iter()
+            .try_for_each(|event| formatter.write_event(event))
+            .map_err(syntax_error),
+        Err(SyntaxError::UnclosedElement("a".to_owned()))
+    );
+}
+
+#[test]
+fn reject_ill_formed_writes() {
+    let elem = |name, attrs: &[(&'static str, &'static str)]| {
+        XmlEvent::EmptyElem(EmptyElem {
+            name: Name::new(name),
+            attrs: attrs
+                .iter()
+                .map(|&(name, value)| Attribute {
+                    name: Name::new(name),
+                    value: AttValue::new(value),
+                })
+                .collect(),
+        })
+    };
+    let in_root = |event| {
+        let events = [
+            XmlEvent::STag(StartTag {
+                name: Name::new("r"),
+                attrs: vec![],
+            }),
+            event,
+        ];
+        write_events(&events, WriterConfig::default()).map(|_| ())
+    };
+    let invalid_name = |name: &str| Err(Error::Write(WriteError::InvalidName(name.to_owned())));
+
+    assert_eq!(in_root(elem("1a", &[])), invalid_name("1a"));
+    assert_eq!(in_root(elem("a b", &[])), invalid_name("a b"));
+    assert_eq!(in_root(elem("", &[])), invalid_name(""));
+    assert_eq!(in_root(elem("a", &[("x=", "1")])), invalid_name("x="));
+    assert_eq!(
+        in_root(XmlEvent::STag(StartTag {
+            name: Name::new("<a>"),
+            attrs: vec![],
+        })),
+        invalid_name("<a>")
+    );
+    assert_eq!(
+        in_root(XmlEvent::Pi(Pi {
+            target: Name::new("a?"),
+            data: None,
+        })),
+        invalid_name("a?")
+    );
+    assert_eq!(
+        in_root(elem("a", &[("x", "1"), ("y", "2"), ("x", "3")])),
+        Err(SyntaxError::DuplicateAttribute("x".to_owned()).into())
+    );
+
+    let invalid_char = Err(Error::Write(WriteError::InvalidChar('\u{1}')));
+    assert_eq!(in_root(XmlEvent::Text(Text::new("a\u{1}b"))), invalid_char);
+    assert_eq!(in_root(elem("a", &[("x", "\u{1}")])), invalid_char);
+    assert_eq!(
+        in_root(XmlEvent::Comment(Comment::new("\u{1}"))),
+        invalid_char
+    );
+    assert_eq!(in_root(XmlEvent::CData(CData::new("\u{1}"))), invalid_char);
+    assert_eq!(
+        in_root(XmlEvent::Pi(Pi {
+            target: Name::new("a"),
+            data: Some(PiData::new("\u{1}")),
+        })),
+        invalid_char
+    );
+    assert_eq!(
+        in_root(XmlEvent::Text(Text::new("\u{FFFE}"))),
+        Err(Error::Write(WriteError::InvalidChar('\u{FFFE}')))
+    );
+    assert_eq!(
+        in_root(XmlEvent::Xml(XmlDecl {
+            version: AttValue::new("1.0"),
+            encoding: None,
+            standalone: None,
+        })),
+        Err(Error::Write(WriteError::MisplacedXmlDecl))
+    );
+    assert_eq!(in_root(elem("a:b-c.d", &[("é", "\t")])), Ok(()));
+
+    let doctype = XmlEvent::DocType(DocTypeDecl {
+        name: Name::new("a"),
+        external_id: Some(ExternalId::System(SystemLiteral::new("'\""))),
+        internal_subset: None,
+        markup_decls: vec![],
+    });
+    assert_eq!(
+        write_events(&[doctype], WriterConfig::default()).map_err(write_error),
+        Err(WriteError::UnquotableLiteral("'\"".to_owned()))
+    );
+
+    // A rejected XML declaration leaves nothing behind.
+    let xml_decl = |version, encoding| {
+        XmlEvent::Xml(XmlDecl {
+            version: AttValue::new(version),
+            encoding: Some(AttValue::new(encoding)),
+            standalone: None,
+        })
+    };
+    let mut writer = EventWriter::new(Vec::new());
+    assert!(writer.write_event(&xml_decl("1.0", "ISO-8859-1")).is_err());
+    assert_eq!(
+        writer.write_event(&xml_decl("'\"", "UTF-8")),
+        Err(Error::Write(WriteError::UnquotableLiteral(
+            "'\"".to_owned()
+        )))
+    );
+    assert!(writer.get_ref().is_empty());
+    assert_eq!(writer.write_event(&xml_decl("1.0", "UTF-8")), Ok(()));
+}
+
+#[test]
+fn write_in_encodings() -> Result<(), Box<dyn std::error::Error>> {
+    let events = |encoding, text| {
+        [
+            XmlEvent::Xml(XmlDecl {
+                version: AttValue::new("1.0"),
+                encoding: Some(AttValue::new(encoding)),
+                standalone: None,
+            }),
+            XmlEvent::STag(StartTag {
+                name: Name::new("a"),
+                attrs: vec![],
+            }),
+            XmlEvent::Text(Text::new(text)),
+            XmlEvent::ETag(EndTag {
+                name: Name::new("a"),
+            }),
+            XmlEvent::Eof,
+        ]
+    };
+    let config = |encoding: &str| WriterConfig {
+        encoding: encoding.to_owned(),
+        ..Default::default()
+    };
+
+    let latin1 = write_events(&events("ISO-8859-1", "é€😀"), config("ISO-8859-1"))?;
+    assert_eq!(
+        latin1,
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>\xE9\x80&#128512;</a>"
+    );
+    assert_eq!(read_text(EventReader::from(latin1.as_slice()))?, "é€😀");
+
+    let utf16 = write_events(&events("UTF-16", "é"), config("UTF-16BE"))?;
+    assert_eq!(utf16[..4], [0xFE, 0xFF, 0x00, b'<']);
+    assert_eq!(read_text(EventReader::from(utf16.as_slice()))?, "é");
+
+    assert_eq!(
+        write_events(&events("UTF-8", "é"), config("ISO-8859-1")).map_err(syntax_error),
+        Err(SyntaxError::EncodingConflict {
+            detected: "windows-1252",
+            declared: "UTF-8".to_owned(),
+        })
+    );
+    assert_eq!(
+        EventWriter::with_config(Vec::new(), config("klingon"))
+            .err()
+            .map(syntax_error),
+        Some(SyntaxError::UnsupportedEncoding("klingon".to_owned()))
+    );
+
+    Ok(())
+}
+
+fn format(xml: &str, config: FormatConfig) -> Result<String, Error> {
+    let mut reader = EventReader::from(xml.as_bytes());
+    let mut formatter = Formatter::new(EventWriter::new(Vec::new()), config);
+
+    loop {
+        let event = reader.next_event()?;
+        let is_eof = event == XmlEvent::Eof;
+
+        formatter.write_event(event)?;
+        if is_eof {
+            break;
+        }
+    }
+    Ok(String::from_utf8(formatter.into_inner().into_inner()).unwrap())
+}
+
+#[test]
+fn format_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = r#"<?xml version="1.0"?><!-- settings --><config>
+<server host="example.com" port="8080"><timeout>30</timeout>
+        <retries/></server>
+<motd>Hello <b>you</b>!</motd><script xml:space="preserve">
+  a
+    b
+</script>
+   </config>"#;
+
+    assert_eq!(
+        format(xml, FormatConfig::default())?,
+        r#"<?xml version="1.0"?>
+<!-- settings -->
+<config>
+  <server host="example.com" port="8080">
+    <timeout>30</timeout>
+    <retries/>
+  </server>
+  <motd>Hello <b>you</b>!</motd>
+  <script xml:space="preserve">
+  a
+    b
+</script>
+</config>
+"#
+    );
+
+    let config = FormatConfig {
+        indent: "\t".to_owned(),
+        line_width: 30,
+    };
+    assert_eq!(
+        format(xml, config)?,
+        "<?xml version=\"1.0\"?>\n<!-- settings -->\n<config>\n\t<server\n\t\thost=\"example.com\"\n\t\tport=\"8080\">\n\t\t<timeout>30</timeout>\n\t\t<retries/>\n\t</server>\n\t<motd>Hello <b>you</b>!</motd>\n\t<script xml:space=\"preserve\">\n  a\n    b\n</script>\n</config>\n"
+    );
+
+    assert_eq!(
+        format(
+            "<r><a> <!-- c --> <b/> </a><p> <!-- c --> t<b/></p><q><b/> t <b/></q></r>",
+            FormatConfig::default()
+        )?,
+        "<r>\n  <a>\n    <!-- c -->\n    <b/>\n  </a>\n  <p> <!-- c --> t<b/></p>\n  <q>\n    <b/> t <b/></q>\n</r>\n"
+    );
+
+    // Each element is written once its content is known to be mixed or
+    // not, long before the root element ends.
+    let mut formatter = Formatter::new(EventWriter::new(Vec::new()), FormatConfig::default());
+    let mut reader = EventReader::from("<list>\n<item>1</item>\n<item> <b/> </item>".as_bytes());
+    while let Ok(event) = reader.next_event() {
+        formatter.write_event(event)?;
+    }
+    assert_eq!(
+        String::from_utf8_lossy(formatter.get_ref()),
+        "<list>\n  <item>1</item>\n  <item>\n    <b/>\n  </item>"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn round_trip_losslessly() -> Result<(), Box<dyn std::error::Error>> {
+    let xml = "<?xml version = '1.0'  encoding=\"UTF-8\" ?>\r\n\
+        <!DOCTYPE  config PUBLIC '-//X//EN'\t\"config.dtd\" [<!ENTITY me \"Jani\">] >\r\n\
+        <?style  href='a.css'?>\
+        <config\r\n    name = 'a &amp; b'\tsize=\"&#49;0\" >\r\n\
+        \t<from >&me; &lt;&#x41;&gt;</from  >\
+        <empty attr='\"' /><![CDATA[<raw>]]><!-- note -->\
+        </config\n>\n";
+
+    let config = ReaderConfig {
+        raw_references: true,
+        ..Default::default()
+    };
+    let mut reader = EventReader::with_config(
+        Parser::from_reader(xml.as_bytes()).raw_line_endings(),
+        config,
+    );
+    let writer_config = WriterConfig {
+        raw_references: true,
+        ..Default::default()
+    };
+    let mut writer = EventWriter::with_config(Vec::new(), writer_config)?;
+    let mut edited = EventWriter::new(Vec::new());
+
+    loop {
+        let mut event = reader.next_event()?;
+        writer.write_spelled(&event, reader.layout())?;
+
+        if let XmlEvent::STag(StartTag { name, attrs }) = &mut event {
+            if name.is("config") {
+                assert_eq!(
+                    reader.layout(),
+                    &Layout {
+                        spaces: ["\r\n    ", " ", " ", "\t", "", "", " "]
+                            .map(str::to_owned)
+                            .to_vec(),
+                        quotes: vec![Quote::Single, Quote::Double],
+                        bom: false,
+                    }
+                );
+                attrs[0].value = AttValue::new("c");
+                attrs.pop();
+            }
+        }
+        if matches!(event, XmlEvent::Text(_) | XmlEvent::DocType(_)) {
+            continue;
+        }
+        edited.write_spelled(&event, reader.layout())?;
+
+        if event == XmlEvent::Eof {
+            break;
+        }
+    }
+    assert_eq!(String::from_utf8(writer.into_inner())?, xml);
+    assert_eq!(
+        String::from_utf8(edited.into_inner())?,
+        "<?xml version = '1.0'  encoding=\"UTF-8\" ?>\r\n\r\n\
+        <?style  href='a.css'?>\
+        <config\r\n    name = 'c' >\
+        <from ></from  >\
+        <empty attr='&quot;' /><![CDATA[<raw>]]><!-- note -->\
+        </config\n>\n"
+    );
+
+    let utf16: Vec<u8> = "\u{FEFF}<a>é</a>"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    let docs: [(&[u8], &str); 3] = [
+        (
+            "\u{FEFF}<a  x = 'a&amp;b'\r\n>t&#65;</a >".as_bytes(),
+            "UTF-8",
+        ),
+        ("\u{FEFF}<!-- a --><a/>".as_bytes(), "UTF-8"),
+        (&utf16, "UTF-16BE"),
+    ];
+
+    for (xml, encoding) in docs {
+        let config = ReaderConfig {
+            raw_references: true,
+            ..Default::default()
+        };
+        let mut reader =
+            EventReader::with_config(Parser::from_reader(xml).raw_line_endings(), config);
+        let writer_config = WriterConfig {
+            raw_references: true,
+            encoding: encoding.to_owned(),
+            ..Default::default()
+        };
+        let mut writer = EventWriter::with_config(Vec::new(), writer_config)?;
+
+        loop {
+            let event = reader.next_event()?;
+            writer.write_spelled(&event, reader.layout())?;
+
+            if event == XmlEvent::Eof {
+                break;
+            }
+        }
+        assert_eq!(writer.into_inner(), xml);
+    }
+
+    let xml = "<a b='1\r\n2'>x\r\ny\r<!--\r\n--></a>\r\n";
+    let mut reader = PushReader::with_config(ReaderConfig {
+        raw_references: true,
+        ..Default::default()
+    })
+    .raw_line_endings();
+    let writer_config = WriterConfig {
+        raw_references: true,
+        ..Default::default()
+    };
+    let mut writer = EventWriter::with_config(Vec::new(), writer_config)?;
+
+    for byte in xml.as_bytes() {
+        reader.feed(&[*byte])?;
+        while let Some(event) = reader.next_event()? {
+            writer.write_spelled(&event, reader.layout())?;
+        }
+    }
+    reader.finish()?;
+    while let Some(event) = reader.next_event()? {
+        writer.write_spelled(&event, reader.layout())?;
+
+        if event == XmlEvent::Eof {
+            break;
+        }
+    }
+    assert_eq!(String::from_utf8(writer.into_inner())?, xml);
+
+    let mut reader = EventReader::from("\u{FEFF}<a/>".as_bytes());
+    reader.next_event()?;
+    assert!(reader.layout().bom);
+    reader.next_event()?;
+    assert!(!reader.layout().bom);
+
+    Ok(())
+}
+
+#[test]
+fn build_and_edit_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::try_from(XML.as_bytes())?;
+    let note = doc.root_element().unwrap();
+
+    assert_eq!(doc.element(note).unwrap().name, Name::new("note"));
+    assert_eq!(doc.parent(note), Some(doc.root()));
+    assert_eq!(doc.xml_decl.as_ref().unwrap().version, AttValue::new("1.0"));
+
+    let to = doc.child_elements(note, "to").next().unwrap();
+    let from = doc.child_elements(note, "from").next().unwrap();
+    assert_eq!(doc.text(to), "Tove");
+    assert_eq!(doc.parent(to), Some(note));
+    assert_eq!(
+        doc.next_sibling(to).map(|id| doc.node(id)),
+        Some(&Node::Text(Text::new("\n    ")))
+    );
+    assert_eq!(
+        doc.previous_sibling(doc.previous_sibling(from).unwrap()),
+        Some(to)
+    );
+    assert_eq!(doc.ancestors(to).collect::<Vec<_>>(), [note, doc.root()]);
+    assert_eq!(
+        doc.text(note).split_whitespace().collect::<Vec<_>>(),
+        ["Tove", "Jani", "Reminder", "Don't", "forget", "me", "this", "weekend!"]
+    );
+
+    let elem = doc.element_mut(note).unwrap();
+    elem.set_attr("lang", "en");
+    elem.set_attr("lang", "fr & en");
+    assert_eq!(elem.attr("lang"), Some("fr & en"));
+
+    doc.set_text(from, "Tove");
+    doc.set_text(to, "Jani");
+    doc.insert_before(to, from);
+
+    let mut cc = Element::new("cc");
+    cc.set_attr("hidden", "yes");
+    let cc = doc.append(note, Node::Element(cc));
+    doc.append(cc, Node::Comment(Comment::new(" nobody ")));
+
+    for id in doc.descendants(note).collect::<Vec<_>>() {
+        if doc.element(id).is_some_and(|elem| elem.name.is("heading")) {
+            doc.detach(id);
+        }
+    }
+    assert_eq!(
+        doc.element_mut(cc).unwrap().remove_attr("hidden"),
+        Some(AttValue::new("yes"))
+    );
+
+    let mut writer = EventWriter::new(Vec::new());
+    doc.write_to(&mut writer)?;
+
+    assert_eq!(
+        String::from_utf8(writer.into_inner())?,
+        r#"<?xml version="1.0" encoding="UTF-8"?><note lang="fr &amp; en">
+    <from>Tove</from><to>Jani</to>
+    
+    
+    <body>Don't forget me this weekend!</body>
+<cc><!-- nobody --></cc></note>"#
+    );
+
+    let mut reader = EventReader::from(XML.as_bytes());
+    while !matches!(reader.next_event()?, XmlEvent::STag(_)) {}
+    assert_eq!(
+        Document::from_events(&mut reader)
+            .map(drop)
+            .map_err(syntax_error),
+        Err(SyntaxError::UnexpectedEndTag("note".to_owned()))
+    );
+
+    Ok(())
+}
+
+const CATALOG: &str = r#"<?xml version="1.0"?>
+<CATALOG xmlns:m="urn:money">
+  <CD xml:id="cd1" xml:lang="en-GB">
+    <TITLE>Empire Burlesque</TITLE>
+    <PRICE m:currency="USD">10.90</PRICE>
+  </CD>
+  <!-- out of stock -->
+  <CD xml:id="cd2">
+    <TITLE>Hide your heart</TITLE>
+    <PRICE m:currency="GBP">9.90</PRICE>
+  </CD>
+  <CD xml:id="cd3">
+    <TITLE>Greatest Hits</TITLE>
+    <PRICE>12.50</PRICE>
+  </CD>
+</CATALOG>"#;
+
+fn strings(doc: &Document, nodes: &[NodeRef]) -> Vec<String> {
+    nodes.iter().map(|node| node.string_value(doc)).collect()
+}
+
+#[test]
+fn evaluate_xpath() -> Result<(), Box<dyn std::error::Error>> {
+    let doc = Document::try_from(CATALOG.as_bytes())?;
+    let root = doc.root();
+    let select = |expr: &str| XPath::new(expr)?.select(&doc, root);
+    let eval = |expr: &str| XPath::new(expr)?.evaluate(&doc, root, &Context::default());
+
+    assert_eq!(
+        strings(&doc, &select("//CD[PRICE > 10]/TITLE")?),
+        ["Empire Burlesque", "Greatest Hits"]
+    );
+    assert_eq!(
+        strings(
+            &doc,
+            &select("/CATALOG/CD[last()]/preceding-sibling::CD/TITLE")?
+        ),
+        ["Empire Burlesque", "Hide your heart"]
+    );
+    assert_eq!(
+        strings(
+            &doc,
+            &select("//PRICE[. < 10]/ancestor::*[1]/TITLE | //CD[1]/TITLE")?
+        ),
+        ["Empire Burlesque", "Hide your heart"]
+    );
+    assert_eq!(
+        strings(&doc, &select("//TITLE[starts-with(., 'G')]/following::*")?),
+        ["12.50"]
+    );
+    assert_eq!(
+        strings(&doc, &select("id('cd3 cd2')/TITLE")?),
+        ["Hide your heart", "Greatest Hits"]
+    );
+    assert_eq!(select("//comment()")?.len(), 1);
+    assert_eq!(select("//CD[lang('en')]")?, select("//CD[1]")?);
+    assert_eq!(select("//*[@*[starts-with(name(), 'xmlns')]]")?, []);
+
+    assert_eq!(eval("count(//CD)")?, Value::Number(3.0));
+    assert_eq!(eval("round(sum(//PRICE) * 10)")?, Value::Number(333.0));
+    assert_eq!(
+        eval("round(-2.5) + floor(2.7) * ceiling(0.1)")?,
+        Value::Number(0.0)
+    );
+    assert_eq!(
+        eval("string(1 div 0)")?,
+        Value::String("Infinity".to_owned())
+    );
+    assert_eq!(eval("number('1e3')")?.string(&doc), "NaN");
+    assert_eq!(eval("7 mod 3 = 1 and not(//CD[4])")?, Value::Boolean(true));
+    assert_eq!(eval("//TITLE = 'Hide your heart'")?, Value::Boolean(true));
+    assert_eq!(
+        eval("normalize-space(translate(substring-after(' a-b-c ', '-'), '-c', '_'))")?,
+        Value::String("b_".to_owned())
+    );
+    assert_eq!(eval("substring('12345', 1.5, 2.6)")?.string(&doc), "234");
+    assert_eq!(
+        eval("count(//CD[1]/namespace::*) + count(//CD[position() = 2]/@*)")?,
+        Value::Number(3.0)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn bind_xpath_namespaces_and_variables() -> Result<(), Box<dyn std::error::Error>> {
+    let doc = Document::try_from(CATALOG.as_bytes())?;
+    let mut context = Context::default();
+    context
+        .namespaces
+        .insert("money".to_owned(), "urn:money".to_owned());
+    context
+        .variables
+        .insert("currency".to_owned(), Value::String("GBP".to_owned()));
+
+    let xpath = XPath::new("//PRICE[@money:currency = $currency]/../TITLE")?;
+    let Value::NodeSet(nodes) = xpath.evaluate(&doc, doc.root(), &context)? else {
+        panic!("expected a node-set");
+    };
+    assert_eq!(strings(&doc, &nodes), ["Hide your heart"]);
+
+    let xpath = XPath::new("concat(name(//@money:*), ' in ', namespace-uri(//PRICE/@*))")?;
+    assert_eq!(
+        xpath.evaluate(&doc, doc.root(), &context)?,
+        Value::String("m:currency in urn:money".to_owned())
+    );
+
+    let first_cd = doc.child_elements(doc.root_element().unwrap(), "CD").next();
+    let xpath = XPath::new("string(@xml:id)")?;
+    assert_eq!(
+        xpath.evaluate(&doc, first_cd.unwrap(), &context)?,
+        Value::String("cd1".to_owned())
+    );
+
+    assert_eq!(
+        syntax_error(XPath::new("//@m:*")?.select(&doc, doc.root()).unwrap_err()),
+        SyntaxError::UnboundPrefix("m".to_owned())
+    );
+    assert_eq!(
+        syntax_error(XPath::new("$price")?.select(&doc, doc.root()).unwrap_err()),
+        SyntaxError::UndefinedVariable("price".to_owned())
+    );
+    assert_eq!(
+        syntax_error(
+            XPath::new("count(//CD)")?
+                .select(&doc, doc.root())
+                .unwrap_err()
+        ),
+        SyntaxError::NotANodeSet
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_xpath() {
+    let error = |expr| match XPath::new(expr) {
+        Err(Error::Syntax(err, position)) => (err, position.column),
+        _ => panic!("expected a syntax error for {expr}"),
+    };
+
+    assert_eq!(
+        error("//CD[PRICE > 10"),
+        (SyntaxError::MismatchedToken("`]`"), 16)
+    );
+    assert_eq!(
+        error("//CD)"),
+        (SyntaxError::UnexpectedToken(")".to_owned()), 5)
+    );
+    assert_eq!(
+        error("sideways::CD"),
+        (SyntaxError::UnknownAxis("sideways".to_owned()), 1)
+    );
+    assert_eq!(
+        error("/CATALOG/upper-case(CD)"),
+        (SyntaxError::UnexpectedToken("upper-case".to_owned()), 10)
+    );
+    assert_eq!(
+        error("upper-case('cd')"),
+        (SyntaxError::UnknownFunction("upper-case".to_owned()), 1)
+    );
+    assert_eq!(
+        error("count(//CD, //PRICE)"),
+        (SyntaxError::WrongArgumentCount("count".to_owned()), 1)
+    );
+    assert_eq!(error("'Empire"), (SyntaxError::UnclosedDelimiter("`'`"), 1));
+}
+
+fn path_reader(patterns: &[&str]) -> Result<PathReader<'static, SliceParser<'static>>, Error> {
+    let patterns = patterns
+        .iter()
+        .map(|pattern| PathPattern::new(pattern))
+        .collect::<Result<_, _>>()?;
+
+    Ok(PathReader::new(
+        EventReader::from(CATALOG.as_bytes()),
+        patterns,
+    ))
+}
+
+#[test]
+fn match_paths_while_streaming() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = path_reader(&["/CATALOG/CD/TITLE", "//PRICE"])?;
+    let mut matches = Vec::new();
+
+    while let Some(text) = reader.next_text()? {
+        matches.push((reader.matched().unwrap(), text));
+    }
+    assert_eq!(
+        matches,
+        [
+            (0, "Empire Burlesque".to_owned()),
+            (1, "10.90".to_owned()),
+            (0, "Hide your heart".to_owned()),
+            (1, "9.90".to_owned()),
+            (0, "Greatest Hits".to_owned()),
+            (1, "12.50".to_owned()),
+        ]
+    );
+
+    let mut reader = path_reader(&["CD[@xml:id = 'cd2']", "CD/*[@m:currency]"])?;
+    assert!(matches!(reader.next_event()?, XmlEvent::STag(tag) if tag.name.is("PRICE")));
+    assert_eq!(reader.matched(), Some(1));
+    assert_eq!(
+        reader.path(),
+        [Name::new("CATALOG"), Name::new("CD"), Name::new("PRICE")]
+    );
+    assert_eq!(reader.next_event()?, XmlEvent::Text(Text::new("10.90")));
+    assert_eq!(
+        reader.next_event()?,
+        XmlEvent::ETag(EndTag {
+            name: Name::new("PRICE")
+        })
+    );
+
+    // The PRICE inside the second CD is part of its match.
+    assert!(matches!(reader.next_event()?, XmlEvent::STag(tag) if tag.name.is("CD")));
+    assert_eq!(reader.matched(), Some(0));
+    let mut names = Vec::new();
+    loop {
+        match reader.next_event()? {
+            XmlEvent::STag(tag) => names.push(tag.name.value().to_owned()),
+            XmlEvent::ETag(tag) if tag.name.is("CD") => break,
+            _ => assert_eq!(reader.matched(), Some(0)),
+        }
+    }
+    assert_eq!(names, ["TITLE", "PRICE"]);
+    assert_eq!(reader.next_text()?, None);
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    // Matching takes a pass per step, however many ways `//` could split
+    // the open elements.
+    let xml = format!("{}<b/>{}", "<a>".repeat(60), "</a>".repeat(60));
+    let pattern = PathPattern::new(&format!("{}//c", "//a".repeat(20)))?;
+    let mut reader = PathReader::new(EventReader::from(xml.as_bytes()), vec![pattern]);
+    assert_eq!(reader.next_event()?, XmlEvent::Eof);
+
+    let pattern = PathPattern::new(&format!("/a{}/b", "//a".repeat(20)))?;
+    let mut reader = PathReader::new(EventReader::from(xml.as_bytes()), vec![pattern]);
+    assert!(matches!(reader.next_event()?, XmlEvent::EmptyElem(_)));
+    assert_eq!(reader.path().len(), 60);
+
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_path_patterns() {
+    let error = |pattern| match PathPattern::new(pattern) {
+        Err(Error::Syntax(err, position)) => (err, position.column),
+        _ => panic!("expected a syntax error for {pattern}"),
+    };
+
+    assert_eq!(
+        error("/CATALOG//"),
+        (SyntaxError::MismatchedToken("Name"), 11)
+    );
+    assert_eq!(
+        error("CD[@id='1'"),
+        (SyntaxError::MismatchedToken("`]`"), 11)
+    );
+    assert_eq!(error("CD[@id=1]"), (SyntaxError::MismatchedToken("`'`"), 8));
+    assert_eq!(
+        error("CD|TITLE"),
+        (SyntaxError::UnexpectedToken("|".to_owned()), 3)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+struct Catalog {
+    #[serde(rename = "CD")]
+    cds: Vec<Cd>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+struct Cd {
+    #[serde(rename = "@xml:id")]
+    id: String,
+    #[serde(rename = "@xml:lang")]
+    lang: Option<String>,
+    #[serde(rename = "TITLE")]
+    title: String,
+    #[serde(rename = "PRICE")]
+    price: Price,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+struct Price {
+    #[serde(rename = "@m:currency")]
+    currency: Option<Currency>,
+    #[serde(rename = "$text")]
+    amount: f64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+enum Currency {
+    #[serde(rename = "USD")]
+    Dollar,
+    #[serde(rename = "GBP")]
+    Pound,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Shape {
+    Circle {
+        #[serde(rename = "@r")]
+        radius: u32,
+    },
+    Square(u32),
+    Point,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+struct Drawing {
+    #[serde(rename = "@visible")]
+    visible: bool,
+    #[serde(rename = "@sizes")]
+    sizes: Vec<u8>,
+    title: Option<String>,
+    shape: Vec<Shape>,
+    tags: Tags,
+}
+
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+struct Tags {
+    #[serde(default)]
+    tag: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let catalog: Catalog = xenna::from_reader(CATALOG.as_bytes())?;
+
+    assert_eq!(catalog.cds.len(), 3);
+    assert_eq!(
+        catalog.cds[0],
+        Cd {
+            id: "cd1".to_owned(),
+            lang: Some("en-GB".to_owned()),
+            title: "Empire Burlesque".to_owned(),
+            price: Price {
+                currency: Some(Currency::Dollar),
+                amount: 10.9,
+            },
+        }
+    );
+    assert_eq!(catalog.cds[1].price.currency, Some(Currency::Pound));
+    assert_eq!(catalog.cds[2].price.currency, None);
+
+    let drawing: Drawing = xenna::from_str(
+        r#"<drawing visible="1" sizes="1 2  3">
+  <!-- shapes -->
+  <shape><circle r="2"/></shape>
+  <shape><square>3</square></shape>
+  <shape><![CDATA[point]]></shape>
+  <tags/>
+</drawing>"#,
+    )?;
+    assert_eq!(
+        drawing,
+        Drawing {
+            visible: true,
+            sizes: vec![1, 2, 3],
+            title: None,
+            shape: vec![Shape::Circle { radius: 2 }, Shape::Square(3), Shape::Point],
+            tags: Tags { tag: Vec::new() },
+        }
+    );
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Opt {
+        v: Option<u32>,
+        w: Option<String>,
+    }
+    let opt = |xml| xenna::from_str::<Opt>(xml);
+    let none = Opt { v: None, w: None };
+
+    assert_eq!(opt("<Opt><v/><w/></Opt>")?, none);
+    assert_eq!(opt("<Opt><v></v><w></w></Opt>")?, none);
+    assert_eq!(opt("<Opt/>")?, none);
+    assert_eq!(xenna::from_str::<Option<u32>>("<v></v>")?, None);
+    assert_eq!(xenna::from_str::<Option<u32>>("<v>1</v>")?, Some(1));
+    assert_eq!(
+        opt("<Opt><v>3</v><w> </w></Opt>")?,
+        Opt {
+            v: Some(3),
+            w: Some(" ".to_owned()),
+        }
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn report_deserialization_errors() {
+    let error = |xml: &str| match xenna::from_str::<Catalog>(xml) {
+        Err(Error::Syntax(err, position)) => (err.to_string(), position.line, position.column),
+        result => panic!("expected a syntax error, got {result:?}"),
+    };
+
+    assert_eq!(
+        error("<CATALOG>\n  <CD xml:id='cd1'>\n    <TITLE>A</TITLE>\n    <PRICE>cheap</PRICE>\n  </CD>\n</CATALOG>"),
+        (
+            "invalid value: string \"cheap\", expected f64".to_owned(),
+            4,
+            12
+        )
+    );
+    assert_eq!(
+        error("<CATALOG>\n  <CD xml:id='cd1'><TITLE>A</TITLE></CD>\n</CATALOG>"),
+        ("missing field `PRICE`".to_owned(), 2, 36)
+    );
+    assert_eq!(
+        error("<CATALOG><CD><TITLE>A</TITLE></CATALOG>"),
+        ("expected `</CD>`, found `</CATALOG>`".to_owned(), 1, 40)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serialize_documents() -> Result<(), Box<dyn std::error::Error>> {
+    let catalog = Catalog {
+        cds: vec![
+            Cd {
+                id: "cd1".to_owned(),
+                lang: None,
+                title: "Tom & Jerry <live>".to_owned(),
+                price: Price {
+                    currency: Some(Currency::Pound),
+                    amount: 9.9,
+                },
+            },
+            Cd {
+                id: "say \"cheese\"".to_owned(),
+                lang: Some("en".to_owned()),
+                title: String::new(),
+                price: Price {
+                    currency: None,
+                    amount: 12.5,
+                },
+            },
+        ],
+    };
+    let mut serializer = Serializer::with_root(EventWriter::new(Vec::new()), "CATALOG");
+    serializer.serialize(&catalog)?;
+    let xml = String::from_utf8(serializer.into_inner().into_inner())?;
+
+    assert_eq!(
+        xml,
+        concat!(
+            r#"<CATALOG><CD xml:id="cd1"><TITLE>Tom &amp; Jerry &lt;live&gt;</TITLE>"#,
+            r#"<PRICE m:currency="GBP">9.9</PRICE></CD>"#,
+            r#"<CD xml:id="say &quot;cheese&quot;" xml:lang="en"><TITLE></TITLE>"#,
+            r#"<PRICE>12.5</PRICE></CD></CATALOG>"#
+        )
+    );
+    assert_eq!(xenna::from_str::<Catalog>(&xml)?, catalog);
+
+    let drawing = Drawing {
+        visible: true,
+        sizes: vec![1, 2, 3],
+        title: None,
+        shape: vec![Shape::Circle { radius: 2 }, Shape::Square(3), Shape::Point],
+        tags: Tags { tag: Vec::new() },
+    };
+    let xml = xenna::to_string(&drawing)?;
+
+    assert_eq!(
+        xml,
+        concat!(
+            r#"<Drawing visible="true" sizes="1 2 3"><shape><circle r="2"/></shape>"#,
+            r#"<shape><square>3</square></shape><shape>point</shape><tags></tags></Drawing>"#
+        )
+    );
+    assert_eq!(xenna::from_str::<Drawing>(&xml)?, drawing);
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn report_serialization_errors() {
+    let error = |result: Result<(), Error>| match result {
+        Err(Error::Syntax(SyntaxError::Custom(message), _)) => message,
+        result => panic!("expected a custom error, got {result:?}"),
+    };
+    let with_root = |fields: &[(&str, &str)]| {
+        let mut serializer = Serializer::with_root(EventWriter::new(Vec::new()), "elem");
+        serializer.serialize(&std::collections::BTreeMap::from_iter(
+            fields.iter().copied(),
+        ))
+    };
+
+    assert_eq!(
+        error(xenna::to_string("text").map(drop)),
+        "a value without a type name needs a root name"
+    );
+    assert_eq!(
+        error(xenna::to_writer(Vec::new(), &vec![1])),
+        "a sequence can't be the root element"
+    );
+    assert_eq!(
+        error(with_root(&[("$text", "x"), ("@id", "1")])),
+        "attribute `id` follows the content of `<elem>`"
+    );
+    assert_eq!(
+        error(with_root(&[("not a name", "1")])),
+        "`not a name` isn't a valid XML name"
+    );
+
+    #[derive(Serialize)]
+    struct Wrap {
+        #[serde(rename = "@a")]
+        a: String,
+        v: String,
+    }
+    let wrap = |a: &str, v: &str| Wrap {
+        a: a.to_owned(),
+        v: v.to_owned(),
+    };
+    for value in [wrap("", "a\u{1}b"), wrap("\u{1}", "")] {
+        assert_eq!(
+            xenna::to_string(&value).map_err(write_error),
+            Err(WriteError::InvalidChar('\u{1}'))
+        );
+    }
+}
+
+/// Reads `xml` with an `EventReader` until the end or the first error,
+/// returning every event or error with where it started.
+fn read_blocking(xml: &[u8]) -> Vec<(Result<XmlEvent<'_>, Error>, Position)> {
+    let mut reader = EventReader::from(xml);
+    let mut events = Vec::new();
+
+    loop {
+        let event = reader.next_event();
+        let is_last = matches!(event, Ok(XmlEvent::Eof) | Err(_));
+        events.push((event, reader.position()));
+
+        if is_last {
+            return events;
+        }
+    }
+}
+
+/// Reads `xml` with an `AsyncEventReader` through a duplex stream passing
+/// on at most `max_buf_size` bytes at a time.
+#[cfg(feature = "tokio")]
+async fn read_async(
+    xml: &[u8],
+    max_buf_size: usize,
+) -> Vec<(Result<XmlEvent<'static>, Error>, Position)> {
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    let (mut client, server) = tokio::io::duplex(max_buf_size);
+    let write = async move {
+        // The reader stops reading at the first error.
+        if client.write_all(xml).await.is_ok() {
+            client.shutdown().await.unwrap();
+        }
+    };
+    let read = async {
+        let mut reader = AsyncEventReader::new(BufReader::new(server));
+        let mut events = Vec::new();
+
+        loop {
+            let event = reader.next_event().await;
+            let is_last = matches!(event, Ok(XmlEvent::Eof) | Err(_));
+            events.push((event, reader.position()));
+
+            if is_last {
+                return events;
+            }
+        }
+    };
+
+    tokio::join!(write, read).1
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn read_events_asynchronously() {
+    let utf16: Vec<u8> = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><a>é</a>"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let docs: [&[u8]; 7] = [
+        XML.as_bytes(),
+        DOCTYPE_XML.as_bytes(),
+        ENTITIES_XML.as_bytes(),
+        "<p lang='日本'>日本語 🦀<!-- a -- b? -->\r\n<![CDATA[<]]>]]&gt;</p>".as_bytes(),
+        b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a b='\xE9'>\xE9</a>",
+        &utf16,
+        b"<a>caf\xC3</a>",
+    ];
+
+    for xml in docs {
+        let expected = read_blocking(xml);
+
+        for max_buf_size in [1, 2, 3, 7, 64] {
+            assert_eq!(read_async(xml, max_buf_size).await, expected);
+        }
+    }
+
+    let mut xml = String::from("<list>");
+    for i in 0..2000 {
+        xml.push_str(&format!("<item id=\"{i}\"><!-- {i} -->é&amp;{i}</item>\n"));
+    }
+    xml.push_str("</list>");
+    assert_eq!(
+        read_async(xml.as_bytes(), 1000).await,
+        read_blocking(xml.as_bytes())
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn read_large_nodes_asynchronously() {
+    let text = "lorem ipsum-dolor ".repeat(256 * 1024);
+    let xml = format!("<a>{text}<!--{text}--><![CDATA[{text}]]></a>");
+    let events: Vec<_> = read_async(xml.as_bytes(), 8 * 1024)
+        .await
+        .into_iter()
+        .map(|(event, _)| event.unwrap())
+        .collect();
+
+    assert_eq!(events[1], XmlEvent::Text(Text::new(&text)));
+    assert_eq!(events[2], XmlEvent::Comment(Comment::new(&text)));
+    assert_eq!(events[3], XmlEvent::CData(CData::new(&text)));
+    assert_eq!(events.len(), 6);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn report_errors_asynchronously() {
+    for xml in [
+        "<a><b></a>",
+        "<a>",
+        "<a x='1' x='2'/>",
+        "<a/><b/>",
+        "<a>&nope;</a>",
+    ] {
+        let events = read_async(xml.as_bytes(), 2).await;
+
+        assert!(matches!(events.last(), Some((Err(Error::Syntax(..)), _))));
+        assert_eq!(events, read_blocking(xml.as_bytes()));
+    }
+}
+
+/// Feeds `chunks` to a `PushReader` as they come, returning every event or
+/// error with where it started.
+fn read_pushed<'c>(
+    chunks: impl IntoIterator<Item = &'c [u8]>,
+) -> Vec<(Result<XmlEvent<'static>, Error>, Position)> {
+    let mut reader = PushReader::new();
+    let mut events = Vec::new();
+
+    let mut read = |reader: &mut PushReader| loop {
+        let event = match reader.next_event() {
+            Ok(Some(event)) => Ok(event),
+            Ok(None) => return false,
+            Err(err) => Err(err),
+        };
+        let is_last = matches!(event, Ok(XmlEvent::Eof) | Err(_));
+        events.push((event, reader.position()));
+
+        if is_last {
+            return true;
+        }
+    };
+
+    for chunk in chunks {
+        reader.feed(chunk).unwrap();
+
+        if read(&mut reader) {
+            return events;
+        }
+    }
+    reader.finish().unwrap();
+    read(&mut reader);
+
+    events
+}
+
+#[test]
+fn push_chunks_to_parser() -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = PushReader::new();
+
+    reader.feed("<p title='café".as_bytes())?;
+    assert_eq!(reader.next_event()?, None);
+    reader.feed(b" \xC3")?;
+    assert_eq!(reader.next_event()?, None);
+    reader.feed(b"\xA9'>caf\xC3")?;
+    assert_eq!(
+        reader.next_event()?,
+        Some(XmlEvent::STag(StartTag {
+            name: Name::new("p"),
+            attrs: vec![Attribute {
+                name: Name::new("title"),
+                value: AttValue::new("café é"),
+            }],
+        }))
+    );
+    assert_eq!(reader.next_event()?, None);
+    reader.feed(b"\xA9<!-- a -")?;
+    assert_eq!(
+        reader.next_event()?,
+        Some(XmlEvent::Text(Text::new("café")))
+    );
+    assert_eq!(reader.next_event()?, None);
+    reader.feed(b"->")?;
+    assert_eq!(
+        reader.next_event()?,
+        Some(XmlEvent::Comment(Comment::new(" a ")))
+    );
+    reader.feed(b"</p>")?;
+    assert_eq!(
+        reader.next_event()?,
+        Some(XmlEvent::ETag(EndTag {
+            name: Name::new("p")
+        }))
+    );
+    assert_eq!(reader.position().column, 33);
+    assert_eq!(reader.next_event()?, None);
+    reader.finish()?;
+    assert_eq!(reader.next_event()?, Some(XmlEvent::Eof));
+
+    let xml = "<?xml version='1.0'?><!DOCTYPE a [<!ENTITY e 'é'>]>\
+        <a b='&e;'>日本<!-- - --><?pi -->?><![CDATA[]]]]></a>";
+    let expected = read_blocking(xml.as_bytes());
+
+    for split in 0..xml.len() {
+        let (head, tail) = xml.as_bytes().split_at(split);
+        assert_eq!(read_pushed([head, tail]), expected);
+    }
+    assert_eq!(read_pushed(xml.as_bytes().chunks(1)), expected);
+
+    let invalid: [&[u8]; 4] = [b"<a><b></a>", b"<a>", b"<a>caf\xC3</a>", b"<a/>x"];
+    for xml in invalid {
+        assert_eq!(read_pushed(xml.chunks(1)), read_blocking(xml));
+    }
+
+    Ok(())
+}